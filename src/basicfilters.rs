@@ -2,7 +2,6 @@ use std::sync::{Arc, RwLock};
 
 use crate::biquad::{Biquad, BiquadCoefficients};
 use crate::config;
-use crate::fifoqueue::FifoQueue;
 use crate::filters::Filter;
 
 use crate::NewValue;
@@ -14,13 +13,43 @@ use crate::Res;
 pub struct Gain {
     pub name: String,
     pub gain: PrcFmt,
+    samplerate: usize,
+    target_gain: PrcFmt,
+    ramp_start: PrcFmt,
+    ramp_samples: usize,
+    ramp_position: usize,
 }
 
+// Half the number of taps of the windowed sinc fractional delay
+const SINC_HALF_LENGTH: usize = 16;
+
+// Largest number of earlier delays that a crossfade starts from
+const MAX_PREVIOUS_TAPS: usize = 8;
+
+// Earlier delays with a smaller weight than this are left out of a crossfade
+const MIN_TAP_WEIGHT: PrcFmt = 0.001;
+
 pub struct Delay {
     pub name: String,
     samplerate: usize,
-    pub queue: FifoQueue<PrcFmt>,
-    biquad: Option<Biquad>,
+    history: Vec<PrcFmt>,
+    index: usize,
+    tap: DelayTap,
+    previous_taps: Vec<(DelayTap, PrcFmt)>,
+    ramp_samples: usize,
+    ramp_position: usize,
+    scratch: Vec<PrcFmt>,
+    previous_output: Vec<PrcFmt>,
+}
+
+/// Reads the delayed signal from the history of a Delay filter.
+/// The fractional part of the delay is given either by a first order allpass filter,
+/// or by a windowed sinc interpolator.
+struct DelayTap {
+    delay: PrcFmt,
+    samples: usize,
+    allpass: Option<Biquad>,
+    sinc: Vec<PrcFmt>,
 }
 
 pub struct Volume {
@@ -35,6 +64,7 @@ pub struct Volume {
     samplerate: usize,
     chunksize: usize,
     processing_status: Arc<RwLock<ProcessingParameters>>,
+    fader: Option<String>,
 }
 
 impl Volume {
@@ -63,6 +93,7 @@ impl Volume {
             samplerate,
             chunksize,
             processing_status,
+            fader: None,
         }
     }
 
@@ -73,17 +104,24 @@ impl Volume {
         samplerate: usize,
         processing_status: Arc<RwLock<ProcessingParameters>>,
     ) -> Self {
-        let current_volume = processing_status.read().unwrap().volume;
-        let mute = processing_status.read().unwrap().mute;
-        Volume::new(
+        if let Some(fader) = &conf.fader {
+            processing_status.write().unwrap().add_fader(fader);
+        }
+        let fader = processing_status
+            .read()
+            .unwrap()
+            .fader(conf.fader.as_deref());
+        let mut volume = Volume::new(
             name,
             conf.ramp_time,
-            current_volume,
-            mute,
+            fader.volume,
+            fader.mute,
             chunksize,
             samplerate,
             processing_status,
-        )
+        );
+        volume.fader = conf.fader;
+        volume
     }
 
     fn make_ramp(&self) -> Vec<PrcFmt> {
@@ -115,8 +153,13 @@ impl Filter for Volume {
     }
 
     fn process_waveform(&mut self, waveform: &mut [PrcFmt]) -> Res<()> {
-        let shared_vol = self.processing_status.read().unwrap().volume;
-        let shared_mute = self.processing_status.read().unwrap().mute;
+        let fader = self
+            .processing_status
+            .read()
+            .unwrap()
+            .fader(self.fader.as_deref());
+        let shared_vol = fader.volume;
+        let shared_mute = fader.mute;
 
         // Volume setting changed
         if (shared_vol - self.target_volume).abs() > 0.01 || self.mute != shared_mute {
@@ -181,6 +224,10 @@ impl Filter for Volume {
             self.ramptime_in_chunks = (conf.ramp_time
                 / (1000.0 * self.chunksize as f32 / self.samplerate as f32))
                 .round() as usize;
+            if let Some(fader) = &conf.fader {
+                self.processing_status.write().unwrap().add_fader(fader);
+            }
+            self.fader = conf.fader;
         } else {
             // This should never happen unless there is a bug somewhere else
             panic!("Invalid config change!");
@@ -188,25 +235,56 @@ impl Filter for Volume {
     }
 }
 
+/// Get the linear gain for a gain in dB, with inversion and muting.
+fn linear_gain(gain_db: PrcFmt, inverted: bool, mute: bool) -> PrcFmt {
+    let mut gain: PrcFmt = 10.0;
+    gain = gain.powf(gain_db / 20.0);
+    if inverted {
+        gain = -gain;
+    }
+    if mute {
+        gain = 0.0;
+    }
+    gain
+}
+
+/// Weight of the new delay at a position in a raised cosine crossfade.
+fn delay_ramp_weight(position: usize, ramp_samples: usize) -> PrcFmt {
+    if position >= ramp_samples {
+        1.0
+    } else {
+        let pi = std::f64::consts::PI as PrcFmt;
+        0.5 - 0.5 * (pi * position as PrcFmt / ramp_samples as PrcFmt).cos()
+    }
+}
+
+/// Convert a ramp time in ms to a number of samples.
+fn ramp_time_in_samples(ramp_time: PrcFmt, samplerate: usize) -> usize {
+    (ramp_time / 1000.0 * samplerate as PrcFmt).round() as usize
+}
+
 impl Gain {
     /// A simple filter providing gain in dB, and can also invert the signal.
     pub fn new(name: String, gain_db: PrcFmt, inverted: bool, mute: bool) -> Self {
-        let mut gain: PrcFmt = 10.0;
-        gain = gain.powf(gain_db / 20.0);
-        if inverted {
-            gain = -gain;
-        }
-        if mute {
-            gain = 0.0;
+        let gain = linear_gain(gain_db, inverted, mute);
+        Gain {
+            name,
+            gain,
+            samplerate: 0,
+            target_gain: gain,
+            ramp_start: gain,
+            ramp_samples: 0,
+            ramp_position: 0,
         }
-        Gain { name, gain }
     }
 
-    pub fn from_config(name: String, conf: config::GainParameters) -> Self {
+    pub fn from_config(name: String, samplerate: usize, conf: config::GainParameters) -> Self {
         let gain = conf.gain;
         let inverted = conf.inverted;
         let mute = conf.mute;
-        Gain::new(name, gain, inverted, mute)
+        let mut filter = Gain::new(name, gain, inverted, mute);
+        filter.samplerate = samplerate;
+        filter
     }
 }
 
@@ -217,6 +295,12 @@ impl Filter for Gain {
 
     fn process_waveform(&mut self, waveform: &mut [PrcFmt]) -> Res<()> {
         for item in waveform.iter_mut() {
+            if self.ramp_position < self.ramp_samples {
+                self.ramp_position += 1;
+                self.gain = self.ramp_start
+                    + (self.target_gain - self.ramp_start) * self.ramp_position as PrcFmt
+                        / self.ramp_samples as PrcFmt;
+            }
             *item *= self.gain;
         }
         Ok(())
@@ -224,17 +308,15 @@ impl Filter for Gain {
 
     fn update_parameters(&mut self, conf: config::Filter) {
         if let config::Filter::Gain { parameters: conf } = conf {
-            let gain_db = conf.gain;
-            let inverted = conf.inverted;
-            let mut gain: PrcFmt = 10.0;
-            gain = gain.powf(gain_db / 20.0);
-            if inverted {
-                gain = -gain;
-            }
-            if conf.mute {
-                gain = 0.0;
+            let gain = linear_gain(conf.gain, conf.inverted, conf.mute);
+            // Ramp linearly from the current gain, also when changing polarity or muting
+            self.ramp_samples = ramp_time_in_samples(conf.ramp_time, self.samplerate);
+            self.ramp_position = 0;
+            self.ramp_start = self.gain;
+            self.target_gain = gain;
+            if self.ramp_samples == 0 {
+                self.gain = gain;
             }
-            self.gain = gain;
         } else {
             // This should never happen unless there is a bug somewhere else
             panic!("Invalid config change!");
@@ -242,45 +324,208 @@ impl Filter for Gain {
     }
 }
 
-impl Delay {
-    /// Creates a delay filter with delay in samples
-    /// Will be improved as it gets slow for long delays
-    pub fn new(name: String, samplerate: usize, delay: PrcFmt, subsample: bool) -> Self {
-        let (integerdelay, biquad) = if subsample {
-            let samples = delay.floor();
-            let fraction = delay - samples;
-            let bqcoeffs = BiquadCoefficients::new(1.0 - fraction, 0.0, 1.0 - fraction, 1.0, 0.0);
-            let bq = Biquad::new("subsample".to_string(), 12345, bqcoeffs);
-            debug!(
-                "Building delay filter '{}' with delay {} + {} samples",
-                name, samples, fraction
-            );
-            (samples as usize, Some(bq))
-        } else {
+impl DelayTap {
+    fn new(
+        name: &str,
+        delay: PrcFmt,
+        subsample: bool,
+        interpolation: &config::DelayInterpolation,
+    ) -> Self {
+        let samples = delay.floor();
+        let fraction = delay - samples;
+        if !subsample {
             let samples = delay.round() as usize;
             debug!(
                 "Building delay filter '{}' with delay {} samples",
                 name, samples
             );
-            (samples, None)
-        };
-        let mut queue = FifoQueue::filled_with(integerdelay + 1, 0.0);
-        let _elem = queue.pop();
+            return DelayTap {
+                delay,
+                samples,
+                allpass: None,
+                sinc: Vec::new(),
+            };
+        }
+        if *interpolation == config::DelayInterpolation::Sinc {
+            let half_length = samples as usize + 1;
+            if half_length >= SINC_HALF_LENGTH {
+                debug!(
+                    "Building delay filter '{}' with delay {} + {} samples, using sinc interpolation",
+                    name, samples, fraction
+                );
+                return DelayTap {
+                    delay,
+                    samples: samples as usize,
+                    allpass: None,
+                    sinc: DelayTap::sinc_coefficients(fraction),
+                };
+            }
+            // The interpolator can't look at future samples,
+            // so short delays get a shorter interpolator instead
+            debug!(
+                "Building delay filter '{}' with delay {} + {} samples, using Lagrange interpolation with {} taps",
+                name, samples, fraction, 2 * half_length
+            );
+            return DelayTap {
+                delay,
+                samples: samples as usize,
+                allpass: None,
+                sinc: DelayTap::lagrange_coefficients(fraction, half_length),
+            };
+        }
+        let bqcoeffs = BiquadCoefficients::new(1.0 - fraction, 0.0, 1.0 - fraction, 1.0, 0.0);
+        let bq = Biquad::new("subsample".to_string(), 12345, bqcoeffs);
+        debug!(
+            "Building delay filter '{}' with delay {} + {} samples",
+            name, samples, fraction
+        );
+        DelayTap {
+            delay,
+            samples: samples as usize,
+            allpass: Some(bq),
+            sinc: Vec::new(),
+        }
+    }
+
+    /// Get the coefficients of a Blackman windowed sinc interpolator for a fractional delay.
+    /// The coefficients are ordered by increasing delay,
+    /// starting at SINC_HALF_LENGTH - 1 samples before the integer part of the delay.
+    fn sinc_coefficients(fraction: PrcFmt) -> Vec<PrcFmt> {
+        let pi = std::f64::consts::PI as PrcFmt;
+        let half_length = SINC_HALF_LENGTH as PrcFmt;
+        let mut coeffs: Vec<PrcFmt> = (0..2 * SINC_HALF_LENGTH)
+            .map(|k| {
+                let t = k as PrcFmt - (half_length - 1.0) - fraction;
+                let sinc = if t == 0.0 {
+                    1.0
+                } else {
+                    (pi * t).sin() / (pi * t)
+                };
+                let window = 0.42
+                    + 0.5 * (pi * t / half_length).cos()
+                    + 0.08 * (2.0 * pi * t / half_length).cos();
+                sinc * window
+            })
+            .collect();
+        let sum: PrcFmt = coeffs.iter().sum();
+        for coeff in coeffs.iter_mut() {
+            *coeff /= sum;
+        }
+        coeffs
+    }
+
+    /// Get the coefficients of a Lagrange interpolator with 2 * half_length taps,
+    /// ordered in the same way as for the sinc interpolator.
+    /// This is used for delays that are too short for the full length sinc.
+    fn lagrange_coefficients(fraction: PrcFmt, half_length: usize) -> Vec<PrcFmt> {
+        let position = (half_length - 1) as PrcFmt + fraction;
+        (0..2 * half_length)
+            .map(|k| {
+                (0..2 * half_length)
+                    .filter(|m| *m != k)
+                    .map(|m| (position - m as PrcFmt) / (k as PrcFmt - m as PrcFmt))
+                    .product()
+            })
+            .collect()
+    }
+
+    /// The largest delay in samples that is read from the history.
+    fn max_delay(&self) -> usize {
+        self.samples + self.sinc.len() / 2
+    }
+
+    /// Check if two taps give the same output.
+    fn same_as(&self, other: &DelayTap) -> bool {
+        self.delay == other.delay
+            && self.samples == other.samples
+            && self.sinc.len() == other.sinc.len()
+            && self.allpass.is_some() == other.allpass.is_some()
+    }
+
+    /// Read the delayed signal for a block of samples, that was written to the history starting at `start`.
+    fn process(&mut self, history: &[PrcFmt], start: usize, output: &mut [PrcFmt]) -> Res<()> {
+        let len = history.len();
+        if self.sinc.is_empty() {
+            for (n, item) in output.iter_mut().enumerate() {
+                *item = history[(start + n + len - self.samples) % len];
+            }
+            if let Some(bq) = &mut self.allpass {
+                bq.process_waveform(output)?;
+            }
+        } else {
+            let first_delay = self.samples + 1 - self.sinc.len() / 2;
+            for (n, item) in output.iter_mut().enumerate() {
+                let newest = start + n + len - first_delay;
+                *item = self
+                    .sinc
+                    .iter()
+                    .enumerate()
+                    .map(|(k, coeff)| coeff * history[(newest - k) % len])
+                    .sum();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Convert the delay of a Delay config to samples.
+fn delay_in_samples(samplerate: usize, conf: &config::DelayParameters) -> PrcFmt {
+    match conf.unit {
+        config::TimeUnit::Milliseconds => conf.delay / 1000.0 * (samplerate as PrcFmt),
+        config::TimeUnit::Millimetres => conf.delay / 1000.0 * (samplerate as PrcFmt) / 343.0,
+        config::TimeUnit::Samples => conf.delay,
+    }
+}
+
+impl Delay {
+    /// Creates a delay filter with delay in samples
+    pub fn new(name: String, samplerate: usize, delay: PrcFmt, subsample: bool) -> Self {
+        let tap = DelayTap::new(
+            &name,
+            delay,
+            subsample,
+            &config::DelayInterpolation::Allpass,
+        );
         Delay {
             name,
             samplerate,
-            queue,
-            biquad,
+            history: vec![0.0; tap.max_delay() + 1],
+            index: 0,
+            tap,
+            previous_taps: Vec::new(),
+            ramp_samples: 0,
+            ramp_position: 0,
+            scratch: Vec::new(),
+            previous_output: Vec::new(),
         }
     }
 
     pub fn from_config(name: String, samplerate: usize, conf: config::DelayParameters) -> Self {
-        let delay_samples = match conf.unit {
-            config::TimeUnit::Milliseconds => conf.delay / 1000.0 * (samplerate as PrcFmt),
-            config::TimeUnit::Millimetres => conf.delay / 1000.0 * (samplerate as PrcFmt) / 343.0,
-            config::TimeUnit::Samples => conf.delay,
-        };
-        Delay::new(name, samplerate, delay_samples, conf.subsample)
+        let delay_samples = delay_in_samples(samplerate, &conf);
+        let mut delay = Delay::new(name, samplerate, 0.0, false);
+        delay.tap = DelayTap::new(
+            &delay.name,
+            delay_samples,
+            conf.subsample,
+            &conf.interpolation,
+        );
+        delay.ramp_samples = ramp_time_in_samples(conf.ramp_time, samplerate);
+        delay
+    }
+
+    /// Make sure the history is long enough for the delays, plus a block of new samples.
+    /// The stored samples are kept, with zeros added before the oldest one.
+    fn ensure_capacity(&mut self, needed: usize) {
+        let len = self.history.len();
+        if needed <= len {
+            return;
+        }
+        let mut history = vec![0.0; needed];
+        for n in 0..len {
+            history[needed - len + n] = self.history[(self.index + n) % len];
+        }
+        self.history = history;
+        self.index = 0;
     }
 }
 
@@ -290,48 +535,88 @@ impl Filter for Delay {
     }
 
     fn process_waveform(&mut self, waveform: &mut [PrcFmt]) -> Res<()> {
-        for item in waveform.iter_mut() {
-            self.queue.push(*item)?;
-            *item = self.queue.pop().unwrap();
+        let max_delay = self
+            .previous_taps
+            .iter()
+            .map(|(tap, _weight)| tap.max_delay())
+            .fold(self.tap.max_delay(), usize::max);
+        self.ensure_capacity(max_delay + waveform.len());
+        let len = self.history.len();
+        let start = self.index;
+        for (n, item) in waveform.iter().enumerate() {
+            self.history[(start + n) % len] = *item;
         }
-        if let Some(bq) = &mut self.biquad {
-            bq.process_waveform(waveform)?;
+        self.index = (start + waveform.len()) % len;
+        self.tap.process(&self.history, start, waveform)?;
+        if !self.previous_taps.is_empty() {
+            // Crossfade from the mix of the previous delays to the new one
+            self.previous_output.clear();
+            self.previous_output.resize(waveform.len(), 0.0);
+            self.scratch.resize(waveform.len(), 0.0);
+            for (tap, tap_weight) in self.previous_taps.iter_mut() {
+                tap.process(&self.history, start, &mut self.scratch)?;
+                for (mixed, value) in self.previous_output.iter_mut().zip(self.scratch.iter()) {
+                    *mixed += *tap_weight * value;
+                }
+            }
+            for (item, old) in waveform.iter_mut().zip(self.previous_output.iter()) {
+                let weight = delay_ramp_weight(self.ramp_position, self.ramp_samples);
+                *item = weight * *item + (1.0 - weight) * old;
+                self.ramp_position += 1;
+            }
+            if self.ramp_position >= self.ramp_samples {
+                self.previous_taps.clear();
+            }
         }
         Ok(())
     }
 
     fn update_parameters(&mut self, conf: config::Filter) {
         if let config::Filter::Delay { parameters: conf } = conf {
-            let delay_samples = match conf.unit {
-                config::TimeUnit::Milliseconds => conf.delay / 1000.0 * (self.samplerate as PrcFmt),
-                config::TimeUnit::Millimetres => {
-                    conf.delay / 1000.0 * (self.samplerate as PrcFmt) / 343.0
-                }
-                config::TimeUnit::Samples => conf.delay,
-            };
-            let (integerdelay, biquad) = if conf.subsample {
-                let full_samples = delay_samples.floor();
-                let fraction = delay_samples - full_samples;
-                let bqcoeffs =
-                    BiquadCoefficients::new(1.0 - fraction, 0.0, 1.0 - fraction, 1.0, 0.0);
-                let bq = Biquad::new("subsample".to_string(), 12345, bqcoeffs);
-                debug!(
-                    "Updating delay filter '{}' with delay {} + {} samples",
-                    self.name, full_samples, fraction
-                );
-                (full_samples as usize, Some(bq))
+            let delay_samples = delay_in_samples(self.samplerate, &conf);
+            debug!("Updating delay filter '{}'", self.name);
+            let tap = DelayTap::new(
+                &self.name,
+                delay_samples,
+                conf.subsample,
+                &conf.interpolation,
+            );
+            let ramp_samples = ramp_time_in_samples(conf.ramp_time, self.samplerate);
+            // Weight of the current delay in the output, less than one while a ramp is running
+            let weight = if self.previous_taps.is_empty() {
+                1.0
             } else {
-                let full_samples = delay_samples.round() as usize;
-                debug!(
-                    "Updating delay filter '{}' with delay {} samples",
-                    self.name, full_samples
-                );
-                (full_samples, None)
+                delay_ramp_weight(self.ramp_position, self.ramp_samples)
             };
-            let mut queue = FifoQueue::filled_with(integerdelay + 1, 0.0);
-            let _elem = queue.pop();
-            self.queue = queue;
-            self.biquad = biquad;
+            let old_tap = std::mem::replace(&mut self.tap, tap);
+            if ramp_samples > 0 && (!old_tap.same_as(&self.tap) || !self.previous_taps.is_empty()) {
+                // Start the new ramp from the current state, also if a previous ramp is still running
+                let mut previous: Vec<(DelayTap, PrcFmt)> = Vec::new();
+                let earlier = self
+                    .previous_taps
+                    .drain(..)
+                    .map(|(tap, tap_weight)| (tap, (1.0 - weight) * tap_weight));
+                for (tap, tap_weight) in std::iter::once((old_tap, weight)).chain(earlier) {
+                    match previous.iter_mut().find(|(other, _)| other.same_as(&tap)) {
+                        Some((_, other_weight)) => *other_weight += tap_weight,
+                        None => previous.push((tap, tap_weight)),
+                    }
+                }
+                // Keep the taps with the largest weights, to limit the processing time.
+                // Dropping a tap gives a small step, but that only happens for very frequent updates.
+                previous.retain(|(_, tap_weight)| *tap_weight >= MIN_TAP_WEIGHT);
+                previous.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+                previous.truncate(MAX_PREVIOUS_TAPS);
+                let total: PrcFmt = previous.iter().map(|(_, tap_weight)| tap_weight).sum();
+                for (_, tap_weight) in previous.iter_mut() {
+                    *tap_weight /= total;
+                }
+                self.previous_taps = previous;
+                self.ramp_position = 0;
+            } else {
+                self.previous_taps.clear();
+            }
+            self.ramp_samples = ramp_samples;
         } else {
             // This should never happen unless there is a bug somewhere else
             panic!("Invalid config change!");
@@ -339,11 +624,14 @@ impl Filter for Delay {
     }
 }
 
-/// Validate a Loudness config.
+/// Validate a Delay config.
 pub fn validate_delay_config(conf: &config::DelayParameters) -> Res<()> {
     if conf.delay < 0.0 {
         return Err(config::ConfigError::new("Delay cannot be negative").into());
     }
+    if conf.ramp_time < 0.0 {
+        return Err(config::ConfigError::new("Ramp time cannot be negative").into());
+    }
     Ok(())
 }
 
@@ -362,13 +650,21 @@ pub fn validate_gain_config(conf: &config::GainParameters) -> Res<()> {
     } else if conf.gain > 150.0 {
         return Err(config::ConfigError::new("Gain must be less than +150 dB").into());
     }
+    if conf.ramp_time < 0.0 {
+        return Err(config::ConfigError::new("Ramp time cannot be negative").into());
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::basicfilters::{Delay, Gain};
+    use crate::basicfilters;
+    use crate::basicfilters::{Delay, Gain, Volume};
+    use crate::config;
     use crate::filters::Filter;
+    use crate::ProcessingParameters;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
 
     fn is_close(left: f64, right: f64, maxdiff: f64) -> bool {
         println!("{} - {}", left, right);
@@ -442,4 +738,215 @@ mod tests {
         delay.process_waveform(&mut waveform).unwrap();
         assert!(compare_waveforms(waveform, waveform_delayed, 1.0e-6));
     }
+
+    fn make_delay_conf(
+        delay: f64,
+        interpolation: config::DelayInterpolation,
+        ramp_time: f64,
+    ) -> config::DelayParameters {
+        config::DelayParameters {
+            delay,
+            unit: config::TimeUnit::Samples,
+            subsample: true,
+            interpolation,
+            ramp_time,
+        }
+    }
+
+    fn sine(freq: f64, fs: usize, start: usize, length: usize) -> Vec<f64> {
+        let omega = 2.0 * std::f64::consts::PI * freq / fs as f64;
+        (start..start + length)
+            .map(|n| (omega * n as f64).sin())
+            .collect()
+    }
+
+    #[test]
+    fn delay_sinc() {
+        // A 10 kHz sine should be delayed by the exact fractional delay
+        let fs = 48000;
+        let conf = make_delay_conf(20.3, config::DelayInterpolation::Sinc, 0.0);
+        let mut delay = Delay::from_config("test".to_string(), fs, conf);
+        let mut waveform = sine(10000.0, fs, 0, 1000);
+        delay.process_waveform(&mut waveform).unwrap();
+        let omega = 2.0 * std::f64::consts::PI * 10000.0 / fs as f64;
+        let expected: Vec<f64> = (500..1000)
+            .map(|n| (omega * (n as f64 - 20.3)).sin())
+            .collect();
+        assert!(compare_waveforms(
+            waveform[500..].to_vec(),
+            expected,
+            1.0e-3
+        ));
+    }
+
+    #[test]
+    fn delay_sinc_integer() {
+        let mut waveform = vec![0.0, -0.5, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let conf = make_delay_conf(16.0, config::DelayInterpolation::Sinc, 0.0);
+        let mut delay = Delay::from_config("test".to_string(), 44100, conf);
+        let mut waveform2 = vec![0.0; 16];
+        delay.process_waveform(&mut waveform).unwrap();
+        delay.process_waveform(&mut waveform2).unwrap();
+        let mut expected = vec![0.0; 16];
+        expected[9] = -0.5;
+        expected[10] = 1.0;
+        assert!(compare_waveforms(waveform2, expected, 1.0e-12));
+    }
+
+    #[test]
+    fn delay_ramped_update() {
+        // Changing the delay crossfades, without any large jump between samples
+        let fs = 48000;
+        let conf = make_delay_conf(10.0, config::DelayInterpolation::Allpass, 5.0);
+        let mut delay = Delay::from_config("test".to_string(), fs, conf);
+        let mut output = Vec::new();
+        for chunk in 0..8 {
+            if chunk == 2 {
+                let newconf = make_delay_conf(200.0, config::DelayInterpolation::Allpass, 5.0);
+                delay.update_parameters(config::Filter::Delay {
+                    parameters: newconf,
+                });
+            }
+            let mut waveform = sine(100.0, fs, 1024 * chunk, 1024);
+            delay.process_waveform(&mut waveform).unwrap();
+            output.extend(waveform);
+        }
+        let max_step = output
+            .windows(2)
+            .skip(100)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f64::max);
+        let omega = 2.0 * std::f64::consts::PI * 100.0 / fs as f64;
+        assert!(max_step < 1.1 * omega);
+        // After the ramp, the new delay is used
+        let expected: Vec<f64> = (7168..8192)
+            .map(|n| (omega * (n as f64 - 200.0)).sin())
+            .collect();
+        assert!(compare_waveforms(output[7168..].to_vec(), expected, 1.0e-9));
+    }
+
+    #[test]
+    fn delay_sinc_short() {
+        // Short delays use a shorter interpolator, that is still accurate at lower frequencies
+        let fs = 48000;
+        for delay_samples in [0.3, 2.5, 7.7] {
+            let conf = make_delay_conf(delay_samples, config::DelayInterpolation::Sinc, 0.0);
+            let mut delay = Delay::from_config("test".to_string(), fs, conf);
+            let mut waveform = sine(1000.0, fs, 0, 1000);
+            delay.process_waveform(&mut waveform).unwrap();
+            let omega = 2.0 * std::f64::consts::PI * 1000.0 / fs as f64;
+            let expected: Vec<f64> = (500..1000)
+                .map(|n| (omega * (n as f64 - delay_samples)).sin())
+                .collect();
+            assert!(compare_waveforms(
+                waveform[500..].to_vec(),
+                expected,
+                1.0e-2
+            ));
+        }
+    }
+
+    #[test]
+    fn delay_repeated_updates() {
+        // Updates during a running ramp continue smoothly from the current mix
+        let fs = 48000;
+        let conf = make_delay_conf(10.0, config::DelayInterpolation::Allpass, 20.0);
+        let mut delay = Delay::from_config("test".to_string(), fs, conf);
+        let mut output = Vec::new();
+        for chunk in 0..40 {
+            if (4..10).contains(&chunk) {
+                let newconf = make_delay_conf(
+                    10.0 + 40.0 * chunk as f64,
+                    config::DelayInterpolation::Allpass,
+                    20.0,
+                );
+                delay.update_parameters(config::Filter::Delay {
+                    parameters: newconf,
+                });
+            }
+            let mut waveform = sine(100.0, fs, 256 * chunk, 256);
+            delay.process_waveform(&mut waveform).unwrap();
+            output.extend(waveform);
+            assert!(delay.previous_taps.len() <= basicfilters::MAX_PREVIOUS_TAPS);
+        }
+        let max_step = output
+            .windows(2)
+            .skip(100)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f64::max);
+        let omega = 2.0 * std::f64::consts::PI * 100.0 / fs as f64;
+        assert!(max_step < 1.1 * omega);
+        // After the ramp, the last delay is used
+        let expected: Vec<f64> = (9216..10240)
+            .map(|n| (omega * (n as f64 - 370.0)).sin())
+            .collect();
+        assert!(compare_waveforms(output[9216..].to_vec(), expected, 1.0e-9));
+    }
+
+    #[test]
+    fn check_delay() {
+        let okconf = make_delay_conf(20.0, config::DelayInterpolation::Sinc, 10.0);
+        assert!(basicfilters::validate_delay_config(&okconf).is_ok());
+        let shortconf = make_delay_conf(0.4, config::DelayInterpolation::Sinc, 10.0);
+        assert!(basicfilters::validate_delay_config(&shortconf).is_ok());
+        let negconf = make_delay_conf(-1.0, config::DelayInterpolation::Allpass, 10.0);
+        assert!(basicfilters::validate_delay_config(&negconf).is_err());
+    }
+
+    #[test]
+    fn gain_ramped_update() {
+        // Inverting the polarity ramps linearly through zero
+        let conf = config::GainParameters {
+            gain: 0.0,
+            inverted: false,
+            mute: false,
+            ramp_time: 0.1,
+        };
+        let mut gain = Gain::from_config("test".to_string(), 40000, conf.clone());
+        let mut newconf = conf;
+        newconf.inverted = true;
+        gain.update_parameters(config::Filter::Gain {
+            parameters: newconf,
+        });
+        let mut waveform = vec![1.0; 6];
+        gain.process_waveform(&mut waveform).unwrap();
+        assert!(compare_waveforms(
+            waveform,
+            vec![0.5, 0.0, -0.5, -1.0, -1.0, -1.0],
+            1.0e-9
+        ));
+    }
+
+    #[test]
+    fn volume_fader() {
+        let status = Arc::new(RwLock::new(ProcessingParameters {
+            volume: -20.0,
+            mute: false,
+            faders: HashMap::new(),
+        }));
+        let conf = config::VolumeParameters {
+            ramp_time: 0.0,
+            fader: Some("sub".to_string()),
+        };
+        let mut volume = Volume::from_config("test".to_string(), conf, 4, 44100, status.clone());
+        // The fader is created when the filter is built, and doesn't follow the main volume
+        assert!(status.read().unwrap().faders.contains_key("sub"));
+        let mut waveform = vec![1.0; 4];
+        volume.process_waveform(&mut waveform).unwrap();
+        assert!(compare_waveforms(waveform, vec![1.0; 4], 1.0e-6));
+        status
+            .write()
+            .unwrap()
+            .faders
+            .get_mut("sub")
+            .unwrap()
+            .volume = -20.0;
+        let mut waveform = vec![1.0; 4];
+        volume.process_waveform(&mut waveform).unwrap();
+        assert!(compare_waveforms(waveform, vec![0.1; 4], 1.0e-6));
+        status.write().unwrap().faders.get_mut("sub").unwrap().mute = true;
+        let mut waveform = vec![1.0; 4];
+        volume.process_waveform(&mut waveform).unwrap();
+        assert!(compare_waveforms(waveform, vec![0.0; 4], 1.0e-6));
+    }
 }
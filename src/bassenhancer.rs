@@ -0,0 +1,311 @@
+// Psychoacoustic bass enhancement.
+// The bass below the crossover frequency is replaced by its harmonics,
+// that give the impression of the missing fundamentals on speakers that can't reproduce them.
+
+use crate::biquad;
+use crate::config;
+use crate::filters::Filter;
+
+// Sample format
+use crate::PrcFmt;
+use crate::Res;
+
+// Release time constant of the envelope follower in seconds
+const RELEASE_TIME: PrcFmt = 0.05;
+
+// Largest number of generated harmonics
+const MAX_HARMONICS: usize = 8;
+
+pub struct BassEnhancer {
+    pub name: String,
+    samplerate: usize,
+    freq: PrcFmt,
+    lowpass: Vec<biquad::Biquad>,
+    highpass: Vec<biquad::Biquad>,
+    harmonics_highpass: Vec<biquad::Biquad>,
+    weights: Vec<PrcFmt>,
+    envelope: PrcFmt,
+    release: PrcFmt,
+    hold_samples: usize,
+    hold_counter: usize,
+    buffer: Vec<PrcFmt>,
+}
+
+/// Get the coefficients of the 2nd order Butterworth sections of a Linkwitz-Riley lowpass or highpass.
+fn lr4_coefficients(samplerate: usize, freq: PrcFmt, highpass: bool) -> biquad::BiquadCoefficients {
+    let q = std::f64::consts::FRAC_1_SQRT_2 as PrcFmt;
+    let filtconf = if highpass {
+        config::BiquadParameters::Highpass { freq, q }
+    } else {
+        config::BiquadParameters::Lowpass { freq, q }
+    };
+    biquad::BiquadCoefficients::from_config(samplerate, filtconf)
+}
+
+/// Make a 4th order Linkwitz-Riley lowpass or highpass, as two 2nd order Butterworth sections.
+fn make_lr4(samplerate: usize, freq: PrcFmt, highpass: bool) -> Vec<biquad::Biquad> {
+    let coeffs = lr4_coefficients(samplerate, freq, highpass);
+    (0..2)
+        .map(|_| biquad::Biquad::new("".to_string(), samplerate, coeffs))
+        .collect()
+}
+
+impl BassEnhancer {
+    pub fn from_config(
+        name: String,
+        samplerate: usize,
+        parameters: config::BassEnhancerParameters,
+    ) -> Self {
+        let release = (-1.0 / (RELEASE_TIME * samplerate as PrcFmt)).exp();
+        let mut enhancer = BassEnhancer {
+            name,
+            samplerate,
+            freq: parameters.freq,
+            lowpass: make_lr4(samplerate, parameters.freq, false),
+            highpass: make_lr4(samplerate, parameters.freq, true),
+            harmonics_highpass: make_lr4(samplerate, parameters.freq, true),
+            weights: Vec::new(),
+            envelope: 0.0,
+            release,
+            hold_samples: 0,
+            hold_counter: 0,
+            buffer: Vec::new(),
+        };
+        enhancer.set_parameters(parameters);
+        enhancer
+    }
+
+    fn set_parameters(&mut self, parameters: config::BassEnhancerParameters) {
+        let fs = self.samplerate;
+        if parameters.freq != self.freq {
+            // Only replace the coefficients, to keep the states of the running filters
+            let lowpass = lr4_coefficients(fs, parameters.freq, false);
+            let highpass = lr4_coefficients(fs, parameters.freq, true);
+            for filter in self.lowpass.iter_mut() {
+                filter.set_coefficients(lowpass);
+            }
+            for filter in self
+                .highpass
+                .iter_mut()
+                .chain(self.harmonics_highpass.iter_mut())
+            {
+                filter.set_coefficients(highpass);
+            }
+            self.freq = parameters.freq;
+        }
+        self.weights = BassEnhancer::harmonic_weights(parameters.harmonics, parameters.gain);
+        // Hold the envelope peaks for one period of a tone two octaves below the crossover
+        self.hold_samples = (4.0 * fs as PrcFmt / parameters.freq).round() as usize;
+    }
+
+    /// Get the weights of the Chebyshev polynomials of order 2 and up.
+    /// The weights fall off as 1/(k-1) for the k-th harmonic.
+    fn harmonic_weights(harmonics: usize, gain: PrcFmt) -> Vec<PrcFmt> {
+        let base: PrcFmt = 10.0;
+        let gain = base.powf(gain / 20.0);
+        (0..harmonics).map(|n| gain / (n + 1) as PrcFmt).collect()
+    }
+
+    /// Generate harmonics from a single sample of the bass signal.
+    /// The sample is normalized by the envelope, so that the Chebyshev polynomial
+    /// of order k turns a sine into its k-th harmonic with the same amplitude.
+    fn process_single(&mut self, input: PrcFmt) -> PrcFmt {
+        let level = input.abs();
+        if level >= self.envelope {
+            self.envelope = level;
+            self.hold_counter = self.hold_samples;
+        } else if self.hold_counter > 0 {
+            self.hold_counter -= 1;
+        } else {
+            self.envelope *= self.release;
+        }
+        if self.envelope < 1.0e-9 {
+            return 0.0;
+        }
+        let x = (input / self.envelope).clamp(-1.0, 1.0);
+        let mut t_prev = 1.0;
+        let mut t_curr = x;
+        let mut output = 0.0;
+        for weight in self.weights.iter() {
+            let t_next = 2.0 * x * t_curr - t_prev;
+            output += weight * t_next;
+            t_prev = t_curr;
+            t_curr = t_next;
+        }
+        output * self.envelope
+    }
+}
+
+impl Filter for BassEnhancer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn process_waveform(&mut self, waveform: &mut [PrcFmt]) -> Res<()> {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(waveform);
+        let mut bass = std::mem::take(&mut self.buffer);
+        for filter in self.lowpass.iter_mut() {
+            filter.process_waveform(&mut bass)?;
+        }
+        for value in bass.iter_mut() {
+            *value = self.process_single(*value);
+        }
+        // Remove any generated content below the crossover, including DC
+        for filter in self.harmonics_highpass.iter_mut() {
+            filter.process_waveform(&mut bass)?;
+        }
+        for filter in self.highpass.iter_mut() {
+            filter.process_waveform(waveform)?;
+        }
+        for (value, harmonic) in waveform.iter_mut().zip(bass.iter()) {
+            *value += harmonic;
+        }
+        self.buffer = bass;
+        Ok(())
+    }
+
+    fn update_parameters(&mut self, conf: config::Filter) {
+        if let config::Filter::BassEnhancer { parameters } = conf {
+            self.set_parameters(parameters);
+        } else {
+            // This should never happen unless there is a bug somewhere else
+            panic!("Invalid config change!");
+        }
+    }
+}
+
+/// Validate a BassEnhancer config.
+pub fn validate_config(samplerate: usize, conf: &config::BassEnhancerParameters) -> Res<()> {
+    if conf.freq <= 0.0 {
+        return Err(config::ConfigError::new("Frequency must be > 0").into());
+    } else if conf.freq >= samplerate as PrcFmt / 2.0 {
+        return Err(config::ConfigError::new("Frequency must be < samplerate/2").into());
+    }
+    if conf.harmonics == 0 || conf.harmonics > MAX_HARMONICS {
+        let msg = format!(
+            "Number of harmonics must be between 1 and {}",
+            MAX_HARMONICS
+        );
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    if conf.gain < -40.0 || conf.gain > 20.0 {
+        return Err(config::ConfigError::new("Gain must be between -40 and +20 dB").into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bassenhancer;
+    use crate::config;
+    use crate::filters::Filter;
+    use crate::PrcFmt;
+
+    fn is_close(left: PrcFmt, right: PrcFmt, maxdiff: PrcFmt) -> bool {
+        println!("{} - {}", left, right);
+        (left - right).abs() < maxdiff
+    }
+
+    // Amplitude of the component at the given frequency, for a waveform with a whole number of periods
+    fn amplitude_at(waveform: &[PrcFmt], freq: PrcFmt, fs: usize) -> PrcFmt {
+        let omega = 2.0 * (std::f64::consts::PI as PrcFmt) * freq / fs as PrcFmt;
+        let (re, im) = waveform
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (n, value)| {
+                (
+                    re + value * (omega * n as PrcFmt).cos(),
+                    im + value * (omega * n as PrcFmt).sin(),
+                )
+            });
+        2.0 * (re * re + im * im).sqrt() / waveform.len() as PrcFmt
+    }
+
+    fn process_sine(freq: PrcFmt) -> Vec<PrcFmt> {
+        let fs = 48000;
+        let conf = config::BassEnhancerParameters {
+            freq: 100.0,
+            harmonics: 4,
+            gain: 0.0,
+        };
+        let mut enhancer = bassenhancer::BassEnhancer::from_config("test".to_string(), fs, conf);
+        let omega = 2.0 * (std::f64::consts::PI as PrcFmt) * freq / fs as PrcFmt;
+        let mut waveform: Vec<PrcFmt> =
+            (0..fs).map(|n| 0.5 * (omega * n as PrcFmt).sin()).collect();
+        enhancer.process_waveform(&mut waveform).unwrap();
+        // Skip the first half second to let the filters settle
+        waveform[fs / 2..].to_vec()
+    }
+
+    #[test]
+    fn bass_harmonics() {
+        let fs = 48000;
+        let output = process_sine(50.0);
+        // The fundamental is removed by the crossover, and harmonics are added above it
+        assert!(amplitude_at(&output, 50.0, fs) < 0.1);
+        assert!(amplitude_at(&output, 150.0, fs) > 0.05);
+        assert!(amplitude_at(&output, 200.0, fs) > 0.05);
+    }
+
+    #[test]
+    fn treble_unchanged() {
+        let fs = 48000;
+        let output = process_sine(2000.0);
+        assert!(is_close(amplitude_at(&output, 2000.0, fs), 0.5, 0.01));
+        assert!(amplitude_at(&output, 4000.0, fs) < 0.001);
+    }
+
+    #[test]
+    fn update_keeps_state() {
+        let fs = 48000;
+        let conf = config::BassEnhancerParameters {
+            freq: 100.0,
+            harmonics: 4,
+            gain: 0.0,
+        };
+        let mut enhancer = bassenhancer::BassEnhancer::from_config("test".to_string(), fs, conf);
+        let mut waveform: Vec<PrcFmt> = (0..1000).map(|n| (n as PrcFmt * 0.01).sin()).collect();
+        enhancer.process_waveform(&mut waveform).unwrap();
+        let state = enhancer.lowpass[1].s1;
+        let envelope = enhancer.envelope;
+        assert!(state != 0.0);
+        for freq in [100.0, 120.0] {
+            let newconf = config::BassEnhancerParameters {
+                freq,
+                harmonics: 2,
+                gain: -6.0,
+            };
+            enhancer.update_parameters(config::Filter::BassEnhancer {
+                parameters: newconf,
+            });
+            assert_eq!(enhancer.lowpass[1].s1, state);
+            assert_eq!(enhancer.envelope, envelope);
+            assert_eq!(enhancer.weights.len(), 2);
+        }
+        assert_eq!(enhancer.freq, 120.0);
+    }
+
+    #[test]
+    fn check_bassenhancer() {
+        let fs = 48000;
+        let okconf = config::BassEnhancerParameters {
+            freq: 100.0,
+            harmonics: 4,
+            gain: -3.0,
+        };
+        assert!(bassenhancer::validate_config(fs, &okconf).is_ok());
+        let badconf1 = config::BassEnhancerParameters {
+            freq: 100.0,
+            harmonics: 0,
+            gain: 0.0,
+        };
+        assert!(bassenhancer::validate_config(fs, &badconf1).is_err());
+        let badconf2 = config::BassEnhancerParameters {
+            freq: 30000.0,
+            harmonics: 4,
+            gain: 0.0,
+        };
+        assert!(bassenhancer::validate_config(fs, &badconf2).is_err());
+    }
+}
@@ -41,9 +41,12 @@ use camillalib::Res;
 
 use camillalib::audiodevice;
 use camillalib::config;
+use camillalib::confighistory::ConfigHistory;
 use camillalib::processing;
 #[cfg(feature = "websocket")]
 use camillalib::socketserver;
+use camillalib::statefile;
+use camillalib::volumecontrol;
 #[cfg(feature = "websocket")]
 use std::net::IpAddr;
 
@@ -103,6 +106,27 @@ pub fn custom_logger_format(
     )
 }
 
+/// Get a websocket token, given either directly or as the path of a file containing it.
+/// Keeping the token in a file avoids showing it in the process list.
+#[cfg(feature = "websocket")]
+fn read_token(
+    matches: &clap::ArgMatches,
+    token_arg: &str,
+    file_arg: &str,
+) -> std::io::Result<Option<String>> {
+    if let Some(path) = matches.value_of(file_arg) {
+        let token = std::fs::read_to_string(path)?.trim_end().to_string();
+        if token.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("The token file {} is empty", path),
+            ));
+        }
+        return Ok(Some(token));
+    }
+    Ok(matches.value_of(token_arg).map(|token| token.to_string()))
+}
+
 fn get_new_config(
     config_path: &Arc<Mutex<Option<String>>>,
     new_config_shared: &Arc<Mutex<Option<config::Configuration>>>,
@@ -149,13 +173,16 @@ fn get_new_config(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn run(
     signal_reload: Arc<AtomicBool>,
     signal_exit: Arc<AtomicUsize>,
     active_config_shared: Arc<Mutex<Option<config::Configuration>>>,
+    config_generation: Arc<AtomicUsize>,
     config_path: Arc<Mutex<Option<String>>>,
     new_config_shared: Arc<Mutex<Option<config::Configuration>>>,
     prev_config_shared: Arc<Mutex<Option<config::Configuration>>>,
+    config_history: Arc<Mutex<ConfigHistory>>,
     status_structs: StatusStructs,
 ) -> Res<ExitState> {
     status_structs.capture.write().unwrap().state = ProcessingState::Starting;
@@ -189,7 +216,12 @@ fn run(
     let mut active_config = conf;
     //let conf_yaml = serde_yaml::to_string(&active_config).unwrap();
     *active_config_shared.lock().unwrap() = Some(active_config.clone());
+    config_generation.fetch_add(1, Ordering::Relaxed);
     *new_config_shared.lock().unwrap() = None;
+    config_history
+        .lock()
+        .unwrap()
+        .record(&active_config, &config_path.lock().unwrap(), false);
     signal_reload.store(false, Ordering::Relaxed);
     signal_exit.store(ExitRequest::NONE, Ordering::Relaxed);
 
@@ -250,10 +282,17 @@ fn run(
                         config::ConfigChange::Pipeline
                         | config::ConfigChange::MixerParameters
                         | config::ConfigChange::FilterParameters { .. } => {
+                            let parameters_only = !matches!(comp, config::ConfigChange::Pipeline);
                             tx_pipeconf.send((comp, conf.clone())).unwrap();
                             active_config = conf;
                             *active_config_shared.lock().unwrap() = Some(active_config.clone());
+                            config_generation.fetch_add(1, Ordering::Relaxed);
                             *new_config_shared.lock().unwrap() = None;
+                            config_history.lock().unwrap().record(
+                                &active_config,
+                                &config_path.lock().unwrap(),
+                                parameters_only,
+                            );
                             let used_channels = config::get_used_capture_channels(&active_config);
                             debug!("Using channels {:?}", used_channels);
                             status_structs.capture.write().unwrap().used_channels = used_channels;
@@ -494,7 +533,7 @@ fn main_process() -> i32 {
                 .help("The configuration file to use")
                 .index(1)
                 //.required(true),
-                .required_unless("wait"),
+                .required_unless_one(&["wait", "statefile"]),
         )
         .arg(
             Arg::with_name("check")
@@ -555,6 +594,13 @@ fn main_process() -> i32 {
                 .long("mute")
                 .display_order(200),
         )
+        .arg(
+            Arg::with_name("statefile")
+                .help("Use the given file for keeping runtime state like volume and active config")
+                .long("statefile")
+                .display_order(200)
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("samplerate")
                 .help("Override samplerate in config")
@@ -654,6 +700,78 @@ fn main_process() -> i32 {
                 .long("wait")
                 .help("Wait for config from websocket")
                 .requires("port"),
+        )
+        .arg(
+            Arg::with_name("control_token")
+                .long("control-token")
+                .display_order(200)
+                .takes_value(true)
+                .help("Token that websocket clients must give to control processing")
+                .requires("port"),
+        )
+        .arg(
+            Arg::with_name("monitor_token")
+                .long("monitor-token")
+                .display_order(200)
+                .takes_value(true)
+                .help("Token that websocket clients must give to read values")
+                .requires("control_token_source"),
+        )
+        .arg(
+            Arg::with_name("control_token_file")
+                .long("control-token-file")
+                .display_order(200)
+                .takes_value(true)
+                .help(
+                    "Read the control token from a file, instead of giving it on the command line",
+                )
+                .requires("port"),
+        )
+        .arg(
+            Arg::with_name("monitor_token_file")
+                .long("monitor-token-file")
+                .display_order(200)
+                .takes_value(true)
+                .help(
+                    "Read the monitor token from a file, instead of giving it on the command line",
+                )
+                .requires("control_token_source"),
+        )
+        .group(
+            clap::ArgGroup::with_name("control_token_source")
+                .args(&["control_token", "control_token_file"]),
+        )
+        .group(
+            clap::ArgGroup::with_name("monitor_token_source")
+                .args(&["monitor_token", "monitor_token_file"]),
+        )
+        .arg(
+            Arg::with_name("config_dir")
+                .long("config-dir")
+                .display_order(200)
+                .takes_value(true)
+                .help("Directory that config files loaded via websocket must be in")
+                .requires("port")
+                .validator(|val: String| -> Result<(), String> {
+                    if PathBuf::from(&val).is_dir() {
+                        return Ok(());
+                    }
+                    Err(String::from("Must be an existing directory"))
+                }),
+        )
+        .arg(
+            Arg::with_name("coeff_dir")
+                .long("coeff-dir")
+                .display_order(200)
+                .takes_value(true)
+                .help("Directory for coefficient files uploaded via websocket")
+                .requires("port")
+                .validator(|val: String| -> Result<(), String> {
+                    if PathBuf::from(&val).is_dir() {
+                        return Ok(());
+                    }
+                    Err(String::from("Must be an existing directory"))
+                }),
         );
     #[cfg(feature = "secure-websocket")]
     let clapapp = clapapp
@@ -729,14 +847,30 @@ fn main_process() -> i32 {
     #[cfg(target_os = "windows")]
     wasapi::initialize_mta().unwrap();
 
-    let configname = matches.value_of("configfile").map(|path| path.to_string());
+    // Values given on the command line take precedence over the saved state
+    let state = matches
+        .value_of("statefile")
+        .and_then(statefile::load_state)
+        .unwrap_or_default();
+
+    let configname = matches
+        .value_of("configfile")
+        .map(|path| path.to_string())
+        .or(state.config_path);
+
+    // The saved preset only describes the saved config path
+    let last_preset = if matches.is_present("configfile") {
+        None
+    } else {
+        state.preset
+    };
 
     let initial_volume = matches
         .value_of("gain")
         .map(|s| s.parse::<f32>().unwrap())
-        .unwrap_or(0.0);
+        .unwrap_or(state.volume);
 
-    let initial_mute = matches.is_present("mute");
+    let initial_mute = matches.is_present("mute") || state.mute;
 
     config::OVERRIDES.write().unwrap().samplerate = matches
         .value_of("samplerate")
@@ -754,7 +888,14 @@ fn main_process() -> i32 {
     debug!("Read config file {:?}", configname);
 
     if matches.is_present("check") {
-        match config::load_validate_config(&configname.unwrap()) {
+        let configname = match configname {
+            Some(name) => name,
+            None => {
+                println!("No config file given, and none in the state file");
+                return EXIT_BAD_CONFIG;
+            }
+        };
+        match config::load_validate_config(&configname) {
             Ok(_) => {
                 println!("Config is valid");
                 return EXIT_OK;
@@ -806,7 +947,12 @@ fn main_process() -> i32 {
     let processing_status = Arc::new(RwLock::new(ProcessingParameters {
         volume: initial_volume,
         mute: initial_mute,
+        faders: state.faders,
     }));
+    // Limit the restored or given volumes to the range of the config
+    if let Some(conf) = &configuration {
+        volumecontrol::clamp_volumes(&conf.volume, &mut processing_status.write().unwrap());
+    }
     let status = Arc::new(RwLock::new(ProcessingStatus {
         stop_reason: StopReason::None,
     }));
@@ -818,23 +964,52 @@ fn main_process() -> i32 {
         status: status.clone(),
     };
     let active_config = Arc::new(Mutex::new(None));
+    let config_generation = Arc::new(AtomicUsize::new(0));
     let new_config = Arc::new(Mutex::new(configuration));
     let previous_config = Arc::new(Mutex::new(None));
+    let config_history = Arc::new(Mutex::new(ConfigHistory::default()));
 
     let active_config_path = Arc::new(Mutex::new(configname));
+    let last_preset = Arc::new(Mutex::new(last_preset));
+
+    if let Some(filename) = matches.value_of("statefile") {
+        statefile::start_saver(
+            filename.to_string(),
+            processing_status.clone(),
+            active_config_path.clone(),
+            last_preset.clone(),
+        );
+    }
 
     #[cfg(feature = "websocket")]
     {
         if let Some(port_str) = matches.value_of("port") {
             let serveraddress = matches.value_of("address").unwrap_or("127.0.0.1");
             let serverport = port_str.parse::<usize>().unwrap();
+            let control_token = match read_token(&matches, "control_token", "control_token_file") {
+                Ok(token) => token,
+                Err(err) => {
+                    error!("Could not read control token: {}", err);
+                    return EXIT_BAD_CONFIG;
+                }
+            };
+            let monitor_token = match read_token(&matches, "monitor_token", "monitor_token_file") {
+                Ok(token) => token,
+                Err(err) => {
+                    error!("Could not read monitor token: {}", err);
+                    return EXIT_BAD_CONFIG;
+                }
+            };
             let shared_data = socketserver::SharedData {
                 signal_reload: signal_reload.clone(),
                 signal_exit: signal_exit.clone(),
                 active_config: active_config.clone(),
+                config_generation: config_generation.clone(),
                 active_config_path: active_config_path.clone(),
+                last_preset: last_preset.clone(),
                 new_config: new_config.clone(),
                 previous_config: previous_config.clone(),
+                config_history: config_history.clone(),
                 capture_status,
                 playback_status,
                 processing_status,
@@ -847,6 +1022,10 @@ fn main_process() -> i32 {
                 cert_file: matches.value_of("cert"),
                 #[cfg(feature = "secure-websocket")]
                 cert_pass: matches.value_of("pass"),
+                control_token: control_token.as_deref(),
+                monitor_token: monitor_token.as_deref(),
+                config_dir: matches.value_of("config_dir"),
+                coeff_dir: matches.value_of("coeff_dir"),
             };
             socketserver::start_server(server_params, shared_data);
         }
@@ -892,14 +1071,17 @@ fn main_process() -> i32 {
             signal_reload.clone(),
             signal_exit.clone(),
             active_config.clone(),
+            config_generation.clone(),
             active_config_path.clone(),
             new_config.clone(),
             previous_config.clone(),
+            config_history.clone(),
             status_structs.clone(),
         );
         match exitstatus {
             Err(e) => {
                 *active_config.lock().unwrap() = None;
+                config_generation.fetch_add(1, Ordering::Relaxed);
                 error!("({}) {}", e.to_string(), e);
                 if !wait {
                     return EXIT_PROCESSING_ERROR;
@@ -908,10 +1090,12 @@ fn main_process() -> i32 {
             Ok(ExitState::Exit) => {
                 debug!("Exiting");
                 *active_config.lock().unwrap() = None;
+                config_generation.fetch_add(1, Ordering::Relaxed);
                 return EXIT_OK;
             }
             Ok(ExitState::Restart) => {
                 *active_config.lock().unwrap() = None;
+                config_generation.fetch_add(1, Ordering::Relaxed);
                 debug!("Restarting with new config");
             }
         };
@@ -11,6 +11,7 @@ use crate::filters::Filter;
 use crate::NewValue;
 use crate::PrcFmt;
 use crate::Res;
+use num_complex::Complex;
 
 /// Struct to hold the biquad coefficients
 #[derive(Clone, Copy, Debug)]
@@ -54,6 +55,84 @@ impl BiquadCoefficients {
         self.a2.abs() < 1.0 && (self.a1.abs() < (self.a2 + 1.0))
     }
 
+    /// Calculate the gain in dB at the given frequency.
+    pub fn gain_at(&self, freq: PrcFmt, fs: usize) -> PrcFmt {
+        let pi = std::f64::consts::PI as PrcFmt;
+        let z = (Complex::i() * 2.0 * pi * freq / (fs as PrcFmt)).exp();
+        let h = (self.b0 + self.b1 * z.powi(-1) + self.b2 * z.powi(-2))
+            / (1.0 + self.a1 * z.powi(-1) + self.a2 * z.powi(-2));
+        20.0 * h.norm().log10()
+    }
+
+    /// Create biquad filters from config, using the given design method.
+    /// - Cookbook: bilinear transform as in the Audio EQ Cookbook, see `from_config`.
+    /// - Matched: magnitude matched to the analog prototype up to Nyquist,
+    ///   avoiding the cramping of the response near Nyquist given by the bilinear transform.
+    pub fn from_config_with_design(
+        fs: usize,
+        design: &config::BiquadDesign,
+        parameters: config::BiquadParameters,
+    ) -> Self {
+        match design {
+            config::BiquadDesign::Cookbook => BiquadCoefficients::from_config(fs, parameters),
+            config::BiquadDesign::Matched => {
+                let (freq, num, den) = analog_prototype(&parameters).unwrap();
+                BiquadCoefficients::matched(fs, freq, num, den)
+            }
+        }
+    }
+
+    /// Design a biquad from a normalized analog prototype, based on
+    /// Martin Vicanek, "Matched Second Order Digital Filters", 2016.
+    /// The poles are matched by impulse invariance. The zeros are then chosen so that the magnitude
+    /// matches the analog prototype at DC, at the center frequency, and at Nyquist.
+    /// Highpass filters instead keep a double zero at DC, and are matched only at the center frequency.
+    fn matched(fs: usize, freq: PrcFmt, num: [PrcFmt; 3], den: [PrcFmt; 3]) -> Self {
+        let pi = std::f64::consts::PI as PrcFmt;
+        let omega = 2.0 * pi * freq / (fs as PrcFmt);
+
+        let omega_p = omega * (den[0] / den[2]).sqrt();
+        let zeta = den[1] / (2.0 * (den[0] * den[2]).sqrt());
+        let a1 = if zeta <= 1.0 {
+            -2.0 * (-zeta * omega_p).exp() * ((1.0 - zeta * zeta).sqrt() * omega_p).cos()
+        } else {
+            -2.0 * (-zeta * omega_p).exp() * ((zeta * zeta - 1.0).sqrt() * omega_p).cosh()
+        };
+        let a2 = (-2.0 * zeta * omega_p).exp();
+
+        let analog_magn_sq = |w: PrcFmt| {
+            let s = Complex::new(0.0, w / omega);
+            let h = (num[0] + num[1] * s + num[2] * s * s) / (den[0] + den[1] * s + den[2] * s * s);
+            h.norm_sqr()
+        };
+        let phi = |w: PrcFmt| {
+            let sn2 = (w / 2.0).sin().powi(2);
+            [1.0 - sn2, sn2, 4.0 * (1.0 - sn2) * sn2]
+        };
+        let big_a = [(1.0 + a1 + a2).powi(2), (1.0 - a1 + a2).powi(2), -4.0 * a2];
+        let phi_c = phi(omega);
+        let den_sq_c = big_a[0] * phi_c[0] + big_a[1] * phi_c[1] + big_a[2] * phi_c[2];
+
+        let target_c = analog_magn_sq(omega) * den_sq_c;
+
+        if num == [0.0, 0.0, 1.0] {
+            // Highpass, keep the double zero at DC and match only at the center frequency
+            let b0 = target_c.sqrt() / (4.0 * phi_c[1]);
+            return BiquadCoefficients::new(a1, a2, b0, -2.0 * b0, b0);
+        }
+        let big_b0 = analog_magn_sq(0.0) * big_a[0];
+        let big_b1 = analog_magn_sq(pi) * big_a[1];
+        let big_b2 = (target_c - big_b0 * phi_c[0] - big_b1 * phi_c[1]) / phi_c[2];
+
+        let sqrt_b0 = big_b0.sqrt();
+        let sqrt_b1 = big_b1.sqrt();
+        let w = 0.5 * (sqrt_b0 + sqrt_b1);
+        let b0 = 0.5 * (w + (w * w + big_b2).max(0.0).sqrt());
+        let b1 = 0.5 * (sqrt_b0 - sqrt_b1);
+        let b2 = -big_b2 / (4.0 * b0);
+        BiquadCoefficients::new(a1, a2, b0, b1, b2)
+    }
+
     /// Create biquad filters from config.
     /// Filter types
     /// - Free: just coefficients
@@ -369,6 +448,81 @@ impl BiquadCoefficients {
     }
 }
 
+/// Get the analog prototype of a second order filter, as frequency, numerator and denominator.
+/// The polynomial coefficients are ordered as s^0, s^1, s^2, with s normalized to the frequency.
+/// Returns None for filter types that don't have a second order prototype.
+fn analog_prototype(
+    parameters: &config::BiquadParameters,
+) -> Option<(PrcFmt, [PrcFmt; 3], [PrcFmt; 3])> {
+    let q_from_bandwidth =
+        |bandwidth: PrcFmt| 1.0 / (2.0 * ((2.0 as PrcFmt).ln() / 2.0 * bandwidth).sinh());
+    let q_from_slope = |slope: PrcFmt, ampl: PrcFmt| {
+        1.0 / ((ampl + 1.0 / ampl) * (1.0 / (slope / 12.0) - 1.0) + 2.0).sqrt()
+    };
+    match *parameters {
+        config::BiquadParameters::Lowpass { freq, q } => {
+            Some((freq, [1.0, 0.0, 0.0], [1.0, 1.0 / q, 1.0]))
+        }
+        config::BiquadParameters::Highpass { freq, q } => {
+            Some((freq, [0.0, 0.0, 1.0], [1.0, 1.0 / q, 1.0]))
+        }
+        config::BiquadParameters::Bandpass(config::NotchWidth::Q { freq, q }) => {
+            Some((freq, [0.0, 1.0 / q, 0.0], [1.0, 1.0 / q, 1.0]))
+        }
+        config::BiquadParameters::Bandpass(config::NotchWidth::Bandwidth { freq, bandwidth }) => {
+            let q = q_from_bandwidth(bandwidth);
+            Some((freq, [0.0, 1.0 / q, 0.0], [1.0, 1.0 / q, 1.0]))
+        }
+        config::BiquadParameters::Notch(config::NotchWidth::Q { freq, q }) => {
+            Some((freq, [1.0, 0.0, 1.0], [1.0, 1.0 / q, 1.0]))
+        }
+        config::BiquadParameters::Notch(config::NotchWidth::Bandwidth { freq, bandwidth }) => {
+            let q = q_from_bandwidth(bandwidth);
+            Some((freq, [1.0, 0.0, 1.0], [1.0, 1.0 / q, 1.0]))
+        }
+        config::BiquadParameters::Peaking(config::PeakingWidth::Q { freq, q, gain }) => {
+            let ampl = PrcFmt::new(10.0).powf(gain / 40.0);
+            Some((freq, [1.0, ampl / q, 1.0], [1.0, 1.0 / (ampl * q), 1.0]))
+        }
+        config::BiquadParameters::Peaking(config::PeakingWidth::Bandwidth {
+            freq,
+            bandwidth,
+            gain,
+        }) => {
+            let ampl = PrcFmt::new(10.0).powf(gain / 40.0);
+            let q = q_from_bandwidth(bandwidth);
+            Some((freq, [1.0, ampl / q, 1.0], [1.0, 1.0 / (ampl * q), 1.0]))
+        }
+        config::BiquadParameters::Highshelf(ref steepness) => {
+            let (freq, q, ampl) = match *steepness {
+                config::ShelfSteepness::Q { freq, q, gain } => {
+                    (freq, q, PrcFmt::new(10.0).powf(gain / 40.0))
+                }
+                config::ShelfSteepness::Slope { freq, slope, gain } => {
+                    let ampl = PrcFmt::new(10.0).powf(gain / 40.0);
+                    (freq, q_from_slope(slope, ampl), ampl)
+                }
+            };
+            let beta = ampl.sqrt() / q;
+            Some((freq, [ampl, ampl * beta, ampl * ampl], [ampl, beta, 1.0]))
+        }
+        config::BiquadParameters::Lowshelf(ref steepness) => {
+            let (freq, q, ampl) = match *steepness {
+                config::ShelfSteepness::Q { freq, q, gain } => {
+                    (freq, q, PrcFmt::new(10.0).powf(gain / 40.0))
+                }
+                config::ShelfSteepness::Slope { freq, slope, gain } => {
+                    let ampl = PrcFmt::new(10.0).powf(gain / 40.0);
+                    (freq, q_from_slope(slope, ampl), ampl)
+                }
+            };
+            let beta = ampl.sqrt() / q;
+            Some((freq, [ampl * ampl, ampl * beta, ampl], [1.0, beta, ampl]))
+        }
+        _ => None,
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Biquad {
     samplerate: usize,
@@ -390,6 +544,11 @@ impl Biquad {
         }
     }
 
+    /// Replace the coefficients while keeping the filter state
+    pub fn set_coefficients(&mut self, coefficients: BiquadCoefficients) {
+        self.coeffs = coefficients;
+    }
+
     /// Process a single sample
     fn process_single(&mut self, input: PrcFmt) -> PrcFmt {
         let out = self.s1 + self.coeffs.b0 * input;
@@ -425,8 +584,13 @@ impl Filter for Biquad {
     }
 
     fn update_parameters(&mut self, conf: config::Filter) {
-        if let config::Filter::Biquad { parameters: conf } = conf {
-            let coeffs = BiquadCoefficients::from_config(self.samplerate, conf);
+        if let config::Filter::Biquad {
+            design,
+            parameters: conf,
+        } = conf
+        {
+            let coeffs =
+                BiquadCoefficients::from_config_with_design(self.samplerate, &design, conf);
             self.coeffs = coeffs;
         } else {
             // This should never happen unless there is a bug somewhere else
@@ -532,10 +696,26 @@ pub fn validate_config(samplerate: usize, parameters: &config::BiquadParameters)
     Ok(())
 }
 
+/// Check that the selected design method supports the filter type.
+pub fn validate_design(
+    design: &config::BiquadDesign,
+    parameters: &config::BiquadParameters,
+) -> Res<()> {
+    if *design == config::BiquadDesign::Matched && analog_prototype(parameters).is_none() {
+        return Err(config::ConfigError::new(
+            "Matched design is only supported for second order lowpass, highpass, bandpass, notch, peaking and shelving filters",
+        )
+        .into());
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::biquad::{validate_config, Biquad, BiquadCoefficients};
-    use crate::config::{BiquadParameters, NotchWidth, PeakingWidth, ShelfSteepness};
+    use crate::biquad::{
+        analog_prototype, validate_config, validate_design, Biquad, BiquadCoefficients,
+    };
+    use crate::config::{BiquadDesign, BiquadParameters, NotchWidth, PeakingWidth, ShelfSteepness};
     use crate::filters::Filter;
     use crate::PrcFmt;
     use num_complex::Complex;
@@ -659,6 +839,79 @@ mod tests {
         assert!(is_close(gain_hf, 0.0, 0.1));
     }
 
+    fn analog_gain(parameters: &BiquadParameters, f: PrcFmt) -> PrcFmt {
+        let (freq, num, den) = analog_prototype(parameters).unwrap();
+        let s = Complex::new(0.0, f / freq);
+        let h = (num[0] + num[1] * s + num[2] * s * s) / (den[0] + den[1] * s + den[2] * s * s);
+        20.0 * h.norm().log10()
+    }
+
+    fn check_matched(conf: BiquadParameters, fs: usize, maxdiff: PrcFmt) {
+        let coeffs =
+            BiquadCoefficients::from_config_with_design(fs, &BiquadDesign::Matched, conf.clone());
+        assert!(coeffs.is_stable());
+        for f in [
+            10.0, 100.0, 1000.0, 5000.0, 10000.0, 15000.0, 20000.0, 23000.0,
+        ] {
+            let (gain, _) = gain_and_phase(coeffs, f, fs);
+            assert!(is_close(gain, analog_gain(&conf, f), maxdiff));
+        }
+    }
+
+    #[test]
+    fn make_matched() {
+        let fs = 48000;
+        let peaking = BiquadParameters::Peaking(PeakingWidth::Q {
+            freq: 16000.0,
+            gain: 6.0,
+            q: 1.5,
+        });
+        check_matched(peaking.clone(), fs, 0.6);
+        // The cookbook peaking filter is cramped and gives 0 dB at Nyquist
+        let cookbook = BiquadCoefficients::from_config(fs, peaking.clone());
+        let (gain_cookbook, _) = gain_and_phase(cookbook, 23999.0, fs);
+        assert!(is_close(gain_cookbook, 0.0, 0.1));
+        let highshelf = BiquadParameters::Highshelf(ShelfSteepness::Slope {
+            freq: 10000.0,
+            slope: 6.0,
+            gain: -6.0,
+        });
+        check_matched(highshelf, fs, 0.3);
+        let lowshelf = BiquadParameters::Lowshelf(ShelfSteepness::Q {
+            freq: 5000.0,
+            q: 0.7,
+            gain: 6.0,
+        });
+        check_matched(lowshelf, fs, 0.3);
+        let lowpass = BiquadParameters::Lowpass {
+            freq: 10000.0,
+            q: 0.7,
+        };
+        check_matched(lowpass, fs, 1.0);
+        let highpass = BiquadParameters::Highpass {
+            freq: 10000.0,
+            q: 0.7,
+        };
+        check_matched(highpass, fs, 1.0);
+        let bandpass = BiquadParameters::Bandpass(NotchWidth::Q {
+            freq: 8000.0,
+            q: 2.0,
+        });
+        check_matched(bandpass, fs, 1.0);
+    }
+
+    #[test]
+    fn check_matched_design() {
+        let lowpass = BiquadParameters::Lowpass {
+            freq: 1000.0,
+            q: 0.7,
+        };
+        assert!(validate_design(&BiquadDesign::Matched, &lowpass).is_ok());
+        let allpass = BiquadParameters::AllpassFO { freq: 1000.0 };
+        assert!(validate_design(&BiquadDesign::Matched, &allpass).is_err());
+        assert!(validate_design(&BiquadDesign::Cookbook, &allpass).is_ok());
+    }
+
     #[test]
     fn make_bandpass() {
         let conf = BiquadParameters::Bandpass(NotchWidth::Q {
@@ -6,12 +6,32 @@
 use crate::biquad;
 use crate::config;
 use crate::filters::Filter;
+use crate::iirdesign;
 
 // Sample format
 //type SmpFmt = i16;
 use crate::PrcFmt;
 use crate::Res;
 
+// ISO 266 preferred center frequencies for octave and third-octave band equalizers
+const ISO_OCTAVE_BANDS: [PrcFmt; 10] = [
+    31.5, 63.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+const ISO_THIRD_OCTAVE_BANDS: [PrcFmt; 31] = [
+    20.0, 25.0, 31.5, 40.0, 50.0, 63.0, 80.0, 100.0, 125.0, 160.0, 200.0, 250.0, 315.0, 400.0,
+    500.0, 630.0, 800.0, 1000.0, 1250.0, 1600.0, 2000.0, 2500.0, 3150.0, 4000.0, 5000.0, 6300.0,
+    8000.0, 10000.0, 12500.0, 16000.0, 20000.0,
+];
+
+// Maximum number of iterations used when compensating for band interaction
+const GEQ_COMPENSATION_ITERATIONS: usize = 20;
+// Compensation stops when all center frequencies are within this many dB of their targets
+const GEQ_COMPENSATION_TOLERANCE: PrcFmt = 0.01;
+// Limit for the compensated band gains in dB
+const GEQ_MAX_BAND_GAIN: PrcFmt = 60.0;
+// Highest allowed band frequency, relative to the Nyquist frequency
+const GEQ_MAX_RELATIVE_FREQ: PrcFmt = 0.95;
+
 #[derive(Clone, Debug)]
 pub struct BiquadCombo {
     samplerate: usize,
@@ -114,6 +134,177 @@ impl BiquadCombo {
         filters
     }
 
+    fn make_from_prototype(
+        samplerate: usize,
+        freq: PrcFmt,
+        sections: Vec<iirdesign::AnalogSection>,
+        highpass: bool,
+    ) -> Vec<biquad::Biquad> {
+        sections
+            .into_iter()
+            .map(|section| {
+                let section = if highpass {
+                    section.to_highpass()
+                } else {
+                    section
+                };
+                let coeffs = section.to_biquad(freq, samplerate);
+                biquad::Biquad::new("".to_string(), samplerate, coeffs)
+            })
+            .collect()
+    }
+
+    /// Get the band center frequencies and the bandwidth in octaves for a graphic equalizer.
+    /// Without explicit frequencies, 10 gains give octave bands and 31 give third-octave bands.
+    fn graphic_eq_bands(
+        nbr_gains: usize,
+        freqs: &Option<Vec<PrcFmt>>,
+    ) -> Option<(Vec<PrcFmt>, PrcFmt)> {
+        match freqs {
+            Some(freqs) => {
+                if freqs.len() != nbr_gains || freqs.is_empty() {
+                    return None;
+                }
+                let bandwidth = if freqs.len() > 1 {
+                    let f_min = freqs.iter().cloned().fold(PrcFmt::INFINITY, PrcFmt::min);
+                    let f_max = freqs.iter().cloned().fold(0.0, PrcFmt::max);
+                    (f_max / f_min).log2() / (freqs.len() - 1) as PrcFmt
+                } else {
+                    1.0
+                };
+                Some((freqs.clone(), bandwidth))
+            }
+            None => match nbr_gains {
+                10 => Some((ISO_OCTAVE_BANDS.to_vec(), 1.0)),
+                31 => Some((ISO_THIRD_OCTAVE_BANDS.to_vec(), 1.0 / 3.0)),
+                _ => None,
+            },
+        }
+    }
+
+    fn graphic_eq_coeffs(
+        samplerate: usize,
+        freqs: &[PrcFmt],
+        q: PrcFmt,
+        gains: &[PrcFmt],
+    ) -> Vec<biquad::BiquadCoefficients> {
+        freqs
+            .iter()
+            .zip(gains.iter())
+            .map(|(f, g)| {
+                let filtconf = config::BiquadParameters::Peaking(config::PeakingWidth::Q {
+                    freq: *f,
+                    q,
+                    gain: *g,
+                });
+                biquad::BiquadCoefficients::from_config(samplerate, filtconf)
+            })
+            .collect()
+    }
+
+    /// Design the peaking filters of a graphic equalizer.
+    /// With compensation enabled, the band gains are adjusted iteratively
+    /// until the combined response at each center frequency matches the requested gain.
+    /// The iteration stops if the error grows, which can happen for closely or unevenly
+    /// spaced bands, and the band gains that gave the smallest error are then used.
+    fn design_graphic_eq(
+        samplerate: usize,
+        gains: &[PrcFmt],
+        freqs: &Option<Vec<PrcFmt>>,
+        compensate: bool,
+    ) -> Vec<biquad::BiquadCoefficients> {
+        let (freqs, bandwidth) = BiquadCombo::graphic_eq_bands(gains.len(), freqs).unwrap();
+        let targets = gains.to_vec();
+        let bw_factor = (2.0 as PrcFmt).powf(bandwidth);
+        let q = bw_factor.sqrt() / (bw_factor - 1.0);
+
+        let mut band_gains = targets.clone();
+        if compensate {
+            let mut best_gains = band_gains.clone();
+            let mut best_error = PrcFmt::INFINITY;
+            for _ in 0..GEQ_COMPENSATION_ITERATIONS {
+                let coeffs = BiquadCombo::graphic_eq_coeffs(samplerate, &freqs, q, &band_gains);
+                let errors: Vec<PrcFmt> = freqs
+                    .iter()
+                    .zip(targets.iter())
+                    .map(|(f, target)| {
+                        target
+                            - coeffs
+                                .iter()
+                                .map(|c| c.gain_at(*f, samplerate))
+                                .sum::<PrcFmt>()
+                    })
+                    .collect();
+                let max_error = errors.iter().fold(0.0, |acc: PrcFmt, e| acc.max(e.abs()));
+                if max_error >= best_error {
+                    debug!(
+                        "Graphic equalizer compensation doesn't converge, remaining error {} dB",
+                        best_error
+                    );
+                    break;
+                }
+                best_error = max_error;
+                best_gains = band_gains.clone();
+                if max_error < GEQ_COMPENSATION_TOLERANCE {
+                    break;
+                }
+                for (gain, error) in band_gains.iter_mut().zip(errors.iter()) {
+                    *gain = (*gain + error).clamp(-GEQ_MAX_BAND_GAIN, GEQ_MAX_BAND_GAIN);
+                }
+            }
+            band_gains = best_gains;
+        }
+
+        BiquadCombo::graphic_eq_coeffs(samplerate, &freqs, q, &band_gains)
+            .into_iter()
+            .zip(band_gains.iter())
+            .filter(|(_c, g)| g.abs() > 0.001)
+            .map(|(c, _g)| c)
+            .collect()
+    }
+
+    /// Design a tilt equalizer, as a first order high shelf with the level scaled
+    /// to give 0 dB at the pivot frequency.
+    /// High frequencies are boosted by `gain` dB while low frequencies are cut by the same amount.
+    fn make_tilt(samplerate: usize, freq: PrcFmt, gain: PrcFmt) -> Vec<biquad::Biquad> {
+        let filtconf = config::BiquadParameters::HighshelfFO {
+            freq,
+            gain: 2.0 * gain,
+        };
+        let mut coeffs = biquad::BiquadCoefficients::from_config(samplerate, filtconf);
+        let base: PrcFmt = 10.0;
+        let scale = base.powf(-gain / 20.0);
+        coeffs.b0 *= scale;
+        coeffs.b1 *= scale;
+        coeffs.b2 *= scale;
+        vec![biquad::Biquad::new("".to_string(), samplerate, coeffs)]
+    }
+
+    /// Design a Baxandall style bass and treble tone control, as a pair of first order shelves.
+    fn make_baxandall(
+        samplerate: usize,
+        bass_freq: PrcFmt,
+        bass_gain: PrcFmt,
+        treble_freq: PrcFmt,
+        treble_gain: PrcFmt,
+    ) -> Vec<biquad::Biquad> {
+        let bass_conf = config::BiquadParameters::LowshelfFO {
+            freq: bass_freq,
+            gain: bass_gain,
+        };
+        let treble_conf = config::BiquadParameters::HighshelfFO {
+            freq: treble_freq,
+            gain: treble_gain,
+        };
+        [bass_conf, treble_conf]
+            .into_iter()
+            .map(|filtconf| {
+                let coeffs = biquad::BiquadCoefficients::from_config(samplerate, filtconf);
+                biquad::Biquad::new("".to_string(), samplerate, coeffs)
+            })
+            .collect()
+    }
+
     pub fn from_config(
         name: String,
         samplerate: usize,
@@ -185,6 +376,155 @@ impl BiquadCombo {
                     filters,
                 }
             }
+            config::BiquadComboParameters::BesselHighpass {
+                order,
+                freq,
+                normalization,
+            } => {
+                let sections = iirdesign::bessel_lowpass(order, &normalization);
+                let filters = BiquadCombo::make_from_prototype(samplerate, freq, sections, true);
+                BiquadCombo {
+                    samplerate,
+                    name,
+                    filters,
+                }
+            }
+            config::BiquadComboParameters::BesselLowpass {
+                order,
+                freq,
+                normalization,
+            } => {
+                let sections = iirdesign::bessel_lowpass(order, &normalization);
+                let filters = BiquadCombo::make_from_prototype(samplerate, freq, sections, false);
+                BiquadCombo {
+                    samplerate,
+                    name,
+                    filters,
+                }
+            }
+            config::BiquadComboParameters::Chebyshev1Highpass {
+                order,
+                freq,
+                ripple,
+            } => {
+                let sections = iirdesign::chebyshev1_lowpass(order, ripple);
+                let filters = BiquadCombo::make_from_prototype(samplerate, freq, sections, true);
+                BiquadCombo {
+                    samplerate,
+                    name,
+                    filters,
+                }
+            }
+            config::BiquadComboParameters::Chebyshev1Lowpass {
+                order,
+                freq,
+                ripple,
+            } => {
+                let sections = iirdesign::chebyshev1_lowpass(order, ripple);
+                let filters = BiquadCombo::make_from_prototype(samplerate, freq, sections, false);
+                BiquadCombo {
+                    samplerate,
+                    name,
+                    filters,
+                }
+            }
+            config::BiquadComboParameters::Chebyshev2Highpass {
+                order,
+                freq,
+                attenuation,
+            } => {
+                let sections = iirdesign::chebyshev2_lowpass(order, attenuation);
+                let filters = BiquadCombo::make_from_prototype(samplerate, freq, sections, true);
+                BiquadCombo {
+                    samplerate,
+                    name,
+                    filters,
+                }
+            }
+            config::BiquadComboParameters::Chebyshev2Lowpass {
+                order,
+                freq,
+                attenuation,
+            } => {
+                let sections = iirdesign::chebyshev2_lowpass(order, attenuation);
+                let filters = BiquadCombo::make_from_prototype(samplerate, freq, sections, false);
+                BiquadCombo {
+                    samplerate,
+                    name,
+                    filters,
+                }
+            }
+            config::BiquadComboParameters::EllipticHighpass {
+                order,
+                freq,
+                ripple,
+                attenuation,
+            } => {
+                let sections = iirdesign::elliptic_lowpass(order, ripple, attenuation);
+                let filters = BiquadCombo::make_from_prototype(samplerate, freq, sections, true);
+                BiquadCombo {
+                    samplerate,
+                    name,
+                    filters,
+                }
+            }
+            config::BiquadComboParameters::EllipticLowpass {
+                order,
+                freq,
+                ripple,
+                attenuation,
+            } => {
+                let sections = iirdesign::elliptic_lowpass(order, ripple, attenuation);
+                let filters = BiquadCombo::make_from_prototype(samplerate, freq, sections, false);
+                BiquadCombo {
+                    samplerate,
+                    name,
+                    filters,
+                }
+            }
+            config::BiquadComboParameters::GraphicEqualizer {
+                gains,
+                freqs,
+                compensate,
+            } => {
+                let filters =
+                    BiquadCombo::design_graphic_eq(samplerate, &gains, &freqs, compensate)
+                        .into_iter()
+                        .map(|coeffs| biquad::Biquad::new("".to_string(), samplerate, coeffs))
+                        .collect();
+                BiquadCombo {
+                    samplerate,
+                    name,
+                    filters,
+                }
+            }
+            config::BiquadComboParameters::Tilt { freq, gain } => {
+                let filters = BiquadCombo::make_tilt(samplerate, freq, gain);
+                BiquadCombo {
+                    samplerate,
+                    name,
+                    filters,
+                }
+            }
+            config::BiquadComboParameters::Baxandall {
+                bass_freq,
+                bass_gain,
+                treble_freq,
+                treble_gain,
+            } => {
+                let filters = BiquadCombo::make_baxandall(
+                    samplerate,
+                    bass_freq,
+                    bass_gain,
+                    treble_freq,
+                    treble_gain,
+                );
+                BiquadCombo {
+                    samplerate,
+                    name,
+                    filters,
+                }
+            }
         }
     }
 }
@@ -269,13 +609,180 @@ pub fn validate_config(samplerate: usize, conf: &config::BiquadComboParameters)
             }
             Ok(())
         }
+        config::BiquadComboParameters::BesselHighpass { freq, order, .. }
+        | config::BiquadComboParameters::BesselLowpass { freq, order, .. } => {
+            validate_freq_and_order(*freq, *order, maxfreq)
+        }
+        config::BiquadComboParameters::Chebyshev1Highpass {
+            freq,
+            order,
+            ripple,
+        }
+        | config::BiquadComboParameters::Chebyshev1Lowpass {
+            freq,
+            order,
+            ripple,
+        } => {
+            validate_freq_and_order(*freq, *order, maxfreq)?;
+            if *ripple <= 0.0 {
+                return Err(config::ConfigError::new("Ripple must be > 0").into());
+            }
+            Ok(())
+        }
+        config::BiquadComboParameters::Chebyshev2Highpass {
+            freq,
+            order,
+            attenuation,
+        }
+        | config::BiquadComboParameters::Chebyshev2Lowpass {
+            freq,
+            order,
+            attenuation,
+        } => {
+            validate_freq_and_order(*freq, *order, maxfreq)?;
+            if *attenuation <= 0.0 {
+                return Err(config::ConfigError::new("Attenuation must be > 0").into());
+            }
+            Ok(())
+        }
+        config::BiquadComboParameters::EllipticHighpass {
+            freq,
+            order,
+            ripple,
+            attenuation,
+        }
+        | config::BiquadComboParameters::EllipticLowpass {
+            freq,
+            order,
+            ripple,
+            attenuation,
+        } => {
+            validate_freq_and_order(*freq, *order, maxfreq)?;
+            if *ripple <= 0.0 {
+                return Err(config::ConfigError::new("Ripple must be > 0").into());
+            }
+            if *attenuation <= *ripple {
+                return Err(
+                    config::ConfigError::new("Attenuation must be larger than ripple").into(),
+                );
+            }
+            Ok(())
+        }
+        config::BiquadComboParameters::GraphicEqualizer { gains, freqs, .. } => {
+            let max_band_freq = GEQ_MAX_RELATIVE_FREQ * maxfreq;
+            match freqs {
+                Some(freqs) => {
+                    if freqs.len() != gains.len() {
+                        return Err(config::ConfigError::new(
+                            "Number of gains must match the number of frequencies",
+                        )
+                        .into());
+                    }
+                    if freqs.is_empty() {
+                        return Err(config::ConfigError::new("At least one band is needed").into());
+                    }
+                    if freqs.iter().any(|f| *f <= 0.0) {
+                        return Err(config::ConfigError::new("Frequency must be > 0").into());
+                    } else if freqs.iter().any(|f| *f >= max_band_freq) {
+                        let msg = format!("Frequency must be < {} Hz", max_band_freq);
+                        return Err(config::ConfigError::new(&msg).into());
+                    }
+                    if freqs.windows(2).any(|pair| pair[1] <= pair[0]) {
+                        return Err(config::ConfigError::new(
+                            "Frequencies must be given in increasing order, without duplicates",
+                        )
+                        .into());
+                    }
+                }
+                None => {
+                    if gains.len() != 10 && gains.len() != 31 {
+                        let msg = format!(
+                            "Expected 10 or 31 gains for standard bands, found {}",
+                            gains.len()
+                        );
+                        return Err(config::ConfigError::new(&msg).into());
+                    }
+                    let (std_freqs, _) = BiquadCombo::graphic_eq_bands(gains.len(), freqs).unwrap();
+                    if std_freqs.iter().any(|f| *f >= max_band_freq) {
+                        let msg = format!(
+                            "The standard bands need a sample rate above {:.0} Hz",
+                            2.0 * std_freqs[std_freqs.len() - 1] / GEQ_MAX_RELATIVE_FREQ
+                        );
+                        return Err(config::ConfigError::new(&msg).into());
+                    }
+                }
+            }
+            if gains.iter().any(|g| g.abs() > 40.0) {
+                return Err(config::ConfigError::new("Gains must be within +-40 dB").into());
+            }
+            Ok(())
+        }
+        config::BiquadComboParameters::Tilt { freq, gain } => {
+            validate_tone_freq(*freq, maxfreq)?;
+            validate_tone_gain(*gain)
+        }
+        config::BiquadComboParameters::Baxandall {
+            bass_freq,
+            bass_gain,
+            treble_freq,
+            treble_gain,
+        } => {
+            validate_tone_freq(*bass_freq, maxfreq)?;
+            validate_tone_freq(*treble_freq, maxfreq)?;
+            if *bass_freq >= *treble_freq {
+                return Err(config::ConfigError::new(
+                    "Bass frequency must be lower than treble frequency",
+                )
+                .into());
+            }
+            validate_tone_gain(*bass_gain)?;
+            validate_tone_gain(*treble_gain)
+        }
     }
 }
 
+// Highest supported order for Bessel, Chebyshev and elliptic filters
+const MAX_DESIGN_ORDER: usize = 20;
+
+fn validate_freq_and_order(freq: PrcFmt, order: usize, maxfreq: PrcFmt) -> Res<()> {
+    if freq <= 0.0 {
+        return Err(config::ConfigError::new("Frequency must be > 0").into());
+    } else if freq >= maxfreq {
+        return Err(config::ConfigError::new("Frequency must be < samplerate/2").into());
+    }
+    if order == 0 || order > MAX_DESIGN_ORDER {
+        let msg = format!("Order must be between 1 and {}", MAX_DESIGN_ORDER);
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    Ok(())
+}
+
+// Largest allowed boost or cut for the tilt and tone controls
+const MAX_TONE_GAIN: PrcFmt = 20.0;
+
+fn validate_tone_freq(freq: PrcFmt, maxfreq: PrcFmt) -> Res<()> {
+    if freq <= 0.0 {
+        return Err(config::ConfigError::new("Frequency must be > 0").into());
+    } else if freq >= maxfreq {
+        return Err(config::ConfigError::new("Frequency must be < samplerate/2").into());
+    }
+    Ok(())
+}
+
+fn validate_tone_gain(gain: PrcFmt) -> Res<()> {
+    if gain.abs() > MAX_TONE_GAIN {
+        let msg = format!("Gain must be within +-{} dB", MAX_TONE_GAIN);
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::biquadcombo;
     use crate::config;
+    use crate::filters::Filter;
+    use crate::iirdesign;
     use crate::PrcFmt;
 
     fn is_close(left: PrcFmt, right: PrcFmt, maxdiff: PrcFmt) -> bool {
@@ -361,6 +868,148 @@ mod tests {
         assert!(biquadcombo::validate_config(fs, &badconf4).is_err());
     }
 
+    #[test]
+    fn check_elliptic() {
+        let fs = 48000;
+        let okconf = config::BiquadComboParameters::EllipticLowpass {
+            freq: 1000.0,
+            order: 5,
+            ripple: 0.5,
+            attenuation: 60.0,
+        };
+        assert!(biquadcombo::validate_config(fs, &okconf).is_ok());
+        let badconf1 = config::BiquadComboParameters::EllipticLowpass {
+            freq: 1000.0,
+            order: 0,
+            ripple: 0.5,
+            attenuation: 60.0,
+        };
+        assert!(biquadcombo::validate_config(fs, &badconf1).is_err());
+        let badconf2 = config::BiquadComboParameters::EllipticLowpass {
+            freq: 1000.0,
+            order: 4,
+            ripple: 3.0,
+            attenuation: 2.0,
+        };
+        assert!(biquadcombo::validate_config(fs, &badconf2).is_err());
+        let badconf3 = config::BiquadComboParameters::EllipticLowpass {
+            freq: 25000.0,
+            order: 4,
+            ripple: 0.5,
+            attenuation: 60.0,
+        };
+        assert!(biquadcombo::validate_config(fs, &badconf3).is_err());
+    }
+
+    #[test]
+    fn chebyshev_highpass_response() {
+        let fs = 48000;
+        let sections = iirdesign::chebyshev1_lowpass(4, 1.0);
+        let filters = biquadcombo::BiquadCombo::make_from_prototype(fs, 1000.0, sections, true);
+        let mut waveform = vec![0.0 as PrcFmt; 4096];
+        waveform[0] = 1.0;
+        for filt in filters.iter() {
+            let mut filt = filt.clone();
+            filt.process_waveform(&mut waveform).unwrap();
+        }
+        // The DC gain of a highpass is zero, and the impulse response should decay
+        let dc: PrcFmt = waveform.iter().sum();
+        assert!(is_close(dc, 0.0, 1e-4));
+        assert!(waveform[4000].abs() < 1e-6);
+    }
+
+    #[test]
+    fn check_graphic_eq() {
+        let fs = 48000;
+        let okconf1 = config::BiquadComboParameters::GraphicEqualizer {
+            gains: vec![0.0; 10],
+            freqs: None,
+            compensate: false,
+        };
+        assert!(biquadcombo::validate_config(fs, &okconf1).is_ok());
+        let okconf2 = config::BiquadComboParameters::GraphicEqualizer {
+            gains: vec![1.0, 2.0, 3.0],
+            freqs: Some(vec![100.0, 1000.0, 10000.0]),
+            compensate: true,
+        };
+        assert!(biquadcombo::validate_config(fs, &okconf2).is_ok());
+        let badconf1 = config::BiquadComboParameters::GraphicEqualizer {
+            gains: vec![0.0; 12],
+            freqs: None,
+            compensate: false,
+        };
+        assert!(biquadcombo::validate_config(fs, &badconf1).is_err());
+        let badconf2 = config::BiquadComboParameters::GraphicEqualizer {
+            gains: vec![1.0, 2.0],
+            freqs: Some(vec![100.0, 1000.0, 10000.0]),
+            compensate: false,
+        };
+        assert!(biquadcombo::validate_config(fs, &badconf2).is_err());
+        let badconf3 = config::BiquadComboParameters::GraphicEqualizer {
+            gains: vec![1.0, 2.0, 3.0],
+            freqs: Some(vec![100.0, 1000.0, 30000.0]),
+            compensate: false,
+        };
+        assert!(biquadcombo::validate_config(fs, &badconf3).is_err());
+        let badconf4 = config::BiquadComboParameters::GraphicEqualizer {
+            gains: vec![1.0, 2.0, 3.0],
+            freqs: Some(vec![100.0, 1000.0, 23000.0]),
+            compensate: false,
+        };
+        assert!(biquadcombo::validate_config(fs, &badconf4).is_err());
+        let badconf5 = config::BiquadComboParameters::GraphicEqualizer {
+            gains: vec![0.0; 31],
+            freqs: None,
+            compensate: false,
+        };
+        assert!(biquadcombo::validate_config(32000, &badconf5).is_err());
+        assert!(biquadcombo::validate_config(44100, &badconf5).is_ok());
+        let badconf6 = config::BiquadComboParameters::GraphicEqualizer {
+            gains: vec![1.0, 2.0],
+            freqs: Some(vec![1000.0, 1000.0]),
+            compensate: false,
+        };
+        assert!(biquadcombo::validate_config(fs, &badconf6).is_err());
+        let badconf7 = config::BiquadComboParameters::GraphicEqualizer {
+            gains: vec![1.0, 2.0, 3.0],
+            freqs: Some(vec![100.0, 5000.0, 1000.0]),
+            compensate: false,
+        };
+        assert!(biquadcombo::validate_config(fs, &badconf7).is_err());
+    }
+
+    #[test]
+    fn graphic_eq_uneven_bands() {
+        let fs = 48000;
+        let gains = vec![6.0, 12.0, -12.0, 3.0];
+        let freqs = Some(vec![100.0, 1000.0, 1050.0, 10000.0]);
+        let error = |coeffs: &[crate::biquad::BiquadCoefficients]| {
+            [100.0, 1000.0, 1050.0, 10000.0]
+                .iter()
+                .zip(gains.iter())
+                .map(|(f, g)| {
+                    let response: PrcFmt = coeffs.iter().map(|c| c.gain_at(*f, fs)).sum();
+                    (response - g).abs()
+                })
+                .fold(0.0, PrcFmt::max)
+        };
+        let plain = biquadcombo::BiquadCombo::design_graphic_eq(fs, &gains, &freqs, false);
+        let compensated = biquadcombo::BiquadCombo::design_graphic_eq(fs, &gains, &freqs, true);
+        assert!(error(&compensated).is_finite());
+        assert!(error(&compensated) <= error(&plain));
+    }
+
+    #[test]
+    fn graphic_eq_compensation() {
+        let fs = 48000;
+        let gains = vec![6.0, -3.0, 4.0, 4.0, 0.0, -6.0, 2.0, 8.0, -2.0, 0.0];
+        let coeffs = biquadcombo::BiquadCombo::design_graphic_eq(fs, &gains, &None, true);
+        for (f, g) in biquadcombo::ISO_OCTAVE_BANDS.iter().zip(gains.iter()) {
+            let response: PrcFmt = coeffs.iter().map(|c| c.gain_at(*f, fs)).sum();
+            assert!(is_close(response, *g, 0.1));
+        }
+    }
+
     #[test]
     fn check_butterworth() {
         let fs = 48000;
@@ -390,4 +1039,73 @@ mod tests {
         };
         assert!(biquadcombo::validate_config(fs, &badconf4).is_err());
     }
+
+    fn filters_gain_at(filters: &[crate::biquad::Biquad], freq: PrcFmt, fs: usize) -> PrcFmt {
+        // Run a sine through the filters and measure the amplitude once settled
+        let omega = 2.0 * (std::f64::consts::PI as PrcFmt) * freq / (fs as PrcFmt);
+        let mut waveform: Vec<PrcFmt> = (0..fs).map(|n| (omega * n as PrcFmt).cos()).collect();
+        for filt in filters.iter() {
+            let mut filt = filt.clone();
+            filt.process_waveform(&mut waveform).unwrap();
+        }
+        let peak = waveform[fs / 2..]
+            .iter()
+            .fold(0.0 as PrcFmt, |acc, val| acc.max(val.abs()));
+        20.0 * peak.log10()
+    }
+
+    #[test]
+    fn tilt_response() {
+        let fs = 48000;
+        let filters = biquadcombo::BiquadCombo::make_tilt(fs, 1000.0, 6.0);
+        assert!(is_close(filters_gain_at(&filters, 1000.0, fs), 0.0, 0.05));
+        assert!(is_close(filters_gain_at(&filters, 10.0, fs), -6.0, 0.1));
+        assert!(is_close(filters_gain_at(&filters, 20000.0, fs), 6.0, 0.2));
+    }
+
+    #[test]
+    fn baxandall_response() {
+        let fs = 48000;
+        let filters = biquadcombo::BiquadCombo::make_baxandall(fs, 100.0, 6.0, 5000.0, -4.0);
+        assert!(is_close(filters_gain_at(&filters, 10.0, fs), 6.0, 0.1));
+        assert!(is_close(filters_gain_at(&filters, 100.0, fs), 3.0, 0.1));
+        assert!(is_close(filters_gain_at(&filters, 5000.0, fs), -2.0, 0.1));
+        assert!(is_close(filters_gain_at(&filters, 700.0, fs), 0.0, 0.3));
+    }
+
+    #[test]
+    fn check_tone_controls() {
+        let fs = 48000;
+        let okconf1 = config::BiquadComboParameters::Tilt {
+            freq: 1000.0,
+            gain: -3.0,
+        };
+        assert!(biquadcombo::validate_config(fs, &okconf1).is_ok());
+        let okconf2 = config::BiquadComboParameters::Baxandall {
+            bass_freq: 100.0,
+            bass_gain: 3.0,
+            treble_freq: 8000.0,
+            treble_gain: -3.0,
+        };
+        assert!(biquadcombo::validate_config(fs, &okconf2).is_ok());
+        let badconf1 = config::BiquadComboParameters::Tilt {
+            freq: 1000.0,
+            gain: 30.0,
+        };
+        assert!(biquadcombo::validate_config(fs, &badconf1).is_err());
+        let badconf2 = config::BiquadComboParameters::Baxandall {
+            bass_freq: 8000.0,
+            bass_gain: 3.0,
+            treble_freq: 100.0,
+            treble_gain: -3.0,
+        };
+        assert!(biquadcombo::validate_config(fs, &badconf2).is_err());
+        let badconf3 = config::BiquadComboParameters::Baxandall {
+            bass_freq: 100.0,
+            bass_gain: 3.0,
+            treble_freq: 30000.0,
+            treble_gain: -3.0,
+        };
+        assert!(biquadcombo::validate_config(fs, &badconf3).is_err());
+    }
 }
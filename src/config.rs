@@ -1,5 +1,6 @@
 use crate::filters;
 use crate::mixer;
+use crate::volumecontrol;
 use serde::{de, Deserialize, Serialize};
 use serde_with;
 use std::collections::HashMap;
@@ -415,6 +416,8 @@ pub enum Filter {
         parameters: ConvParameters,
     },
     Biquad {
+        #[serde(default)]
+        design: BiquadDesign,
         parameters: BiquadParameters,
     },
     BiquadCombo {
@@ -438,6 +441,18 @@ pub enum Filter {
     DiffEq {
         parameters: DiffEqParameters,
     },
+    BassEnhancer {
+        parameters: BassEnhancerParameters,
+    },
+    NoiseGate {
+        parameters: NoiseGateParameters,
+    },
+    DynamicEq {
+        parameters: DynamicEqParameters,
+    },
+    SoftClip {
+        parameters: SoftClipParameters,
+    },
 }
 
 #[allow(clippy::upper_case_acronyms)]
@@ -497,6 +512,8 @@ pub enum ConvParameters {
         filename: String,
         #[serde(default)]
         channel: usize,
+        #[serde(default)]
+        use_pipeline_channel: bool,
     },
     Values {
         values: Vec<PrcFmt>,
@@ -557,6 +574,18 @@ pub enum NotchWidth {
     Bandwidth { freq: PrcFmt, bandwidth: PrcFmt },
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum BiquadDesign {
+    Cookbook,
+    Matched,
+}
+
+impl Default for BiquadDesign {
+    fn default() -> Self {
+        BiquadDesign::Cookbook
+    }
+}
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type")]
@@ -645,6 +674,80 @@ pub enum BiquadComboParameters {
         qhs: PrcFmt,
         ghs: PrcFmt,
     },
+    BesselHighpass {
+        freq: PrcFmt,
+        order: usize,
+        #[serde(default)]
+        normalization: BesselNormalization,
+    },
+    BesselLowpass {
+        freq: PrcFmt,
+        order: usize,
+        #[serde(default)]
+        normalization: BesselNormalization,
+    },
+    Chebyshev1Highpass {
+        freq: PrcFmt,
+        order: usize,
+        ripple: PrcFmt,
+    },
+    Chebyshev1Lowpass {
+        freq: PrcFmt,
+        order: usize,
+        ripple: PrcFmt,
+    },
+    Chebyshev2Highpass {
+        freq: PrcFmt,
+        order: usize,
+        attenuation: PrcFmt,
+    },
+    Chebyshev2Lowpass {
+        freq: PrcFmt,
+        order: usize,
+        attenuation: PrcFmt,
+    },
+    EllipticHighpass {
+        freq: PrcFmt,
+        order: usize,
+        ripple: PrcFmt,
+        attenuation: PrcFmt,
+    },
+    EllipticLowpass {
+        freq: PrcFmt,
+        order: usize,
+        ripple: PrcFmt,
+        attenuation: PrcFmt,
+    },
+    GraphicEqualizer {
+        gains: Vec<PrcFmt>,
+        #[serde(default)]
+        freqs: Option<Vec<PrcFmt>>,
+        #[serde(default)]
+        compensate: bool,
+    },
+    Tilt {
+        freq: PrcFmt,
+        gain: PrcFmt,
+    },
+    Baxandall {
+        bass_freq: PrcFmt,
+        bass_gain: PrcFmt,
+        treble_freq: PrcFmt,
+        treble_gain: PrcFmt,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum BesselNormalization {
+    Phase,
+    Delay,
+    Magnitude,
+}
+
+impl Default for BesselNormalization {
+    fn default() -> Self {
+        BesselNormalization::Phase
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -652,6 +755,8 @@ pub enum BiquadComboParameters {
 pub struct VolumeParameters {
     #[serde(default = "default_ramp_time")]
     pub ramp_time: f32,
+    #[serde(default)]
+    pub fader: Option<String>,
 }
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
@@ -663,6 +768,8 @@ pub struct LoudnessParameters {
     pub high_boost: f32,
     #[serde(default = "default_loudness_boost")]
     pub low_boost: f32,
+    #[serde(default)]
+    pub fader: Option<String>,
 }
 
 fn default_loudness_boost() -> f32 {
@@ -681,6 +788,8 @@ pub struct GainParameters {
     pub inverted: bool,
     #[serde(default)]
     pub mute: bool,
+    #[serde(default = "default_parameter_ramp_time")]
+    pub ramp_time: PrcFmt,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -691,6 +800,27 @@ pub struct DelayParameters {
     pub unit: TimeUnit,
     #[serde(default)]
     pub subsample: bool,
+    #[serde(default)]
+    pub interpolation: DelayInterpolation,
+    #[serde(default = "default_parameter_ramp_time")]
+    pub ramp_time: PrcFmt,
+}
+
+fn default_parameter_ramp_time() -> PrcFmt {
+    20.0
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub enum DelayInterpolation {
+    Allpass,
+    Sinc,
+}
+
+impl Default for DelayInterpolation {
+    fn default() -> Self {
+        DelayInterpolation::Allpass
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -733,6 +863,139 @@ pub struct DiffEqParameters {
     pub b: Vec<PrcFmt>,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BassEnhancerParameters {
+    pub freq: PrcFmt,
+    #[serde(default = "default_bass_harmonics")]
+    pub harmonics: usize,
+    #[serde(default)]
+    pub gain: PrcFmt,
+}
+
+fn default_bass_harmonics() -> usize {
+    4
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct NoiseGateParameters {
+    pub threshold: PrcFmt,
+    #[serde(default = "default_gate_range")]
+    pub range: PrcFmt,
+    #[serde(default)]
+    pub ratio: Option<PrcFmt>,
+    #[serde(default = "default_gate_hysteresis")]
+    pub hysteresis: PrcFmt,
+    #[serde(default = "default_gate_attack")]
+    pub attack: PrcFmt,
+    #[serde(default = "default_gate_hold")]
+    pub hold: PrcFmt,
+    #[serde(default = "default_gate_release")]
+    pub release: PrcFmt,
+}
+
+fn default_gate_range() -> PrcFmt {
+    80.0
+}
+
+fn default_gate_hysteresis() -> PrcFmt {
+    3.0
+}
+
+fn default_gate_attack() -> PrcFmt {
+    1.0
+}
+
+fn default_gate_hold() -> PrcFmt {
+    50.0
+}
+
+fn default_gate_release() -> PrcFmt {
+    100.0
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub enum DynamicEqBand {
+    Peaking,
+    Lowshelf,
+    Highshelf,
+}
+
+impl Default for DynamicEqBand {
+    fn default() -> Self {
+        DynamicEqBand::Peaking
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DynamicEqParameters {
+    #[serde(default)]
+    pub band: DynamicEqBand,
+    pub freq: PrcFmt,
+    #[serde(default = "default_dyneq_q")]
+    pub q: PrcFmt,
+    pub threshold: PrcFmt,
+    #[serde(default = "default_dyneq_ratio")]
+    pub ratio: PrcFmt,
+    pub gain: PrcFmt,
+    #[serde(default = "default_dyneq_attack")]
+    pub attack: PrcFmt,
+    #[serde(default = "default_dyneq_release")]
+    pub release: PrcFmt,
+}
+
+fn default_dyneq_q() -> PrcFmt {
+    0.707
+}
+
+fn default_dyneq_ratio() -> PrcFmt {
+    2.0
+}
+
+fn default_dyneq_attack() -> PrcFmt {
+    10.0
+}
+
+fn default_dyneq_release() -> PrcFmt {
+    200.0
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub enum SoftClipCurve {
+    Tanh,
+    Cubic,
+    Polynomial,
+}
+
+impl Default for SoftClipCurve {
+    fn default() -> Self {
+        SoftClipCurve::Tanh
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SoftClipParameters {
+    #[serde(default)]
+    pub curve: SoftClipCurve,
+    #[serde(default = "default_softclip_knee")]
+    pub knee: PrcFmt,
+    #[serde(default = "default_softclip_oversampling")]
+    pub oversampling: usize,
+}
+
+fn default_softclip_knee() -> PrcFmt {
+    -6.0
+}
+
+fn default_softclip_oversampling() -> usize {
+    1
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct MixerChannels {
@@ -768,6 +1031,8 @@ pub struct MixerMapping {
 pub struct Mixer {
     pub channels: MixerChannels,
     pub mapping: Vec<MixerMapping>,
+    #[serde(default = "default_parameter_ramp_time")]
+    pub ramp_time: PrcFmt,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -776,6 +1041,79 @@ pub struct Mixer {
 pub enum PipelineStep {
     Mixer { name: String },
     Filter { channel: usize, names: Vec<String> },
+    Processor { name: String },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+#[serde(deny_unknown_fields)]
+pub enum Processor {
+    Crossfeed { parameters: CrossfeedParameters },
+    Upmixer { parameters: UpmixerParameters },
+    MidSide { parameters: MidSideParameters },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CrossfeedParameters {
+    #[serde(default = "default_stereo_channels")]
+    pub channels: [usize; 2],
+    #[serde(default = "default_crossfeed_freq")]
+    pub freq: PrcFmt,
+    #[serde(default = "default_crossfeed_feed")]
+    pub feed: PrcFmt,
+}
+
+fn default_stereo_channels() -> [usize; 2] {
+    [0, 1]
+}
+
+fn default_crossfeed_freq() -> PrcFmt {
+    700.0
+}
+
+fn default_crossfeed_feed() -> PrcFmt {
+    4.5
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UpmixerParameters {
+    #[serde(default = "default_stereo_channels")]
+    pub channels: [usize; 2],
+    #[serde(default)]
+    pub center: Option<usize>,
+    #[serde(default)]
+    pub surround: Option<[usize; 2]>,
+    #[serde(default)]
+    pub lfe: Option<usize>,
+    #[serde(default = "default_lfe_freq")]
+    pub lfe_freq: PrcFmt,
+}
+
+fn default_lfe_freq() -> PrcFmt {
+    120.0
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MidSideParameters {
+    #[serde(default = "default_stereo_channels")]
+    pub channels: [usize; 2],
+    #[serde(default = "default_width")]
+    pub width: PrcFmt,
+    #[serde(default)]
+    pub mid_gain: PrcFmt,
+    #[serde(default)]
+    pub side_gain: PrcFmt,
+    #[serde(default)]
+    pub mid_filters: Vec<String>,
+    #[serde(default)]
+    pub side_filters: Vec<String>,
+}
+
+fn default_width() -> PrcFmt {
+    1.0
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -788,7 +1126,54 @@ pub struct Configuration {
     #[serde(deserialize_with = "serde_with::rust::maps_duplicate_key_is_error::deserialize")]
     pub filters: HashMap<String, Filter>,
     #[serde(default)]
+    #[serde(deserialize_with = "serde_with::rust::maps_duplicate_key_is_error::deserialize")]
+    pub processors: HashMap<String, Processor>,
+    #[serde(default)]
     pub pipeline: Vec<PipelineStep>,
+    #[serde(default)]
+    pub volume: VolumeSettings,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct VolumeSettings {
+    #[serde(default = "default_min_volume")]
+    pub min_volume: f32,
+    #[serde(default = "default_max_volume")]
+    pub max_volume: f32,
+    #[serde(default)]
+    pub curve: VolumeCurve,
+}
+
+impl Default for VolumeSettings {
+    fn default() -> Self {
+        VolumeSettings {
+            min_volume: default_min_volume(),
+            max_volume: default_max_volume(),
+            curve: VolumeCurve::default(),
+        }
+    }
+}
+
+fn default_min_volume() -> f32 {
+    -120.0
+}
+
+fn default_max_volume() -> f32 {
+    20.0
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub enum VolumeCurve {
+    Linear,
+    Perceptual,
+}
+
+impl Default for VolumeCurve {
+    fn default() -> Self {
+        VolumeCurve::Linear
+    }
 }
 
 fn validate_nonzero_usize<'de, D>(d: D) -> Result<usize, D::Error>
@@ -978,6 +1363,17 @@ fn replace_tokens_in_config(config: &mut Configuration) {
             _ => {}
         }
     }
+    for (_name, processor) in config.processors.iter_mut() {
+        if let Processor::MidSide { parameters } = processor {
+            for name in parameters
+                .mid_filters
+                .iter_mut()
+                .chain(parameters.side_filters.iter_mut())
+            {
+                *name = replace_tokens(name, samplerate, num_channels);
+            }
+        }
+    }
     for mut step in config.pipeline.iter_mut() {
         match &mut step {
             PipelineStep::Filter { names, .. } => {
@@ -985,7 +1381,7 @@ fn replace_tokens_in_config(config: &mut Configuration) {
                     *name = replace_tokens(name, samplerate, num_channels);
                 }
             }
-            PipelineStep::Mixer { name } => {
+            PipelineStep::Mixer { name } | PipelineStep::Processor { name } => {
                 *name = replace_tokens(name, samplerate, num_channels);
             }
         }
@@ -1042,6 +1438,7 @@ pub enum ConfigChange {
     FilterParameters {
         filters: Vec<String>,
         mixers: Vec<String>,
+        processors: Vec<String>,
     },
     MixerParameters,
     Pipeline,
@@ -1065,11 +1462,17 @@ pub fn config_diff(currentconf: &Configuration, newconf: &Configuration) -> Conf
     if currentconf.pipeline != newconf.pipeline {
         return ConfigChange::Pipeline;
     }
-    if currentconf.mixers != newconf.mixers {
-        return ConfigChange::MixerParameters;
+    for (mixer, params) in &newconf.mixers {
+        if let Some(current_mixer) = currentconf.mixers.get(mixer) {
+            if params.channels != current_mixer.channels {
+                // The number of channels changed, need to rebuild the pipeline
+                return ConfigChange::MixerParameters;
+            }
+        }
     }
     let mut filters = Vec::<String>::new();
     let mut mixers = Vec::<String>::new();
+    let mut processors = Vec::<String>::new();
     for (filter, params) in &newconf.filters {
         // The pipeline didn't change, any added filter isn't included and can be skipped
         if let Some(current_filter) = currentconf.filters.get(filter) {
@@ -1083,7 +1486,11 @@ pub fn config_diff(currentconf: &Configuration, newconf: &Configuration) -> Conf
                 | (Filter::Dither { .. }, Filter::Dither { .. })
                 | (Filter::DiffEq { .. }, Filter::DiffEq { .. })
                 | (Filter::Volume { .. }, Filter::Volume { .. })
-                | (Filter::Loudness { .. }, Filter::Loudness { .. }) => {}
+                | (Filter::Loudness { .. }, Filter::Loudness { .. })
+                | (Filter::BassEnhancer { .. }, Filter::BassEnhancer { .. })
+                | (Filter::NoiseGate { .. }, Filter::NoiseGate { .. })
+                | (Filter::DynamicEq { .. }, Filter::DynamicEq { .. })
+                | (Filter::SoftClip { .. }, Filter::SoftClip { .. }) => {}
                 _ => {
                     // A filter changed type, need to rebuild the pipeline
                     return ConfigChange::Pipeline;
@@ -1103,7 +1510,37 @@ pub fn config_diff(currentconf: &Configuration, newconf: &Configuration) -> Conf
             }
         }
     }
-    ConfigChange::FilterParameters { filters, mixers }
+    for (processor, params) in &newconf.processors {
+        // The pipeline didn't change, any added processor isn't included and can be skipped
+        if let Some(current_processor) = currentconf.processors.get(processor) {
+            if std::mem::discriminant(params) != std::mem::discriminant(current_processor) {
+                // A processor changed type, need to rebuild the pipeline
+                return ConfigChange::Pipeline;
+            }
+            if let (
+                Processor::MidSide { parameters: new },
+                Processor::MidSide {
+                    parameters: current,
+                },
+            ) = (params, current_processor)
+            {
+                if new.mid_filters != current.mid_filters
+                    || new.side_filters != current.side_filters
+                {
+                    // The filters of a processor changed, need to rebuild the pipeline
+                    return ConfigChange::Pipeline;
+                }
+            }
+            if params != current_processor {
+                processors.push(processor.to_string());
+            }
+        }
+    }
+    ConfigChange::FilterParameters {
+        filters,
+        mixers,
+        processors,
+    }
 }
 
 /// Validate the loaded configuration, stop on errors and print a helpful message.
@@ -1131,6 +1568,7 @@ pub fn validate_config(conf: &mut Configuration, filename: Option<&str>) -> Res<
     if conf.devices.silence_timeout < 0.0 {
         return Err(ConfigError::new("silence_timeout cannot be negative").into());
     }
+    volumecontrol::validate_config(&conf.volume)?;
     #[cfg(target_os = "windows")]
     if let CaptureDevice::Wasapi { format, .. } = &conf.devices.capture {
         if *format == SampleFormat::FLOAT64LE {
@@ -1260,7 +1698,11 @@ pub fn validate_config(conf: &mut Configuration, filename: Option<&str>) -> Res<
                         let msg = format!("Use of missing filter '{}'", name);
                         return Err(ConfigError::new(&msg).into());
                     }
-                    match filters::validate_filter(fs, conf.filters.get(name).unwrap()) {
+                    let filter_conf = filters::resolve_pipeline_channel(
+                        conf.filters.get(name).unwrap().clone(),
+                        *channel,
+                    );
+                    match filters::validate_filter(fs, &filter_conf) {
                         Ok(_) => {}
                         Err(err) => {
                             let msg = format!("Invalid filter '{}'. Reason: {}", name, err);
@@ -1269,6 +1711,52 @@ pub fn validate_config(conf: &mut Configuration, filename: Option<&str>) -> Res<
                     }
                 }
             }
+            PipelineStep::Processor { name } => {
+                if !conf.processors.contains_key(name) {
+                    let msg = format!("Use of missing processor '{}'", name);
+                    return Err(ConfigError::new(&msg).into());
+                }
+                let processor_conf = conf.processors.get(name).unwrap();
+                match filters::validate_processor(fs, num_channels, processor_conf) {
+                    Ok(_) => {}
+                    Err(err) => {
+                        let msg = format!("Invalid processor '{}'. Reason: {}", name, err);
+                        return Err(ConfigError::new(&msg).into());
+                    }
+                }
+                if let Processor::MidSide { parameters } = processor_conf {
+                    // The mid filters run on the first channel of the processor, and the side filters on the second
+                    let mid_filters = parameters
+                        .mid_filters
+                        .iter()
+                        .map(|filter_name| (filter_name, parameters.channels[0]));
+                    let side_filters = parameters
+                        .side_filters
+                        .iter()
+                        .map(|filter_name| (filter_name, parameters.channels[1]));
+                    for (filter_name, channel) in mid_filters.chain(side_filters) {
+                        if !conf.filters.contains_key(filter_name) {
+                            let msg = format!(
+                                "Use of missing filter '{}' in processor '{}'",
+                                filter_name, name
+                            );
+                            return Err(ConfigError::new(&msg).into());
+                        }
+                        let filter_conf = filters::resolve_pipeline_channel(
+                            conf.filters.get(filter_name).unwrap().clone(),
+                            channel,
+                        );
+                        match filters::validate_filter(fs, &filter_conf) {
+                            Ok(_) => {}
+                            Err(err) => {
+                                let msg =
+                                    format!("Invalid filter '{}'. Reason: {}", filter_name, err);
+                                return Err(ConfigError::new(&msg).into());
+                            }
+                        }
+                    }
+                }
+            }
         }
     }
     let num_channels_out = conf.devices.playback.channels();
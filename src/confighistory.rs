@@ -0,0 +1,348 @@
+// History of applied configurations.
+// Every configuration that is applied is added to a bounded history, that remote control clients
+// can step back and forth in, or revert to a selected entry of.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config;
+use crate::Res;
+
+// Number of configurations kept in the history
+pub const DEFAULT_HISTORY_LENGTH: usize = 20;
+
+// Parameter changes that follow each other within this time are merged into one entry,
+// so that for example moving a slider doesn't fill the history
+const MERGE_TIME: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Debug)]
+struct HistoryEntry {
+    id: usize,
+    config: config::Configuration,
+    config_path: Option<String>,
+    timestamp: u64,
+    label: Option<String>,
+}
+
+/// Summary of a history entry, without the configuration itself.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct HistoryEntryInfo {
+    pub id: usize,
+    pub timestamp: u64,
+    pub label: Option<String>,
+    pub config_path: Option<String>,
+    pub current: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct ConfigHistory {
+    entries: VecDeque<HistoryEntry>,
+    // Position in entries of the entry that is active
+    current: Option<usize>,
+    // Id of the entry that is being reverted to, until it has been applied
+    pending: Option<usize>,
+    // Id of the entry that was added or updated by the last parameter change, and when that happened
+    last_parameter_change: Option<(usize, Instant)>,
+    merge_time: Duration,
+    next_id: usize,
+    max_length: usize,
+}
+
+impl Default for ConfigHistory {
+    fn default() -> Self {
+        ConfigHistory::new(DEFAULT_HISTORY_LENGTH)
+    }
+}
+
+impl ConfigHistory {
+    pub fn new(max_length: usize) -> Self {
+        ConfigHistory {
+            entries: VecDeque::with_capacity(max_length),
+            current: None,
+            pending: None,
+            last_parameter_change: None,
+            merge_time: MERGE_TIME,
+            next_id: 0,
+            max_length: max_length.max(1),
+        }
+    }
+
+    /// Record a configuration that was applied.
+    /// When the configuration is the one of a revert, undo or redo, that entry becomes the current one.
+    /// A configuration equal to the current entry is not added again.
+    /// A change of only filter or mixer parameters replaces the current entry instead of adding a new one,
+    /// if that entry was added by a parameter change shortly before and hasn't been labeled.
+    /// Otherwise any entries after the current one are dropped, like the redo steps in an editor,
+    /// and the oldest entry is removed when the history is full.
+    pub fn record(
+        &mut self,
+        config: &config::Configuration,
+        config_path: &Option<String>,
+        parameters_only: bool,
+    ) {
+        let last_parameter_change = self.last_parameter_change.take();
+        if let Some(position) = self.pending.take().and_then(|id| self.position(id)) {
+            if self.entries[position].config == *config {
+                self.entries[position].config_path = config_path.clone();
+                self.current = Some(position);
+                return;
+            }
+        }
+        if let Some(current) = self.current {
+            let is_last = current + 1 == self.entries.len();
+            let entry = &mut self.entries[current];
+            if entry.config == *config {
+                entry.config_path = config_path.clone();
+                self.last_parameter_change = last_parameter_change;
+                return;
+            }
+            let merge = matches!(last_parameter_change, Some((id, time))
+                if id == entry.id && time.elapsed() < self.merge_time);
+            if merge && parameters_only && is_last && entry.label.is_none() {
+                entry.config = config.clone();
+                entry.config_path = config_path.clone();
+                entry.timestamp = unix_time();
+                self.last_parameter_change = Some((entry.id, Instant::now()));
+                return;
+            }
+            self.entries.truncate(current + 1);
+        }
+        self.entries.push_back(HistoryEntry {
+            id: self.next_id,
+            config: config.clone(),
+            config_path: config_path.clone(),
+            timestamp: unix_time(),
+            label: None,
+        });
+        if parameters_only {
+            self.last_parameter_change = Some((self.next_id, Instant::now()));
+        }
+        self.next_id += 1;
+        while self.entries.len() > self.max_length {
+            self.entries.pop_front();
+        }
+        self.current = Some(self.entries.len() - 1);
+    }
+
+    // Find the position of the entry with the given id.
+    fn position(&self, id: usize) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.id == id)
+    }
+
+    // Find the position of the entry with the given id, or give an error if there is none.
+    fn find(&self, id: usize) -> Res<usize> {
+        match self.position(id) {
+            Some(position) => Ok(position),
+            None => {
+                let msg = format!("No config with id {} in history", id);
+                Err(config::ConfigError::new(&msg).into())
+            }
+        }
+    }
+
+    /// List the entries, oldest first.
+    pub fn list(&self) -> Vec<HistoryEntryInfo> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| HistoryEntryInfo {
+                id: entry.id,
+                timestamp: entry.timestamp,
+                label: entry.label.clone(),
+                config_path: entry.config_path.clone(),
+                current: self.current == Some(position),
+            })
+            .collect()
+    }
+
+    /// Return the configuration and config file path of the entry with the given id, so that they can be applied.
+    /// The entry becomes the current one when the configuration is recorded as applied.
+    pub fn revert(&mut self, id: usize) -> Res<(config::Configuration, Option<String>)> {
+        let position = self.find(id)?;
+        let entry = &self.entries[position];
+        self.pending = Some(entry.id);
+        Ok((entry.config.clone(), entry.config_path.clone()))
+    }
+
+    /// Step back to the entry before the current one.
+    pub fn undo(&mut self) -> Res<(config::Configuration, Option<String>)> {
+        match self.current {
+            Some(current) if current > 0 => self.revert(self.entries[current - 1].id),
+            _ => Err(config::ConfigError::new("Nothing to undo").into()),
+        }
+    }
+
+    /// Step forward to the entry after the current one.
+    pub fn redo(&mut self) -> Res<(config::Configuration, Option<String>)> {
+        match self.current {
+            Some(current) if current + 1 < self.entries.len() => {
+                self.revert(self.entries[current + 1].id)
+            }
+            _ => Err(config::ConfigError::new("Nothing to redo").into()),
+        }
+    }
+
+    /// Set or clear the label of an entry, or of the current entry if no id is given.
+    pub fn set_label(&mut self, id: Option<usize>, label: Option<String>) -> Res<()> {
+        let position = match (id, self.current) {
+            (Some(id), _) => self.find(id)?,
+            (None, Some(current)) => current,
+            (None, None) => {
+                return Err(config::ConfigError::new("The config history is empty").into())
+            }
+        };
+        self.entries[position].label = label;
+        Ok(())
+    }
+}
+
+// Get the current time in seconds since 1970-01-01 UTC.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Configuration;
+    use crate::confighistory::ConfigHistory;
+    use std::time::Duration;
+
+    fn make_config(queuelimit: usize) -> Configuration {
+        let yaml = format!(
+            "
+devices:
+  samplerate: 44100
+  chunksize: 1024
+  queuelimit: {}
+  capture:
+    type: Stdin
+    channels: 2
+    format: S16LE
+  playback:
+    type: Stdout
+    channels: 2
+    format: S16LE
+",
+            queuelimit
+        );
+        serde_yaml::from_str(&yaml).unwrap()
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut history = ConfigHistory::new(10);
+        assert!(history.undo().is_err());
+        for n in 1..=4 {
+            history.record(&make_config(n), &None, false);
+        }
+        let (conf, _) = history.undo().unwrap();
+        assert_eq!(conf.devices.queuelimit, 3);
+        history.record(&conf, &None, false);
+        let (conf, _) = history.undo().unwrap();
+        assert_eq!(conf.devices.queuelimit, 2);
+        // The current entry only changes when the config has been applied
+        assert!(history.list()[2].current);
+        // Applying the reverted config doesn't add a new entry
+        history.record(&conf, &None, false);
+        assert_eq!(history.list().len(), 4);
+        assert!(history.list()[1].current);
+        let (conf, _) = history.redo().unwrap();
+        assert_eq!(conf.devices.queuelimit, 3);
+        history.record(&conf, &None, false);
+        // A new config drops the redo steps
+        history.record(&make_config(5), &None, false);
+        assert!(history.redo().is_err());
+        let ids: Vec<usize> = history.list().iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, vec![0, 1, 2, 4]);
+        let queuelimits: Vec<usize> = ids
+            .iter()
+            .map(|id| history.revert(*id).unwrap().0.devices.queuelimit)
+            .collect();
+        assert_eq!(queuelimits, vec![1, 2, 3, 5]);
+        assert!(history.revert(3).is_err());
+    }
+
+    #[test]
+    fn failed_revert() {
+        let mut history = ConfigHistory::new(10);
+        for n in 1..=3 {
+            history.record(&make_config(n), &None, false);
+        }
+        history.revert(0).unwrap();
+        // Another config is applied instead, for example because the reverted one failed
+        history.record(&make_config(4), &None, false);
+        let entries = history.list();
+        assert_eq!(entries.len(), 4);
+        assert!(entries[3].current);
+        let (conf, _) = history.undo().unwrap();
+        assert_eq!(conf.devices.queuelimit, 3);
+    }
+
+    #[test]
+    fn bounded_length() {
+        let mut history = ConfigHistory::new(3);
+        for n in 1..=5 {
+            history.record(&make_config(n), &Some(format!("config{}.yml", n)), false);
+        }
+        let entries = history.list();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].config_path, Some("config3.yml".to_string()));
+        // The ids stay the same when old entries are removed
+        assert_eq!(entries[0].id, 2);
+        assert!(entries[2].current);
+        assert!(history.revert(0).is_err());
+        assert_eq!(
+            history.revert(3).unwrap().1,
+            Some("config4.yml".to_string())
+        );
+    }
+
+    #[test]
+    fn parameter_changes() {
+        let mut history = ConfigHistory::new(10);
+        history.record(&make_config(1), &None, false);
+        // Quick parameter changes, like moving a slider, end up in a single entry
+        for n in 2..=5 {
+            history.record(&make_config(n), &None, true);
+        }
+        assert_eq!(history.list().len(), 2);
+        let (conf, _) = history.undo().unwrap();
+        assert_eq!(conf.devices.queuelimit, 1);
+        assert_eq!(history.revert(1).unwrap().0.devices.queuelimit, 5);
+        // Other changes, and labeled entries, are never merged
+        history.record(&make_config(6), &None, false);
+        history.record(&make_config(7), &None, true);
+        history.set_label(None, Some("keep".to_string())).unwrap();
+        history.record(&make_config(8), &None, true);
+        assert_eq!(history.list().len(), 5);
+        // Parameter changes that are further apart get entries of their own
+        history.merge_time = Duration::ZERO;
+        history.record(&make_config(9), &None, true);
+        history.record(&make_config(10), &None, true);
+        assert_eq!(history.list().len(), 7);
+    }
+
+    #[test]
+    fn labels() {
+        let mut history = ConfigHistory::new(3);
+        assert!(history.set_label(None, Some("empty".to_string())).is_err());
+        history.record(&make_config(1), &None, false);
+        history.record(&make_config(2), &None, false);
+        history
+            .set_label(None, Some("more bass".to_string()))
+            .unwrap();
+        history
+            .set_label(Some(0), Some("flat".to_string()))
+            .unwrap();
+        assert!(history.set_label(Some(2), None).is_err());
+        let entries = history.list();
+        assert_eq!(entries[0].label, Some("flat".to_string()));
+        assert_eq!(entries[1].label, Some("more bass".to_string()));
+        history.set_label(Some(0), None).unwrap();
+        assert_eq!(history.list()[0].label, None);
+    }
+}
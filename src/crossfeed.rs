@@ -0,0 +1,266 @@
+// Headphone crossfeed, based on the Bauer stereophonic-to-binaural DSP (bs2b).
+// Algorithm: http://bs2b.sourceforge.net/
+
+use crate::audiodevice::AudioChunk;
+use crate::config;
+use crate::filters::Processor;
+
+// Sample format
+//type SmpFmt = i16;
+use crate::PrcFmt;
+use crate::Res;
+
+// Allowed ranges for the cut frequency and feed level, same as in libbs2b
+const MIN_FREQ: PrcFmt = 300.0;
+const MAX_FREQ: PrcFmt = 2000.0;
+const MIN_FEED: PrcFmt = 1.0;
+const MAX_FEED: PrcFmt = 15.0;
+
+#[derive(Clone, Debug)]
+struct CrossfeedCoefficients {
+    a0_lo: PrcFmt,
+    b1_lo: PrcFmt,
+    a0_hi: PrcFmt,
+    a1_hi: PrcFmt,
+    b1_hi: PrcFmt,
+}
+
+impl CrossfeedCoefficients {
+    /// Calculate the coefficients for the lowpass of the crossfed signal,
+    /// and for the high shelf of the direct signal.
+    /// The levels are normalized to give unity gain for a mono signal.
+    fn new(samplerate: usize, freq: PrcFmt, feed: PrcFmt) -> Self {
+        let pi = std::f64::consts::PI as PrcFmt;
+        let base: PrcFmt = 10.0;
+        let gain_lo_db = feed * -5.0 / 6.0 - 3.0;
+        let gain_hi_db = feed / 6.0 - 3.0;
+        let gain_lo = base.powf(gain_lo_db / 20.0);
+        let gain_hi = 1.0 - base.powf(gain_hi_db / 20.0);
+        let freq_hi = freq * (2.0 as PrcFmt).powf((gain_lo_db - 20.0 * gain_hi.log10()) / 12.0);
+        let gain = 1.0 / (1.0 - gain_hi + gain_lo);
+
+        let x_lo = (-2.0 * pi * freq / samplerate as PrcFmt).exp();
+        let x_hi = (-2.0 * pi * freq_hi / samplerate as PrcFmt).exp();
+        CrossfeedCoefficients {
+            a0_lo: gain_lo * (1.0 - x_lo) * gain,
+            b1_lo: x_lo,
+            a0_hi: (1.0 - gain_hi * (1.0 - x_hi)) * gain,
+            a1_hi: -x_hi * gain,
+            b1_hi: x_hi,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct CrossfeedState {
+    lo: PrcFmt,
+    hi: PrcFmt,
+    prev_input: PrcFmt,
+}
+
+impl CrossfeedState {
+    /// Flush stored subnormal numbers to zero.
+    fn flush_subnormals(&mut self) {
+        if self.lo.is_subnormal() {
+            self.lo = 0.0;
+        }
+        if self.hi.is_subnormal() {
+            self.hi = 0.0;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Crossfeed {
+    pub name: String,
+    samplerate: usize,
+    channels: [usize; 2],
+    coeffs: CrossfeedCoefficients,
+    states: [CrossfeedState; 2],
+}
+
+impl Crossfeed {
+    pub fn from_config(
+        name: String,
+        samplerate: usize,
+        parameters: config::CrossfeedParameters,
+    ) -> Self {
+        let coeffs = CrossfeedCoefficients::new(samplerate, parameters.freq, parameters.feed);
+        Crossfeed {
+            name,
+            samplerate,
+            channels: parameters.channels,
+            coeffs,
+            states: [CrossfeedState::default(), CrossfeedState::default()],
+        }
+    }
+
+    /// Process a single sample of one channel, returns the direct and the crossfed parts.
+    fn process_single(
+        coeffs: &CrossfeedCoefficients,
+        state: &mut CrossfeedState,
+        input: PrcFmt,
+    ) -> (PrcFmt, PrcFmt) {
+        state.lo = coeffs.a0_lo * input + coeffs.b1_lo * state.lo;
+        state.hi = coeffs.a0_hi * input + coeffs.a1_hi * state.prev_input + coeffs.b1_hi * state.hi;
+        state.prev_input = input;
+        (state.hi, state.lo)
+    }
+}
+
+impl Processor for Crossfeed {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn process_chunk(&mut self, chunk: &mut AudioChunk) -> Res<()> {
+        let [chan_l, chan_r] = self.channels;
+        if chunk.waveforms[chan_l].is_empty() && chunk.waveforms[chan_r].is_empty() {
+            return Ok(());
+        }
+        // An unused channel still receives the crossfeed from the other one
+        for chan in [chan_l, chan_r] {
+            if chunk.waveforms[chan].is_empty() {
+                chunk.waveforms[chan] = vec![0.0; chunk.frames];
+            }
+        }
+        let mut left = std::mem::take(&mut chunk.waveforms[chan_l]);
+        let mut right = std::mem::take(&mut chunk.waveforms[chan_r]);
+        let [state_l, state_r] = &mut self.states;
+        for (value_l, value_r) in left.iter_mut().zip(right.iter_mut()) {
+            let (direct_l, cross_l) = Crossfeed::process_single(&self.coeffs, state_l, *value_l);
+            let (direct_r, cross_r) = Crossfeed::process_single(&self.coeffs, state_r, *value_r);
+            *value_l = direct_l + cross_r;
+            *value_r = direct_r + cross_l;
+        }
+        state_l.flush_subnormals();
+        state_r.flush_subnormals();
+        chunk.waveforms[chan_l] = left;
+        chunk.waveforms[chan_r] = right;
+        Ok(())
+    }
+
+    fn update_parameters(&mut self, conf: config::Processor) {
+        if let config::Processor::Crossfeed { parameters } = conf {
+            self.coeffs =
+                CrossfeedCoefficients::new(self.samplerate, parameters.freq, parameters.feed);
+            if self.channels != parameters.channels {
+                self.channels = parameters.channels;
+                self.states = [CrossfeedState::default(), CrossfeedState::default()];
+            }
+        } else {
+            // This should never happen unless there is a bug somewhere else
+            panic!("Invalid config change!");
+        }
+    }
+}
+
+/// Validate a Crossfeed config.
+pub fn validate_config(
+    samplerate: usize,
+    num_channels: usize,
+    conf: &config::CrossfeedParameters,
+) -> Res<()> {
+    if conf.channels.iter().any(|chan| *chan >= num_channels) {
+        let msg = format!(
+            "Invalid channels {:?}, max is {}",
+            conf.channels,
+            num_channels - 1
+        );
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    if conf.channels[0] == conf.channels[1] {
+        return Err(config::ConfigError::new("The two channels must be different").into());
+    }
+    if conf.freq < MIN_FREQ || conf.freq > MAX_FREQ {
+        let msg = format!("Frequency must be between {} and {} Hz", MIN_FREQ, MAX_FREQ);
+        return Err(config::ConfigError::new(&msg).into());
+    } else if conf.freq >= samplerate as PrcFmt / 2.0 {
+        return Err(config::ConfigError::new("Frequency must be < samplerate/2").into());
+    }
+    if conf.feed < MIN_FEED || conf.feed > MAX_FEED {
+        let msg = format!("Feed must be between {} and {} dB", MIN_FEED, MAX_FEED);
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audiodevice::AudioChunk;
+    use crate::config;
+    use crate::crossfeed;
+    use crate::filters::Processor;
+    use crate::PrcFmt;
+
+    fn is_close(left: PrcFmt, right: PrcFmt, maxdiff: PrcFmt) -> bool {
+        println!("{} - {}", left, right);
+        (left - right).abs() < maxdiff
+    }
+
+    fn make_crossfeed(feed: PrcFmt) -> crossfeed::Crossfeed {
+        let conf = config::CrossfeedParameters {
+            channels: [0, 1],
+            freq: 700.0,
+            feed,
+        };
+        crossfeed::Crossfeed::from_config("test".to_string(), 44100, conf)
+    }
+
+    #[test]
+    fn crossfeed_mono_unity_gain() {
+        let mut cf = make_crossfeed(4.5);
+        let frames = 4096;
+        let mut chunk = AudioChunk::new(
+            vec![vec![0.5; frames], vec![0.5; frames]],
+            1.0,
+            -1.0,
+            frames,
+            frames,
+        );
+        cf.process_chunk(&mut chunk).unwrap();
+        assert!(is_close(chunk.waveforms[0][frames - 1], 0.5, 1e-6));
+        assert!(is_close(chunk.waveforms[1][frames - 1], 0.5, 1e-6));
+    }
+
+    #[test]
+    fn crossfeed_feed_level() {
+        let mut cf = make_crossfeed(6.0);
+        let frames = 4096;
+        let mut chunk = AudioChunk::new(vec![vec![1.0; frames], vec![]], 1.0, -1.0, frames, frames);
+        cf.process_chunk(&mut chunk).unwrap();
+        // At low frequencies, the crossfed signal is 'feed' dB below the direct one
+        let direct = chunk.waveforms[0][frames - 1];
+        let crossed = chunk.waveforms[1][frames - 1];
+        assert!(is_close(20.0 * (direct / crossed).log10(), 6.0, 1e-4));
+    }
+
+    #[test]
+    fn check_crossfeed() {
+        let okconf = config::CrossfeedParameters {
+            channels: [2, 3],
+            freq: 700.0,
+            feed: 4.5,
+        };
+        assert!(crossfeed::validate_config(44100, 4, &okconf).is_ok());
+        assert!(crossfeed::validate_config(44100, 2, &okconf).is_err());
+        let badconf1 = config::CrossfeedParameters {
+            channels: [0, 0],
+            freq: 700.0,
+            feed: 4.5,
+        };
+        assert!(crossfeed::validate_config(44100, 2, &badconf1).is_err());
+        let badconf2 = config::CrossfeedParameters {
+            channels: [0, 1],
+            freq: 3000.0,
+            feed: 4.5,
+        };
+        assert!(crossfeed::validate_config(44100, 2, &badconf2).is_err());
+        let badconf3 = config::CrossfeedParameters {
+            channels: [0, 1],
+            freq: 700.0,
+            feed: 20.0,
+        };
+        assert!(crossfeed::validate_config(44100, 2, &badconf3).is_err());
+    }
+}
@@ -0,0 +1,321 @@
+// Dynamic equalizer band.
+// A peaking or shelving filter with a gain that depends on the level in a band-limited detector.
+// A negative gain cuts the band when the level rises above the threshold,
+// and a positive gain boosts the band when the level falls below the threshold.
+
+use crate::biquad;
+use crate::config;
+use crate::filters::Filter;
+use crate::helpers::time_constant;
+
+// Sample format
+use crate::PrcFmt;
+use crate::Res;
+
+// Number of samples between updates of the gain stage coefficients
+const UPDATE_INTERVAL: usize = 32;
+
+// Smallest gain change in dB that triggers new coefficients
+const GAIN_STEP: PrcFmt = 0.01;
+
+// Largest allowed gain in dB
+const MAX_GAIN: PrcFmt = 40.0;
+
+pub struct DynamicEq {
+    pub name: String,
+    samplerate: usize,
+    parameters: config::DynamicEqParameters,
+    detector: biquad::Biquad,
+    filter: biquad::Biquad,
+    attack: PrcFmt,
+    release: PrcFmt,
+    envelope: PrcFmt,
+    gain: PrcFmt,
+    current_gain: PrcFmt,
+    buffer: Vec<PrcFmt>,
+}
+
+/// Get the coefficients of the detector, that picks out the band controlled by the filter.
+fn detector_coefficients(
+    samplerate: usize,
+    parameters: &config::DynamicEqParameters,
+) -> biquad::BiquadCoefficients {
+    let freq = parameters.freq;
+    let q = parameters.q;
+    let filtconf = match parameters.band {
+        config::DynamicEqBand::Peaking => {
+            config::BiquadParameters::Bandpass(config::NotchWidth::Q { freq, q })
+        }
+        config::DynamicEqBand::Lowshelf => config::BiquadParameters::Lowpass { freq, q },
+        config::DynamicEqBand::Highshelf => config::BiquadParameters::Highpass { freq, q },
+    };
+    biquad::BiquadCoefficients::from_config(samplerate, filtconf)
+}
+
+/// Get the coefficients of the gain stage for a given gain.
+fn filter_coefficients(
+    samplerate: usize,
+    parameters: &config::DynamicEqParameters,
+    gain: PrcFmt,
+) -> biquad::BiquadCoefficients {
+    let freq = parameters.freq;
+    let q = parameters.q;
+    let filtconf = match parameters.band {
+        config::DynamicEqBand::Peaking => {
+            config::BiquadParameters::Peaking(config::PeakingWidth::Q { freq, q, gain })
+        }
+        config::DynamicEqBand::Lowshelf => {
+            config::BiquadParameters::Lowshelf(config::ShelfSteepness::Q { freq, q, gain })
+        }
+        config::DynamicEqBand::Highshelf => {
+            config::BiquadParameters::Highshelf(config::ShelfSteepness::Q { freq, q, gain })
+        }
+    };
+    biquad::BiquadCoefficients::from_config(samplerate, filtconf)
+}
+
+impl DynamicEq {
+    pub fn from_config(
+        name: String,
+        samplerate: usize,
+        parameters: config::DynamicEqParameters,
+    ) -> Self {
+        let detector = biquad::Biquad::new(
+            "".to_string(),
+            samplerate,
+            detector_coefficients(samplerate, &parameters),
+        );
+        let filter = biquad::Biquad::new(
+            "".to_string(),
+            samplerate,
+            filter_coefficients(samplerate, &parameters, 0.0),
+        );
+        let mut dyneq = DynamicEq {
+            name,
+            samplerate,
+            parameters: parameters.clone(),
+            detector,
+            filter,
+            attack: 0.0,
+            release: 0.0,
+            envelope: 0.0,
+            gain: 0.0,
+            current_gain: 0.0,
+            buffer: Vec::new(),
+        };
+        dyneq.set_parameters(parameters);
+        dyneq
+    }
+
+    fn set_parameters(&mut self, parameters: config::DynamicEqParameters) {
+        self.detector
+            .set_coefficients(detector_coefficients(self.samplerate, &parameters));
+        self.filter.set_coefficients(filter_coefficients(
+            self.samplerate,
+            &parameters,
+            self.current_gain,
+        ));
+        self.attack = time_constant(parameters.attack, self.samplerate);
+        self.release = time_constant(parameters.release, self.samplerate);
+        self.parameters = parameters;
+    }
+
+    /// Get the gain in dB of the band for a given detector level in dB.
+    /// Above the threshold, a cut increases with the level.
+    /// Below the threshold, a boost increases as the level decreases.
+    /// The gain is limited to the range between 0 and the configured gain.
+    fn gain_for_level(&self, level_db: PrcFmt) -> PrcFmt {
+        let slope = 1.0 - 1.0 / self.parameters.ratio;
+        let gain = self.parameters.gain;
+        if gain < 0.0 {
+            let overshoot = (level_db - self.parameters.threshold).max(0.0);
+            (-overshoot * slope).max(gain)
+        } else {
+            let undershoot = (self.parameters.threshold - level_db).max(0.0);
+            (undershoot * slope).min(gain)
+        }
+    }
+
+    /// Follow the level of the detector output, and smooth the resulting gain.
+    /// The level is a peak follower that decays with the release time.
+    /// The gain moves with the attack time when the level rises, and with the release time when it falls.
+    fn update_gain(&mut self, detected: &[PrcFmt]) {
+        for value in detected.iter() {
+            self.envelope = value.abs().max(self.envelope * self.release);
+            let level_db = 20.0 * (self.envelope + 1.0e-30).log10();
+            let target = self.gain_for_level(level_db);
+            let coeff = if target < self.gain {
+                self.attack
+            } else {
+                self.release
+            };
+            self.gain = target + coeff * (self.gain - target);
+        }
+    }
+}
+
+impl Filter for DynamicEq {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn process_waveform(&mut self, waveform: &mut [PrcFmt]) -> Res<()> {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(waveform);
+        let mut detected = std::mem::take(&mut self.buffer);
+        self.detector.process_waveform(&mut detected)?;
+        for (wave_chunk, det_chunk) in waveform
+            .chunks_mut(UPDATE_INTERVAL)
+            .zip(detected.chunks(UPDATE_INTERVAL))
+        {
+            self.update_gain(det_chunk);
+            if (self.gain - self.current_gain).abs() > GAIN_STEP {
+                self.current_gain = self.gain;
+                self.filter.set_coefficients(filter_coefficients(
+                    self.samplerate,
+                    &self.parameters,
+                    self.gain,
+                ));
+            }
+            self.filter.process_waveform(wave_chunk)?;
+        }
+        self.buffer = detected;
+        Ok(())
+    }
+
+    fn update_parameters(&mut self, conf: config::Filter) {
+        if let config::Filter::DynamicEq { parameters } = conf {
+            self.set_parameters(parameters);
+        } else {
+            // This should never happen unless there is a bug somewhere else
+            panic!("Invalid config change!");
+        }
+    }
+}
+
+/// Validate a DynamicEq config.
+pub fn validate_config(samplerate: usize, conf: &config::DynamicEqParameters) -> Res<()> {
+    if conf.freq <= 0.0 {
+        return Err(config::ConfigError::new("Frequency must be > 0").into());
+    } else if conf.freq >= samplerate as PrcFmt / 2.0 {
+        return Err(config::ConfigError::new("Frequency must be < samplerate/2").into());
+    }
+    if conf.q <= 0.0 {
+        return Err(config::ConfigError::new("Q must be > 0").into());
+    }
+    if conf.threshold > 0.0 {
+        return Err(config::ConfigError::new("Threshold must be less than 0 dB").into());
+    } else if conf.threshold < -150.0 {
+        return Err(config::ConfigError::new("Threshold must be higher than -150 dB").into());
+    }
+    if conf.ratio <= 1.0 {
+        return Err(config::ConfigError::new("Ratio must be larger than 1").into());
+    }
+    if conf.gain.abs() > MAX_GAIN {
+        let msg = format!("Gain must be between -{} and +{} dB", MAX_GAIN, MAX_GAIN);
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    if conf.attack < 0.0 || conf.release < 0.0 {
+        return Err(config::ConfigError::new("Attack and release times cannot be negative").into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config;
+    use crate::dynamiceq;
+    use crate::filters::Filter;
+    use crate::PrcFmt;
+
+    fn is_close(left: PrcFmt, right: PrcFmt, maxdiff: PrcFmt) -> bool {
+        println!("{} - {}", left, right);
+        (left - right).abs() < maxdiff
+    }
+
+    fn make_conf(band: config::DynamicEqBand, gain: PrcFmt) -> config::DynamicEqParameters {
+        config::DynamicEqParameters {
+            band,
+            freq: 100.0,
+            q: 2.0,
+            threshold: -30.0,
+            ratio: 2.0,
+            gain,
+            attack: 5.0,
+            release: 200.0,
+        }
+    }
+
+    // Process one second of a sine and return the output gain in dB, measured over the last 0.1 s
+    fn sine_gain(conf: config::DynamicEqParameters, freq: PrcFmt, level_db: PrcFmt) -> PrcFmt {
+        let fs = 48000;
+        let mut dyneq = dynamiceq::DynamicEq::from_config("test".to_string(), fs, conf);
+        let ampl = (10.0 as PrcFmt).powf(level_db / 20.0);
+        let omega = 2.0 * (std::f64::consts::PI as PrcFmt) * freq / fs as PrcFmt;
+        let mut waveform: Vec<PrcFmt> = (0..fs)
+            .map(|n| ampl * (omega * n as PrcFmt).sin())
+            .collect();
+        dyneq.process_waveform(&mut waveform).unwrap();
+        let peak = waveform[fs - fs / 10..]
+            .iter()
+            .fold(0.0 as PrcFmt, |acc, value| acc.max(value.abs()));
+        20.0 * (peak / ampl).log10()
+    }
+
+    #[test]
+    fn cut_above_threshold() {
+        // Quiet signals are left alone
+        let quiet = sine_gain(
+            make_conf(config::DynamicEqBand::Peaking, -12.0),
+            100.0,
+            -40.0,
+        );
+        assert!(is_close(quiet, 0.0, 0.1));
+        // 10 dB over the threshold with ratio 2 gives 5 dB of cut
+        let loud = sine_gain(
+            make_conf(config::DynamicEqBand::Peaking, -12.0),
+            100.0,
+            -20.0,
+        );
+        assert!(is_close(loud, -5.0, 0.5));
+        // The cut is limited by the gain
+        let louder = sine_gain(make_conf(config::DynamicEqBand::Peaking, -3.0), 100.0, -6.0);
+        assert!(is_close(louder, -3.0, 0.5));
+    }
+
+    #[test]
+    fn boost_below_threshold() {
+        let mut conf = make_conf(config::DynamicEqBand::Lowshelf, 6.0);
+        conf.q = 0.707;
+        let quiet = sine_gain(conf.clone(), 30.0, -50.0);
+        assert!(is_close(quiet, 6.0, 0.5));
+        let loud = sine_gain(conf, 30.0, -10.0);
+        assert!(is_close(loud, 0.0, 0.1));
+    }
+
+    #[test]
+    fn outside_band_unchanged() {
+        let gain = sine_gain(
+            make_conf(config::DynamicEqBand::Peaking, -12.0),
+            5000.0,
+            -6.0,
+        );
+        assert!(is_close(gain, 0.0, 0.1));
+    }
+
+    #[test]
+    fn check_dynamiceq() {
+        let fs = 48000;
+        let okconf = make_conf(config::DynamicEqBand::Peaking, -6.0);
+        assert!(dynamiceq::validate_config(fs, &okconf).is_ok());
+        let mut badconf = okconf.clone();
+        badconf.ratio = 1.0;
+        assert!(dynamiceq::validate_config(fs, &badconf).is_err());
+        let mut badconf = okconf.clone();
+        badconf.freq = 30000.0;
+        assert!(dynamiceq::validate_config(fs, &badconf).is_err());
+        let mut badconf = okconf;
+        badconf.gain = 50.0;
+        assert!(dynamiceq::validate_config(fs, &badconf).is_err());
+    }
+}
@@ -87,9 +87,9 @@ impl FftConv {
                 skip_bytes_lines,
             } => filters::read_coeff_file(&filename, &format, read_bytes_lines, skip_bytes_lines)
                 .unwrap(),
-            config::ConvParameters::Wav { filename, channel } => {
-                filters::read_wav(&filename, channel).unwrap()
-            }
+            config::ConvParameters::Wav {
+                filename, channel, ..
+            } => filters::read_wav(&filename, channel).unwrap(),
         };
         FftConv::new(name, data_length, &values)
     }
@@ -171,9 +171,9 @@ impl Filter for FftConv {
                     filters::read_coeff_file(&filename, &format, read_bytes_lines, skip_bytes_lines)
                         .unwrap()
                 }
-                config::ConvParameters::Wav { filename, channel } => {
-                    filters::read_wav(&filename, channel).unwrap()
-                }
+                config::ConvParameters::Wav {
+                    filename, channel, ..
+                } => filters::read_wav(&filename, channel).unwrap(),
             };
 
             let nsegments = ((coeffs.len() as PrcFmt) / (self.npoints as PrcFmt)).ceil() as usize;
@@ -227,7 +227,9 @@ pub fn validate_config(conf: &config::ConvParameters) -> Res<()> {
             }
             Ok(())
         }
-        config::ConvParameters::Wav { filename, channel } => {
+        config::ConvParameters::Wav {
+            filename, channel, ..
+        } => {
             let coeffs = filters::read_wav(filename, *channel)?;
             if coeffs.is_empty() {
                 return Err(config::ConfigError::new("Conv coefficients are empty").into());
@@ -1,23 +1,30 @@
 use crate::audiodevice::AudioChunk;
 use crate::basicfilters;
+use crate::bassenhancer;
 use crate::biquad;
 use crate::biquadcombo;
 use crate::config;
 use crate::conversions;
+use crate::crossfeed;
 use crate::diffeq;
 use crate::dither;
+use crate::dynamiceq;
 #[cfg(not(feature = "FFTW"))]
 use crate::fftconv;
 #[cfg(feature = "FFTW")]
 use crate::fftconv_fftw as fftconv;
 use crate::loudness;
+use crate::midside;
 use crate::mixer;
+use crate::noisegate;
+use crate::softclip;
+use crate::upmixer;
 use rawsample::SampleReader;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fs::File;
 use std::io::BufReader;
-use std::io::{BufRead, Read, Seek, SeekFrom};
+use std::io::{BufRead, Cursor, Read, Seek, SeekFrom};
 use std::sync::{Arc, RwLock};
 
 use crate::PrcFmt;
@@ -65,13 +72,34 @@ const SUBTYPE_PCM: Guid = Guid {
     data4: [128, 0, 0, 170, 0, 56, 155, 113],
 };
 
+/// Wave64 chunk id of the file header
+const W64_GUID_RIFF: [u8; 16] = [
+    0x72, 0x69, 0x66, 0x66, 0x2E, 0x91, 0xCF, 0x11, 0xA5, 0xD6, 0x28, 0xDB, 0x04, 0xC1, 0x00, 0x00,
+];
+
+/// Wave64 "wave" format id
+const W64_GUID_WAVE: [u8; 16] = [
+    0x77, 0x61, 0x76, 0x65, 0xF3, 0xAC, 0xD3, 0x11, 0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A,
+];
+
+/// Wave64 "fmt " chunk id
+const W64_GUID_FMT: [u8; 16] = [
+    0x66, 0x6D, 0x74, 0x20, 0xF3, 0xAC, 0xD3, 0x11, 0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A,
+];
+
+/// Wave64 "data" chunk id
+const W64_GUID_DATA: [u8; 16] = [
+    0x64, 0x61, 0x74, 0x61, 0xF3, 0xAC, 0xD3, 0x11, 0x8C, 0xD1, 0x00, 0xC0, 0x4F, 0x8E, 0xDB, 0x8A,
+];
+
 #[derive(Debug)]
 pub struct WavParams {
-    sample_format: config::FileFormat,
-    sample_rate: usize,
-    data_offset: usize,
-    data_length: usize,
-    channels: usize,
+    pub sample_format: config::FileFormat,
+    pub sample_rate: usize,
+    pub data_offset: usize,
+    pub data_length: usize,
+    pub channels: usize,
+    pub big_endian: bool,
 }
 
 pub trait Filter {
@@ -83,6 +111,23 @@ pub trait Filter {
     fn name(&self) -> String;
 }
 
+pub trait Processor {
+    // Process a chunk, with access to all channels
+    fn process_chunk(&mut self, chunk: &mut AudioChunk) -> Res<()>;
+
+    fn update_parameters(&mut self, config: config::Processor);
+
+    // Update the parameters of the filters used by the processor, if any
+    fn update_filter_parameters(
+        &mut self,
+        _filterconfigs: HashMap<String, config::Filter>,
+        _changed: Vec<String>,
+    ) {
+    }
+
+    fn name(&self) -> String;
+}
+
 pub fn pad_vector(values: &[PrcFmt], length: usize) -> Vec<PrcFmt> {
     let new_len = if values.len() > length {
         values.len()
@@ -175,6 +220,86 @@ pub fn read_coeff_file(
     Ok(coefficients)
 }
 
+/// Parse the contents of a wav "fmt " chunk.
+/// Returns the sample format, number of channels and sample rate.
+fn parse_wav_fmt_chunk(data: &[u8], filename: &str) -> Res<(config::FileFormat, u16, u32)> {
+    let chunk_length = data.len();
+    let formatcode = u16::from_le_bytes(data[0..2].try_into().unwrap());
+    let channels = u16::from_le_bytes(data[2..4].try_into().unwrap());
+    let sample_rate = u32::from_le_bytes(data[4..8].try_into().unwrap());
+    let bytes_per_frame = u16::from_le_bytes(data[12..14].try_into().unwrap());
+    let bits = u16::from_le_bytes(data[14..16].try_into().unwrap());
+    if channels == 0 {
+        let msg = format!("Invalid number of channels in wav file '{}'", filename);
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    let bytes_per_sample = bytes_per_frame / channels;
+    let sample_format = match (formatcode, bits, bytes_per_sample) {
+        (1, 16, 2) => config::FileFormat::S16LE,
+        (1, 24, 3) => config::FileFormat::S24LE3,
+        (1, 24, 4) => config::FileFormat::S24LE,
+        (1, 32, 4) => config::FileFormat::S32LE,
+        (3, 32, 4) => config::FileFormat::FLOAT32LE,
+        (3, 64, 8) => config::FileFormat::FLOAT64LE,
+        (0xFFFE, _, _) => {
+            // waveformatex
+            if chunk_length != 40 {
+                let msg = format!("Invalid extended header of wav file '{}'", filename);
+                return Err(config::ConfigError::new(&msg).into());
+            }
+            let cb_size = u16::from_le_bytes(data[16..18].try_into().unwrap());
+            let valid_bits_per_sample = u16::from_le_bytes(data[18..20].try_into().unwrap());
+            let channel_mask = u32::from_le_bytes(data[20..24].try_into().unwrap());
+            let subformat = &data[24..40];
+            let subformat_guid = Guid::from_slice(subformat.try_into().unwrap());
+            trace!(
+                "Found extended wav fmt chunk: subformatcode: {:?}, cb_size: {}, channel_mask: {}, valid bits per sample: {}",
+                subformat_guid, cb_size, channel_mask, valid_bits_per_sample
+            );
+            match (
+                subformat_guid,
+                bits,
+                bytes_per_sample,
+                valid_bits_per_sample,
+            ) {
+                (SUBTYPE_PCM, 16, 2, 16) => config::FileFormat::S16LE,
+                (SUBTYPE_PCM, 24, 3, 24) => config::FileFormat::S24LE3,
+                (SUBTYPE_PCM, 24, 4, 24) => config::FileFormat::S24LE,
+                (SUBTYPE_PCM, 32, 4, 32) => config::FileFormat::S32LE,
+                (SUBTYPE_FLOAT, 32, 4, 32) => config::FileFormat::FLOAT32LE,
+                (SUBTYPE_FLOAT, 64, 8, 64) => config::FileFormat::FLOAT64LE,
+                (_, _, _, _) => {
+                    let msg = format!("Unsupported extended wav format of file '{}'", filename);
+                    return Err(config::ConfigError::new(&msg).into());
+                }
+            }
+        }
+        (_, _, _) => {
+            let msg = format!("Unsupported wav format of file '{}'", filename);
+            return Err(config::ConfigError::new(&msg).into());
+        }
+    };
+    trace!(
+        "Found wav fmt chunk: formatcode: {}, channels: {}, samplerate: {}, bits: {}, bytes_per_frame: {}",
+        formatcode, channels, sample_rate, bits, bytes_per_frame
+    );
+    Ok((sample_format, channels, sample_rate))
+}
+
+/// Convert an 80-bit IEEE 754 extended precision float, as used for the sample rate in aiff files.
+fn extended_to_f64(data: &[u8]) -> f64 {
+    let sign = if data[0] & 0x80 > 0 { -1.0 } else { 1.0 };
+    let exponent = (u16::from_be_bytes(data[0..2].try_into().unwrap()) & 0x7FFF) as i32;
+    let mantissa = u64::from_be_bytes(data[2..10].try_into().unwrap());
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    sign * mantissa as f64 * 2.0_f64.powi(exponent - 16383 - 63)
+}
+
+/// Read the header of a coefficient file and find the location and format of the sample data.
+/// Supported file types are RIFF wav (including extensible), RF64/BW64, Sony Wave64 and AIFF/AIFC.
+/// The type is detected from the header of the file.
 pub fn find_data_in_wav(filename: &str) -> Res<WavParams> {
     let f = File::open(filename)?;
     let filesize = f.metadata()?.len();
@@ -182,21 +307,30 @@ pub fn find_data_in_wav(filename: &str) -> Res<WavParams> {
     let mut header = [0; 12];
     let _ = file.read(&mut header)?;
 
-    let riff_b = "RIFF".as_bytes();
-    let wave_b = "WAVE".as_bytes();
-    let data_b = "data".as_bytes();
-    let fmt_b = "fmt ".as_bytes();
-    let riff_err = header.iter().take(4).zip(riff_b).any(|(a, b)| *a != *b);
-    let wave_err = header
-        .iter()
-        .skip(8)
-        .take(4)
-        .zip(wave_b)
-        .any(|(a, b)| *a != *b);
-    if riff_err || wave_err {
+    match &header[0..4] {
+        b"RIFF" | b"RF64" | b"BW64" => find_data_in_riff(&mut file, &header, filesize, filename),
+        b"riff" => find_data_in_w64(&mut file, filesize, filename),
+        b"FORM" => find_data_in_aiff(&mut file, &header, filesize, filename),
+        _ => {
+            let msg = format!("Invalid wav header in file '{}'", filename);
+            Err(config::ConfigError::new(&msg).into())
+        }
+    }
+}
+
+/// Parse a RIFF wav file. Also handles RF64 and BW64, where the sizes of large chunks
+/// are given in a separate "ds64" chunk.
+fn find_data_in_riff(
+    file: &mut BufReader<&File>,
+    header: &[u8; 12],
+    filesize: u64,
+    filename: &str,
+) -> Res<WavParams> {
+    if &header[8..12] != b"WAVE" {
         let msg = format!("Invalid wav header in file '{}'", filename);
         return Err(config::ConfigError::new(&msg).into());
     }
+    let is_rf64 = &header[0..4] != b"RIFF";
     let mut next_chunk_location = 12;
     let mut found_fmt = false;
     let mut found_data = false;
@@ -207,86 +341,55 @@ pub fn find_data_in_wav(filename: &str) -> Res<WavParams> {
     let mut channels = 0;
     let mut data_offset = 0;
     let mut data_length = 0;
+    let mut ds64_data_length = None;
 
     while (!found_fmt || !found_data) && next_chunk_location < filesize {
         file.seek(SeekFrom::Start(next_chunk_location))?;
         let _ = file.read(&mut buffer)?;
         let chunk_length = u32::from_le_bytes(buffer[4..8].try_into().unwrap());
+        let mut chunk_size = chunk_length as u64;
         trace!("Analyzing wav chunk of length: {}", chunk_length);
-        let is_data = buffer.iter().take(4).zip(data_b).all(|(a, b)| *a == *b);
-        let is_fmt = buffer.iter().take(4).zip(fmt_b).all(|(a, b)| *a == *b);
-        if is_fmt && (chunk_length == 16 || chunk_length == 18 || chunk_length == 40) {
+        let chunk_id = &buffer[0..4];
+        if chunk_id == b"fmt " && (chunk_length == 16 || chunk_length == 18 || chunk_length == 40) {
             found_fmt = true;
             let mut data = vec![0; chunk_length as usize];
-            let _ = file.read(&mut data).unwrap();
-            let formatcode = u16::from_le_bytes(data[0..2].try_into().unwrap());
-            channels = u16::from_le_bytes(data[2..4].try_into().unwrap());
-            sample_rate = u32::from_le_bytes(data[4..8].try_into().unwrap());
-            let bytes_per_frame = u16::from_le_bytes(data[12..14].try_into().unwrap());
-            let bits = u16::from_le_bytes(data[14..16].try_into().unwrap());
-            let bytes_per_sample = bytes_per_frame / channels;
-            sample_format = match (formatcode, bits, bytes_per_sample) {
-                (1, 16, 2) => config::FileFormat::S16LE,
-                (1, 24, 3) => config::FileFormat::S24LE3,
-                (1, 24, 4) => config::FileFormat::S24LE,
-                (1, 32, 4) => config::FileFormat::S32LE,
-                (3, 32, 4) => config::FileFormat::FLOAT32LE,
-                (3, 64, 8) => config::FileFormat::FLOAT64LE,
-                (0xFFFE, _, _) => {
-                    // waveformatex
-                    if chunk_length != 40 {
-                        let msg = format!("Invalid extended header of wav file '{}'", filename);
-                        return Err(config::ConfigError::new(&msg).into());
-                    }
-                    let cb_size = u16::from_le_bytes(data[16..18].try_into().unwrap());
-                    let valid_bits_per_sample =
-                        u16::from_le_bytes(data[18..20].try_into().unwrap());
-                    let channel_mask = u32::from_le_bytes(data[20..24].try_into().unwrap());
-                    let subformat = &data[24..40];
-                    let subformat_guid = Guid::from_slice(subformat.try_into().unwrap());
-                    trace!(
-                        "Found extended wav fmt chunk: subformatcode: {:?}, cb_size: {}, channel_mask: {}, valid bits per sample: {}",
-                        subformat_guid, cb_size, channel_mask, valid_bits_per_sample
-                    );
-                    match (
-                        subformat_guid,
-                        bits,
-                        bytes_per_sample,
-                        valid_bits_per_sample,
-                    ) {
-                        (SUBTYPE_PCM, 16, 2, 16) => config::FileFormat::S16LE,
-                        (SUBTYPE_PCM, 24, 3, 24) => config::FileFormat::S24LE3,
-                        (SUBTYPE_PCM, 24, 4, 24) => config::FileFormat::S24LE,
-                        (SUBTYPE_PCM, 32, 4, 32) => config::FileFormat::S32LE,
-                        (SUBTYPE_FLOAT, 32, 4, 32) => config::FileFormat::FLOAT32LE,
-                        (SUBTYPE_FLOAT, 64, 8, 64) => config::FileFormat::FLOAT64LE,
-                        (_, _, _, _) => {
-                            let msg =
-                                format!("Unsupported extended wav format of file '{}'", filename);
-                            return Err(config::ConfigError::new(&msg).into());
-                        }
-                    }
-                }
-                (_, _, _) => {
-                    let msg = format!("Unsupported wav format of file '{}'", filename);
-                    return Err(config::ConfigError::new(&msg).into());
-                }
-            };
+            file.read_exact(&mut data)?;
+            let fmt = parse_wav_fmt_chunk(&data, filename)?;
+            sample_format = fmt.0;
+            channels = fmt.1;
+            sample_rate = fmt.2;
+        } else if chunk_id == b"ds64" && is_rf64 {
+            let mut data = [0; 16];
+            file.read_exact(&mut data)?;
+            let riff_size = u64::from_le_bytes(data[0..8].try_into().unwrap());
+            let size = u64::from_le_bytes(data[8..16].try_into().unwrap());
             trace!(
-                "Found wav fmt chunk: formatcode: {}, channels: {}, samplerate: {}, bits: {}, bytes_per_frame: {}",
-                formatcode, channels, sample_rate, bits, bytes_per_frame
+                "Found ds64 chunk, riff size: {}, data size: {}",
+                riff_size,
+                size
             );
-        } else if is_data {
+            ds64_data_length = Some(size);
+        } else if chunk_id == b"data" {
             found_data = true;
             data_offset = next_chunk_location + 8;
-            data_length = chunk_length;
+            if is_rf64 && chunk_length == u32::MAX {
+                match ds64_data_length {
+                    Some(length) => chunk_size = length,
+                    None => {
+                        let msg = format!("Missing ds64 chunk in file '{}'", filename);
+                        return Err(config::ConfigError::new(&msg).into());
+                    }
+                }
+            }
+            data_length = chunk_size;
             trace!(
                 "Found wav data chunk, start: {}, length: {}",
                 data_offset,
                 data_length
             )
         }
-        next_chunk_location += 8 + chunk_length as u64;
+        // chunks are padded to an even number of bytes
+        next_chunk_location += 8 + chunk_size + chunk_size % 2;
     }
     if found_data && found_fmt {
         trace!("Wav file with parameters: format: {:?},  samplerate: {}, channels: {}, data_length: {}, data_offset: {}", sample_format, sample_rate, channels, data_length, data_offset);
@@ -296,12 +399,205 @@ pub fn find_data_in_wav(filename: &str) -> Res<WavParams> {
             channels: channels as usize,
             data_length: data_length as usize,
             data_offset: data_offset as usize,
+            big_endian: false,
         });
     }
     let msg = format!("Unable to parse wav file '{}'", filename);
     Err(config::ConfigError::new(&msg).into())
 }
 
+/// Parse a Sony Wave64 file. This uses the same fmt chunk as RIFF wav,
+/// but with GUIDs as chunk ids and 64-bit chunk sizes.
+fn find_data_in_w64(file: &mut BufReader<&File>, filesize: u64, filename: &str) -> Res<WavParams> {
+    let mut header = [0; 40];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+    if header[0..16] != W64_GUID_RIFF || header[24..40] != W64_GUID_WAVE {
+        let msg = format!("Invalid Wave64 header in file '{}'", filename);
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    let mut next_chunk_location = 40;
+    let mut buffer = [0; 24];
+    let mut fmt = None;
+    let mut data = None;
+
+    while (fmt.is_none() || data.is_none()) && next_chunk_location < filesize {
+        file.seek(SeekFrom::Start(next_chunk_location))?;
+        file.read_exact(&mut buffer)?;
+        // The chunk size includes the 24 bytes of the chunk header
+        let chunk_size = u64::from_le_bytes(buffer[16..24].try_into().unwrap());
+        trace!("Analyzing Wave64 chunk of size: {}", chunk_size);
+        if chunk_size < 24 {
+            let msg = format!("Invalid chunk size in Wave64 file '{}'", filename);
+            return Err(config::ConfigError::new(&msg).into());
+        }
+        let length = chunk_size - 24;
+        if buffer[0..16] == W64_GUID_FMT && (length == 16 || length == 18 || length == 40) {
+            let mut fmt_data = vec![0; length as usize];
+            file.read_exact(&mut fmt_data)?;
+            fmt = Some(parse_wav_fmt_chunk(&fmt_data, filename)?);
+        } else if buffer[0..16] == W64_GUID_DATA {
+            trace!(
+                "Found Wave64 data chunk, start: {}, length: {}",
+                next_chunk_location + 24,
+                length
+            );
+            data = Some((next_chunk_location + 24, length));
+        }
+        // chunks are aligned to 8 bytes
+        next_chunk_location += (chunk_size + 7) & !7;
+    }
+    if let (Some((sample_format, channels, sample_rate)), Some((data_offset, data_length))) =
+        (fmt, data)
+    {
+        trace!("Wave64 file with parameters: format: {:?},  samplerate: {}, channels: {}, data_length: {}, data_offset: {}", sample_format, sample_rate, channels, data_length, data_offset);
+        return Ok(WavParams {
+            sample_format,
+            sample_rate: sample_rate as usize,
+            channels: channels as usize,
+            data_length: data_length as usize,
+            data_offset: data_offset as usize,
+            big_endian: false,
+        });
+    }
+    let msg = format!("Unable to parse Wave64 file '{}'", filename);
+    Err(config::ConfigError::new(&msg).into())
+}
+
+/// Parse an AIFF or AIFC file. Uncompressed AIFF data is big-endian,
+/// while AIFC can also contain little-endian ("sowt") and float data.
+fn find_data_in_aiff(
+    file: &mut BufReader<&File>,
+    header: &[u8; 12],
+    filesize: u64,
+    filename: &str,
+) -> Res<WavParams> {
+    let is_aifc = match &header[8..12] {
+        b"AIFF" => false,
+        b"AIFC" => true,
+        _ => {
+            let msg = format!("Invalid aiff header in file '{}'", filename);
+            return Err(config::ConfigError::new(&msg).into());
+        }
+    };
+    let mut next_chunk_location = 12;
+    let mut buffer = [0; 8];
+    let mut comm = None;
+    let mut data = None;
+
+    while (comm.is_none() || data.is_none()) && next_chunk_location < filesize {
+        file.seek(SeekFrom::Start(next_chunk_location))?;
+        file.read_exact(&mut buffer)?;
+        let chunk_length = u32::from_be_bytes(buffer[4..8].try_into().unwrap()) as u64;
+        trace!("Analyzing aiff chunk of length: {}", chunk_length);
+        let chunk_id = &buffer[0..4];
+        if chunk_id == b"COMM" && chunk_length >= 18 {
+            if next_chunk_location + 8 + chunk_length > filesize {
+                let msg = format!("Invalid COMM chunk length in aiff file '{}'", filename);
+                return Err(config::ConfigError::new(&msg).into());
+            }
+            let mut comm_data = vec![0; chunk_length as usize];
+            file.read_exact(&mut comm_data)?;
+            let channels = u16::from_be_bytes(comm_data[0..2].try_into().unwrap());
+            if channels == 0 {
+                let msg = format!("Invalid number of channels in aiff file '{}'", filename);
+                return Err(config::ConfigError::new(&msg).into());
+            }
+            let bits = u16::from_be_bytes(comm_data[6..8].try_into().unwrap());
+            let sample_rate = extended_to_f64(&comm_data[8..18]).round() as usize;
+            let compression: &[u8] = if is_aifc && comm_data.len() >= 22 {
+                &comm_data[18..22]
+            } else {
+                b"NONE"
+            };
+            trace!(
+                "Found aiff COMM chunk: channels: {}, samplerate: {}, bits: {}, compression: {}",
+                channels,
+                sample_rate,
+                bits,
+                String::from_utf8_lossy(compression)
+            );
+            let (sample_format, big_endian) = match (compression, bits) {
+                (b"NONE", 9..=16) => (config::FileFormat::S16LE, true),
+                (b"NONE", 17..=24) => (config::FileFormat::S24LE3, true),
+                (b"NONE", 25..=32) => (config::FileFormat::S32LE, true),
+                (b"sowt", 9..=16) => (config::FileFormat::S16LE, false),
+                (b"sowt", 17..=24) => (config::FileFormat::S24LE3, false),
+                (b"sowt", 25..=32) => (config::FileFormat::S32LE, false),
+                (b"fl32", 32) | (b"FL32", 32) => (config::FileFormat::FLOAT32LE, true),
+                (b"fl64", 64) | (b"FL64", 64) => (config::FileFormat::FLOAT64LE, true),
+                (_, _) => {
+                    let msg = format!("Unsupported aiff format of file '{}'", filename);
+                    return Err(config::ConfigError::new(&msg).into());
+                }
+            };
+            comm = Some((sample_format, big_endian, channels, sample_rate));
+        } else if chunk_id == b"SSND" && chunk_length >= 8 {
+            let mut ssnd_header = [0; 8];
+            file.read_exact(&mut ssnd_header)?;
+            let offset = u32::from_be_bytes(ssnd_header[0..4].try_into().unwrap()) as u64;
+            let data_offset = next_chunk_location + 16 + offset;
+            let data_length = chunk_length.saturating_sub(8 + offset);
+            trace!(
+                "Found aiff SSND chunk, start: {}, length: {}",
+                data_offset,
+                data_length
+            );
+            data = Some((data_offset, data_length));
+        }
+        // chunks are padded to an even number of bytes
+        next_chunk_location += 8 + chunk_length + chunk_length % 2;
+    }
+    if let (
+        Some((sample_format, big_endian, channels, sample_rate)),
+        Some((data_offset, data_length)),
+    ) = (comm, data)
+    {
+        trace!("Aiff file with parameters: format: {:?}, big endian: {}, samplerate: {}, channels: {}, data_length: {}, data_offset: {}", sample_format, big_endian, sample_rate, channels, data_length, data_offset);
+        return Ok(WavParams {
+            sample_format,
+            sample_rate,
+            channels: channels as usize,
+            data_length: data_length as usize,
+            data_offset: data_offset as usize,
+            big_endian,
+        });
+    }
+    let msg = format!("Unable to parse aiff file '{}'", filename);
+    Err(config::ConfigError::new(&msg).into())
+}
+
+/// Read big-endian sample data by swapping the byte order of each sample
+/// and then decoding it as the corresponding little-endian format.
+fn read_big_endian_data(
+    filename: &str,
+    format: &config::FileFormat,
+    read_bytes: usize,
+    skip_bytes: usize,
+) -> Res<Vec<PrcFmt>> {
+    let mut file = BufReader::new(File::open(filename)?);
+    file.seek(SeekFrom::Start(skip_bytes as u64))?;
+    let mut rawdata = Vec::with_capacity(read_bytes);
+    file.take(read_bytes as u64).read_to_end(&mut rawdata)?;
+    for sample in rawdata.chunks_exact_mut(format.bytes_per_sample()) {
+        sample.reverse();
+    }
+    let rawformat = conversions::map_file_formats(format);
+    let mut cursor = Cursor::new(rawdata);
+    let mut coefficients = Vec::<PrcFmt>::new();
+    let mut nextvalue = vec![0.0; 1];
+    while let Ok(1) = PrcFmt::read_samples(&mut cursor, &mut nextvalue, &rawformat) {
+        coefficients.push(nextvalue[0]);
+    }
+    debug!(
+        "Read big-endian data from: '{}', format: {:?}, number of coeffs: {}",
+        filename,
+        format,
+        coefficients.len()
+    );
+    Ok(coefficients)
+}
+
 pub fn read_wav(filename: &str, channel: usize) -> Res<Vec<PrcFmt>> {
     let params = find_data_in_wav(filename)?;
     if channel >= params.channels {
@@ -312,12 +608,21 @@ pub fn read_wav(filename: &str, channel: usize) -> Res<Vec<PrcFmt>> {
         return Err(config::ConfigError::new(&msg).into());
     }
 
-    let alldata = read_coeff_file(
-        filename,
-        &params.sample_format,
-        params.data_length,
-        params.data_offset,
-    )?;
+    let alldata = if params.big_endian {
+        read_big_endian_data(
+            filename,
+            &params.sample_format,
+            params.data_length,
+            params.data_offset,
+        )?
+    } else {
+        read_coeff_file(
+            filename,
+            &params.sample_format,
+            params.data_length,
+            params.data_offset,
+        )?
+    };
 
     let data = alldata
         .iter()
@@ -355,7 +660,7 @@ impl FilterGroup {
         debug!("Build from config");
         let mut filters = Vec::<Box<dyn Filter>>::new();
         for name in names {
-            let filter_cfg = filter_configs[&name].clone();
+            let filter_cfg = resolve_pipeline_channel(filter_configs[&name].clone(), channel);
             let filter: Box<dyn Filter> =
                 match filter_cfg {
                     config::Filter::Conv { parameters } => Box::new(fftconv::FftConv::from_config(
@@ -363,10 +668,14 @@ impl FilterGroup {
                         waveform_length,
                         parameters,
                     )),
-                    config::Filter::Biquad { parameters } => Box::new(biquad::Biquad::new(
+                    config::Filter::Biquad { design, parameters } => Box::new(biquad::Biquad::new(
                         name,
                         sample_freq,
-                        biquad::BiquadCoefficients::from_config(sample_freq, parameters),
+                        biquad::BiquadCoefficients::from_config_with_design(
+                            sample_freq,
+                            &design,
+                            parameters,
+                        ),
                     )),
                     config::Filter::BiquadCombo { parameters } => Box::new(
                         biquadcombo::BiquadCombo::from_config(name, sample_freq, parameters),
@@ -374,9 +683,9 @@ impl FilterGroup {
                     config::Filter::Delay { parameters } => Box::new(
                         basicfilters::Delay::from_config(name, sample_freq, parameters),
                     ),
-                    config::Filter::Gain { parameters } => {
-                        Box::new(basicfilters::Gain::from_config(name, parameters))
-                    }
+                    config::Filter::Gain { parameters } => Box::new(
+                        basicfilters::Gain::from_config(name, sample_freq, parameters),
+                    ),
                     config::Filter::Volume { parameters } => {
                         Box::new(basicfilters::Volume::from_config(
                             name,
@@ -401,6 +710,18 @@ impl FilterGroup {
                     config::Filter::DiffEq { parameters } => {
                         Box::new(diffeq::DiffEq::from_config(name, parameters))
                     }
+                    config::Filter::NoiseGate { parameters } => Box::new(
+                        noisegate::NoiseGate::from_config(name, sample_freq, parameters),
+                    ),
+                    config::Filter::BassEnhancer { parameters } => Box::new(
+                        bassenhancer::BassEnhancer::from_config(name, sample_freq, parameters),
+                    ),
+                    config::Filter::DynamicEq { parameters } => Box::new(
+                        dynamiceq::DynamicEq::from_config(name, sample_freq, parameters),
+                    ),
+                    config::Filter::SoftClip { parameters } => {
+                        Box::new(softclip::SoftClip::from_config(name, parameters))
+                    }
                 };
             filters.push(filter);
         }
@@ -414,11 +735,22 @@ impl FilterGroup {
     ) {
         for filter in &mut self.filters {
             if changed.iter().any(|n| n == &filter.name()) {
-                filter.update_parameters(filterconfigs[&filter.name()].clone());
+                filter.update_parameters(resolve_pipeline_channel(
+                    filterconfigs[&filter.name()].clone(),
+                    self.channel,
+                ));
             }
         }
     }
 
+    /// Apply all the filters to a single waveform.
+    pub fn process_waveform(&mut self, waveform: &mut [PrcFmt]) -> Res<()> {
+        for filter in &mut self.filters {
+            filter.process_waveform(waveform)?;
+        }
+        Ok(())
+    }
+
     /// Apply all the filters to an AudioChunk.
     fn process_chunk(&mut self, input: &mut AudioChunk) -> Res<()> {
         if !input.waveforms[self.channel].is_empty() {
@@ -431,10 +763,11 @@ impl FilterGroup {
 }
 
 /// A Pipeline is made up of a series of PipelineSteps,
-/// each one can be a single Mixer of a group of Filters
+/// each one can be a single Mixer, a group of Filters or a Processor
 pub enum PipelineStep {
     MixerStep(mixer::Mixer),
     FilterStep(FilterGroup),
+    ProcessorStep(Box<dyn Processor>),
 }
 
 pub struct Pipeline {
@@ -453,7 +786,7 @@ impl Pipeline {
             match step {
                 config::PipelineStep::Mixer { name } => {
                     let mixconf = conf.mixers[&name].clone();
-                    let mixer = mixer::Mixer::from_config(name, mixconf);
+                    let mixer = mixer::Mixer::from_config(name, mixconf, conf.devices.samplerate);
                     steps.push(PipelineStep::MixerStep(mixer));
                 }
                 config::PipelineStep::Filter { channel, names } => {
@@ -467,6 +800,36 @@ impl Pipeline {
                     );
                     steps.push(PipelineStep::FilterStep(fltgrp));
                 }
+                config::PipelineStep::Processor { name } => {
+                    let procconf = conf.processors[&name].clone();
+                    let processor: Box<dyn Processor> = match procconf {
+                        config::Processor::Crossfeed { parameters } => {
+                            Box::new(crossfeed::Crossfeed::from_config(
+                                name,
+                                conf.devices.samplerate,
+                                parameters,
+                            ))
+                        }
+                        config::Processor::MidSide { parameters } => {
+                            Box::new(midside::MidSide::from_config(
+                                name,
+                                parameters,
+                                conf.filters.clone(),
+                                conf.devices.chunksize,
+                                conf.devices.samplerate,
+                                processing_status.clone(),
+                            ))
+                        }
+                        config::Processor::Upmixer { parameters } => {
+                            Box::new(upmixer::Upmixer::from_config(
+                                name,
+                                conf.devices.samplerate,
+                                parameters,
+                            ))
+                        }
+                    };
+                    steps.push(PipelineStep::ProcessorStep(processor));
+                }
             }
         }
         Pipeline { steps }
@@ -477,6 +840,7 @@ impl Pipeline {
         conf: config::Configuration,
         filters: Vec<String>,
         mixers: Vec<String>,
+        processors: Vec<String>,
     ) {
         debug!("Updating parameters");
         for mut step in &mut self.steps {
@@ -489,11 +853,17 @@ impl Pipeline {
                 PipelineStep::FilterStep(flt) => {
                     flt.update_parameters(conf.filters.clone(), filters.clone());
                 }
+                PipelineStep::ProcessorStep(processor) => {
+                    if processors.iter().any(|n| n == &processor.name()) {
+                        processor.update_parameters(conf.processors[&processor.name()].clone());
+                    }
+                    processor.update_filter_parameters(conf.filters.clone(), filters.clone());
+                }
             }
         }
     }
 
-    /// Process an AudioChunk by calling either a MixerStep or a FilterStep
+    /// Process an AudioChunk by calling either a MixerStep, a FilterStep or a ProcessorStep
     pub fn process_chunk(&mut self, mut chunk: AudioChunk) -> AudioChunk {
         for mut step in &mut self.steps {
             match &mut step {
@@ -503,31 +873,85 @@ impl Pipeline {
                 PipelineStep::FilterStep(flt) => {
                     flt.process_chunk(&mut chunk).unwrap();
                 }
+                PipelineStep::ProcessorStep(processor) => {
+                    processor.process_chunk(&mut chunk).unwrap();
+                }
             }
         }
         chunk
     }
 }
 
+/// Set the channel to read for Wav convolution filters that take their channel
+/// from the pipeline. Other filter configs are returned unchanged.
+pub fn resolve_pipeline_channel(filter_config: config::Filter, channel: usize) -> config::Filter {
+    match filter_config {
+        config::Filter::Conv {
+            parameters:
+                config::ConvParameters::Wav {
+                    filename,
+                    use_pipeline_channel: true,
+                    ..
+                },
+        } => config::Filter::Conv {
+            parameters: config::ConvParameters::Wav {
+                filename,
+                channel,
+                use_pipeline_channel: true,
+            },
+        },
+        other => other,
+    }
+}
+
 /// Validate the filter config, to give a helpful message intead of a panic.
 pub fn validate_filter(fs: usize, filter_config: &config::Filter) -> Res<()> {
     match filter_config {
         config::Filter::Conv { parameters } => fftconv::validate_config(parameters),
-        config::Filter::Biquad { parameters } => biquad::validate_config(fs, parameters),
+        config::Filter::Biquad { design, parameters } => {
+            biquad::validate_config(fs, parameters)?;
+            biquad::validate_design(design, parameters)
+        }
         config::Filter::Delay { parameters } => basicfilters::validate_delay_config(parameters),
         config::Filter::Gain { parameters } => basicfilters::validate_gain_config(parameters),
         config::Filter::Dither { parameters } => dither::validate_config(parameters),
         config::Filter::DiffEq { parameters } => diffeq::validate_config(parameters),
+        config::Filter::BassEnhancer { parameters } => {
+            bassenhancer::validate_config(fs, parameters)
+        }
+        config::Filter::NoiseGate { parameters } => noisegate::validate_config(parameters),
+        config::Filter::DynamicEq { parameters } => dynamiceq::validate_config(fs, parameters),
+        config::Filter::SoftClip { parameters } => softclip::validate_config(parameters),
         config::Filter::Volume { parameters } => basicfilters::validate_volume_config(parameters),
         config::Filter::Loudness { parameters } => loudness::validate_config(parameters),
         config::Filter::BiquadCombo { parameters } => biquadcombo::validate_config(fs, parameters),
     }
 }
 
+/// Validate the processor config, to give a helpful message intead of a panic.
+pub fn validate_processor(
+    fs: usize,
+    num_channels: usize,
+    processor_config: &config::Processor,
+) -> Res<()> {
+    match processor_config {
+        config::Processor::Crossfeed { parameters } => {
+            crossfeed::validate_config(fs, num_channels, parameters)
+        }
+        config::Processor::Upmixer { parameters } => {
+            upmixer::validate_config(fs, num_channels, parameters)
+        }
+        config::Processor::MidSide { parameters } => {
+            midside::validate_config(num_channels, parameters)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::config;
     use crate::config::FileFormat;
-    use crate::filters::{find_data_in_wav, read_wav};
+    use crate::filters::{find_data_in_wav, read_wav, resolve_pipeline_channel};
     use crate::filters::{pad_vector, read_coeff_file};
     use crate::PrcFmt;
 
@@ -719,4 +1143,83 @@ mod tests {
         let bad = read_wav("testdata/int32.wav", 1);
         assert!(bad.is_err());
     }
+
+    #[test]
+    pub fn test_resolve_pipeline_channel() {
+        let conf = config::Filter::Conv {
+            parameters: config::ConvParameters::Wav {
+                filename: "testdata/int32.wav".to_string(),
+                channel: 0,
+                use_pipeline_channel: true,
+            },
+        };
+        let resolved = resolve_pipeline_channel(conf, 3);
+        if let config::Filter::Conv {
+            parameters: config::ConvParameters::Wav { channel, .. },
+        } = resolved
+        {
+            assert_eq!(channel, 3);
+        } else {
+            panic!("Unexpected filter config");
+        }
+        let fixed = config::Filter::Conv {
+            parameters: config::ConvParameters::Wav {
+                filename: "testdata/int32.wav".to_string(),
+                channel: 0,
+                use_pipeline_channel: false,
+            },
+        };
+        let resolved = resolve_pipeline_channel(fixed.clone(), 3);
+        assert_eq!(resolved, fixed);
+    }
+
+    #[test]
+    pub fn test_analyze_aiff() {
+        let info = find_data_in_wav("testdata/int32.aiff").unwrap();
+        println!("{:?}", info);
+        assert_eq!(info.sample_format, FileFormat::S32LE);
+        assert!(info.big_endian);
+        assert_eq!(info.sample_rate, 44100);
+        assert_eq!(info.data_offset, 54);
+        assert_eq!(info.data_length, 20);
+        assert_eq!(info.channels, 1);
+    }
+
+    #[test]
+    pub fn test_read_aiff() {
+        let values = read_wav("testdata/int32.aiff", 0).unwrap();
+        println!("{:?}", values);
+        let expected: Vec<PrcFmt> = vec![-1.0, -0.5, 0.0, 0.5, 1.0];
+        assert!(compare_waveforms(&values, &expected, 1e-9));
+    }
+
+    #[test]
+    pub fn test_invalid_aiff() {
+        let info = find_data_in_wav("testdata/int16_mono.aiff").unwrap();
+        assert_eq!(info.channels, 1);
+        assert!(find_data_in_wav("testdata/no_channels.aiff").is_err());
+        assert!(find_data_in_wav("testdata/long_comm.aiff").is_err());
+    }
+
+    #[test]
+    pub fn test_read_w64() {
+        let info = find_data_in_wav("testdata/float32.w64").unwrap();
+        assert_eq!(info.sample_format, FileFormat::FLOAT32LE);
+        assert_eq!(info.data_offset, 104);
+        assert_eq!(info.data_length, 20);
+        let values = read_wav("testdata/float32.w64", 0).unwrap();
+        let expected: Vec<PrcFmt> = vec![-1.0, -0.5, 0.0, 0.5, 1.0];
+        assert!(compare_waveforms(&values, &expected, 1e-15));
+    }
+
+    #[test]
+    pub fn test_read_rf64() {
+        let info = find_data_in_wav("testdata/int32.rf64").unwrap();
+        assert_eq!(info.sample_format, FileFormat::S32LE);
+        assert_eq!(info.data_offset, 80);
+        assert_eq!(info.data_length, 20);
+        let values = read_wav("testdata/int32.rf64", 0).unwrap();
+        let expected: Vec<PrcFmt> = vec![-1.0, -0.5, 0.0, 0.5, 1.0];
+        assert!(compare_waveforms(&values, &expected, 1e-9));
+    }
 }
@@ -68,3 +68,12 @@ pub fn multiply_add_elements(
         *r += val;
     }
 }
+
+// Coefficient of a one-pole smoothing filter with the given time constant in ms,
+// as used for the attack and release of level detectors.
+pub fn time_constant(time_ms: PrcFmt, samplerate: usize) -> PrcFmt {
+    if time_ms <= 0.0 {
+        return 0.0;
+    }
+    (-1000.0 / (time_ms * samplerate as PrcFmt)).exp()
+}
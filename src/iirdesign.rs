@@ -0,0 +1,435 @@
+// Design of Bessel, Chebyshev and elliptic filters.
+// The analog prototypes are decomposed into first and second order sections,
+// that are then transformed to biquads with the bilinear transform.
+// Elliptic filter design follows Sophocles J. Orfanidis,
+// "Lecture Notes on Elliptic Filter Design", 2006.
+
+use crate::biquad::BiquadCoefficients;
+use crate::config::BesselNormalization;
+use crate::PrcFmt;
+use num_complex::Complex;
+use std::f64::consts::PI;
+
+// The filters are always designed with 64-bit precision
+type DesignFmt = f64;
+type C64 = Complex<DesignFmt>;
+
+/// A first or second order analog section, given as polynomials in s.
+/// The coefficients are ordered as s^0, s^1, s^2.
+/// A first order section has zero s^2 coefficients.
+#[derive(Clone, Copy, Debug)]
+pub struct AnalogSection {
+    pub num: [f64; 3],
+    pub den: [f64; 3],
+}
+
+impl AnalogSection {
+    /// Second order lowpass section from a complex pole,
+    /// with an optional pair of zeros on the imaginary axis at +-j*zero.
+    /// The gain at DC is normalized to one.
+    fn from_pole_pair(pole: C64, zero: Option<f64>) -> Self {
+        let den = [pole.norm_sqr(), -2.0 * pole.re, 1.0];
+        let num = match zero {
+            Some(z) => {
+                let scale = den[0] / (z * z);
+                [z * z * scale, 0.0, scale]
+            }
+            None => [den[0], 0.0, 0.0],
+        };
+        AnalogSection { num, den }
+    }
+
+    /// First order lowpass section from a real pole, normalized to unity gain at DC.
+    fn from_real_pole(pole: f64) -> Self {
+        AnalogSection {
+            num: [-pole, 0.0, 0.0],
+            den: [-pole, 1.0, 0.0],
+        }
+    }
+
+    fn is_first_order(&self) -> bool {
+        self.num[2] == 0.0 && self.den[2] == 0.0
+    }
+
+    /// Lowpass to highpass transformation, by substituting s with 1/s.
+    pub fn to_highpass(self) -> Self {
+        if self.is_first_order() {
+            AnalogSection {
+                num: [self.num[1], self.num[0], 0.0],
+                den: [self.den[1], self.den[0], 0.0],
+            }
+        } else {
+            AnalogSection {
+                num: [self.num[2], self.num[1], self.num[0]],
+                den: [self.den[2], self.den[1], self.den[0]],
+            }
+        }
+    }
+
+    /// Magnitude of the response at angular frequency w.
+    pub fn magnitude_at(&self, w: f64) -> f64 {
+        let s = C64::new(0.0, w);
+        let num = self.num[0] + self.num[1] * s + self.num[2] * s * s;
+        let den = self.den[0] + self.den[1] * s + self.den[2] * s * s;
+        (num / den).norm()
+    }
+
+    /// Transform to a digital biquad with the bilinear transform,
+    /// with the frequency 1 rad/s of the prototype mapped to `freq`.
+    pub fn to_biquad(self, freq: PrcFmt, fs: usize) -> BiquadCoefficients {
+        let c = 1.0 / (PI * freq as DesignFmt / fs as DesignFmt).tan();
+        let (b, a) = if self.is_first_order() {
+            let b = [
+                self.num[0] + self.num[1] * c,
+                self.num[0] - self.num[1] * c,
+                0.0,
+            ];
+            let a = [
+                self.den[0] + self.den[1] * c,
+                self.den[0] - self.den[1] * c,
+                0.0,
+            ];
+            (b, a)
+        } else {
+            let transform = |p: &[f64; 3]| {
+                [
+                    p[0] + p[1] * c + p[2] * c * c,
+                    2.0 * (p[0] - p[2] * c * c),
+                    p[0] - p[1] * c + p[2] * c * c,
+                ]
+            };
+            (transform(&self.num), transform(&self.den))
+        };
+        BiquadCoefficients::normalize(
+            a[0] as PrcFmt,
+            a[1] as PrcFmt,
+            a[2] as PrcFmt,
+            b[0] as PrcFmt,
+            b[1] as PrcFmt,
+            b[2] as PrcFmt,
+        )
+    }
+}
+
+/// Build lowpass sections from a set of poles and optional imaginary zeros.
+/// Only poles with positive imaginary part, and real poles, are used.
+/// Zeros are given as the magnitudes of the upper half plane zeros, paired with the poles in order.
+fn make_sections(poles: &[C64], zeros: &[f64], gain: f64) -> Vec<AnalogSection> {
+    let mut sections = Vec::new();
+    let mut pair_idx = 0;
+    for pole in poles.iter() {
+        if pole.im > 1.0e-9 * pole.norm() {
+            let zero = zeros.get(pair_idx).copied();
+            sections.push(AnalogSection::from_pole_pair(*pole, zero));
+            pair_idx += 1;
+        } else if pole.im.abs() <= 1.0e-9 * pole.norm() {
+            sections.push(AnalogSection::from_real_pole(pole.re));
+        }
+    }
+    if let Some(first) = sections.first_mut() {
+        for coeff in first.num.iter_mut() {
+            *coeff *= gain;
+        }
+    }
+    sections
+}
+
+fn factorial(n: usize) -> f64 {
+    (1..=n).fold(1.0, |acc, k| acc * k as f64)
+}
+
+/// Find the roots of a monic polynomial with the Durand-Kerner method.
+/// The coefficients are given in ascending order.
+fn polynomial_roots(coeffs: &[f64]) -> Vec<C64> {
+    let order = coeffs.len() - 1;
+    let eval = |x: C64| {
+        coeffs
+            .iter()
+            .rev()
+            .fold(C64::new(0.0, 0.0), |acc, c| acc * x + c)
+    };
+    let radius = coeffs[0].abs().powf(1.0 / order as f64).max(1.0);
+    let mut roots: Vec<C64> = (0..order)
+        .map(|n| C64::from_polar(radius, 2.0 * PI * n as f64 / order as f64 + 0.4))
+        .collect();
+    for _ in 0..1000 {
+        let mut max_change: f64 = 0.0;
+        for n in 0..order {
+            let mut den = C64::new(1.0, 0.0);
+            for m in 0..order {
+                if m != n {
+                    den *= roots[n] - roots[m];
+                }
+            }
+            let delta = eval(roots[n]) / den;
+            roots[n] -= delta;
+            max_change = max_change.max(delta.norm() / roots[n].norm());
+        }
+        if max_change < 1.0e-15 {
+            break;
+        }
+    }
+    roots
+}
+
+/// Lowpass Bessel prototype.
+pub fn bessel_lowpass(order: usize, normalization: &BesselNormalization) -> Vec<AnalogSection> {
+    // Coefficients of the reverse Bessel polynomial, this gives unity group delay at DC
+    let coeffs: Vec<f64> = (0..=order)
+        .map(|k| {
+            factorial(2 * order - k)
+                / (2.0_f64.powi((order - k) as i32) * factorial(k) * factorial(order - k))
+        })
+        .collect();
+    let mut poles = polynomial_roots(&coeffs);
+    match normalization {
+        BesselNormalization::Delay => {}
+        BesselNormalization::Phase => {
+            let scale = coeffs[0].powf(1.0 / order as f64);
+            poles.iter_mut().for_each(|p| *p /= scale);
+        }
+        BesselNormalization::Magnitude => {
+            let scale = coeffs[0].powf(1.0 / order as f64);
+            poles.iter_mut().for_each(|p| *p /= scale);
+            // Search for the -3 dB point, the magnitude decreases monotonically
+            let sections = make_sections(&poles, &[], 1.0);
+            let magnitude = |w: f64| sections.iter().map(|s| s.magnitude_at(w)).product::<f64>();
+            let mut low: f64 = 0.01;
+            let mut high = 100.0;
+            for _ in 0..100 {
+                let mid = (low * high).sqrt();
+                if magnitude(mid) > 0.5_f64.sqrt() {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+            let w_3db = (low * high).sqrt();
+            poles.iter_mut().for_each(|p| *p /= w_3db);
+        }
+    }
+    make_sections(&poles, &[], 1.0)
+}
+
+/// Lowpass Chebyshev type I prototype, with the given passband ripple in dB.
+/// The gain at 1 rad/s is -ripple dB.
+pub fn chebyshev1_lowpass(order: usize, ripple: PrcFmt) -> Vec<AnalogSection> {
+    let ripple = ripple as DesignFmt;
+    let eps = (10.0_f64.powf(ripple / 10.0) - 1.0).sqrt();
+    let mu = (1.0 / eps).asinh() / order as f64;
+    let poles: Vec<C64> = (0..order)
+        .map(|k| {
+            let theta = PI * (2 * k + 1) as f64 / (2 * order) as f64;
+            C64::new(-mu.sinh() * theta.sin(), mu.cosh() * theta.cos())
+        })
+        .collect();
+    let gain = if order % 2 == 0 {
+        1.0 / (1.0 + eps * eps).sqrt()
+    } else {
+        1.0
+    };
+    make_sections(&poles, &[], gain)
+}
+
+/// Lowpass Chebyshev type II prototype, with the given stopband attenuation in dB.
+/// The gain at 1 rad/s is -attenuation dB.
+pub fn chebyshev2_lowpass(order: usize, attenuation: PrcFmt) -> Vec<AnalogSection> {
+    let attenuation = attenuation as DesignFmt;
+    let eps = 1.0 / (10.0_f64.powf(attenuation / 10.0) - 1.0).sqrt();
+    let mu = (1.0 / eps).asinh() / order as f64;
+    let mut poles = Vec::new();
+    let mut zeros = Vec::new();
+    for k in 0..order {
+        let theta = PI * (2 * k + 1) as f64 / (2 * order) as f64;
+        // Inverting the pole flips the sign of the imaginary part,
+        // so the upper half plane poles are the ones with cos(theta) < 0
+        poles.push(C64::new(-mu.sinh() * theta.sin(), mu.cosh() * theta.cos()).inv());
+        if theta.cos() < -1.0e-9 {
+            zeros.push(-1.0 / theta.cos());
+        }
+    }
+    make_sections(&poles, &zeros, 1.0)
+}
+
+// Landen sequence of descending moduli, with the complementary modulus given separately for accuracy.
+fn landen(k: f64, kp: f64) -> Vec<f64> {
+    let mut moduli = Vec::new();
+    let mut k_n = (k / (1.0 + kp)).powi(2);
+    moduli.push(k_n);
+    while k_n > 1.0e-15 && moduli.len() < 50 {
+        k_n = (k_n / (1.0 + (1.0 - k_n * k_n).sqrt())).powi(2);
+        moduli.push(k_n);
+    }
+    moduli
+}
+
+fn complement(k: f64) -> f64 {
+    ((1.0 - k) * (1.0 + k)).sqrt()
+}
+
+// Complete elliptic integral of the first kind, for modulus k with complement kp.
+fn ellip_k(k: f64, kp: f64) -> f64 {
+    landen(k, kp).iter().map(|k_n| 1.0 + k_n).product::<f64>() * PI / 2.0
+}
+
+// Jacobi elliptic function cd(u*K, k), with u normalized to the quarter period K.
+fn cde(u: C64, k: f64) -> C64 {
+    let mut w = (u * PI / 2.0).cos();
+    for k_n in landen(k, complement(k)).iter().rev() {
+        w = (1.0 + k_n) * w / (1.0 + k_n * w * w);
+    }
+    w
+}
+
+// Jacobi elliptic function sn(u*K, k), with u normalized to the quarter period K.
+fn sne(u: C64, k: f64) -> C64 {
+    cde(1.0 - u, k)
+}
+
+// Symmetric remainder
+fn srem(x: f64, y: f64) -> f64 {
+    x - y * (x / y).round()
+}
+
+// Inverse of cde
+fn acde(w: C64, k: f64) -> C64 {
+    let mut w = w;
+    let mut k_prev = k;
+    for k_n in landen(k, complement(k)).iter() {
+        w = w / (1.0 + (1.0 - w * w * k_prev * k_prev).sqrt()) * 2.0 / (1.0 + k_n);
+        k_prev = *k_n;
+    }
+    let u = w.acos() * 2.0 / PI;
+    let ratio = ellip_k(complement(k), k) / ellip_k(k, complement(k));
+    C64::new(srem(u.re, 4.0), srem(u.im, 2.0 * ratio))
+}
+
+// Inverse of sne
+fn asne(w: C64, k: f64) -> C64 {
+    1.0 - acde(w, k)
+}
+
+// Solve the degree equation for the elliptic modulus k, given the order and k1.
+fn ellipdeg(order: usize, k1: f64) -> f64 {
+    let k1p = complement(k1);
+    let q1 = (-PI * ellip_k(k1p, k1) / ellip_k(k1, k1p)).exp();
+    let q = q1.powf(1.0 / order as f64);
+    let num: f64 = (0..8).map(|m| q.powi(m * (m + 1))).sum();
+    let den: f64 = 1.0 + 2.0 * (1..8).map(|m| q.powi(m * m)).sum::<f64>();
+    4.0 * q.sqrt() * (num / den).powi(2)
+}
+
+/// Lowpass elliptic (Cauer) prototype, with the given passband ripple and stopband attenuation in dB.
+/// The gain at 1 rad/s is -ripple dB.
+pub fn elliptic_lowpass(order: usize, ripple: PrcFmt, attenuation: PrcFmt) -> Vec<AnalogSection> {
+    let ripple = ripple as DesignFmt;
+    let attenuation = attenuation as DesignFmt;
+    let eps_p = (10.0_f64.powf(ripple / 10.0) - 1.0).sqrt();
+    let eps_s = (10.0_f64.powf(attenuation / 10.0) - 1.0).sqrt();
+    let k1 = eps_p / eps_s;
+    let k = ellipdeg(order, k1);
+    let j = C64::i();
+    let v0 = -j * asne(j / eps_p, k1) / order as f64;
+
+    let mut poles = Vec::new();
+    let mut zeros = Vec::new();
+    for i in 1..=order / 2 {
+        let u_i = (2 * i - 1) as f64 / order as f64;
+        let zeta = cde(C64::new(u_i, 0.0), k);
+        zeros.push(1.0 / (k * zeta.re));
+        let pole = j * cde(u_i - j * v0, k);
+        // Use the pole with positive imaginary part to represent the pair
+        poles.push(if pole.im < 0.0 { pole.conj() } else { pole });
+    }
+    if order % 2 == 1 {
+        let pole = j * sne(j * v0, k);
+        poles.push(C64::new(pole.re, 0.0));
+    }
+    let gain = if order % 2 == 0 {
+        1.0 / (1.0 + eps_p * eps_p).sqrt()
+    } else {
+        1.0
+    };
+    make_sections(&poles, &zeros, gain)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::BesselNormalization;
+    use crate::iirdesign::{
+        bessel_lowpass, chebyshev1_lowpass, chebyshev2_lowpass, ellipdeg, elliptic_lowpass,
+        AnalogSection,
+    };
+
+    fn is_close(left: f64, right: f64, maxdiff: f64) -> bool {
+        println!("{} - {}", left, right);
+        (left - right).abs() < maxdiff
+    }
+
+    fn gain_db(sections: &[AnalogSection], w: f64) -> f64 {
+        20.0 * sections
+            .iter()
+            .map(|s| s.magnitude_at(w))
+            .product::<f64>()
+            .log10()
+    }
+
+    #[test]
+    fn bessel_2_phase() {
+        let sections = bessel_lowpass(2, &BesselNormalization::Phase);
+        assert_eq!(sections.len(), 1);
+        // s^2 + sqrt(3)*s + 1
+        assert!(is_close(sections[0].den[0], 1.0, 1e-9));
+        assert!(is_close(sections[0].den[1], 3.0_f64.sqrt(), 1e-9));
+    }
+
+    #[test]
+    fn bessel_5_delay_and_magnitude() {
+        let sections = bessel_lowpass(5, &BesselNormalization::Delay);
+        assert_eq!(sections.len(), 3);
+        // Unity group delay at DC
+        let delay: f64 = sections
+            .iter()
+            .map(|s| s.den[1] / s.den[0] - s.num[1] / s.num[0])
+            .sum();
+        assert!(is_close(delay, 1.0, 1e-9));
+        let sections = bessel_lowpass(5, &BesselNormalization::Magnitude);
+        assert!(is_close(gain_db(&sections, 1.0), -3.0103, 1e-3));
+    }
+
+    #[test]
+    fn chebyshev1() {
+        for order in 1..8 {
+            let sections = chebyshev1_lowpass(order, 1.0);
+            assert!(is_close(gain_db(&sections, 1.0), -1.0, 1e-6));
+            assert!(gain_db(&sections, 0.5) > -1.0 - 1e-6);
+            assert!(gain_db(&sections, 0.5) < 1e-6);
+        }
+    }
+
+    #[test]
+    fn chebyshev2() {
+        for order in 1..8 {
+            let sections = chebyshev2_lowpass(order, 40.0);
+            assert!(is_close(gain_db(&sections, 0.0), 0.0, 1e-6));
+            assert!(is_close(gain_db(&sections, 1.0), -40.0, 1e-6));
+            assert!(gain_db(&sections, 1.5) < -40.0 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn elliptic() {
+        let k1 = (10.0_f64.powf(0.05) - 1.0).sqrt() / (10.0_f64.powf(6.0) - 1.0).sqrt();
+        for order in 1..8 {
+            let sections = elliptic_lowpass(order, 0.5, 60.0);
+            assert!(is_close(gain_db(&sections, 1.0), -0.5, 1e-6));
+            assert!(gain_db(&sections, 0.7) > -0.5 - 1e-6);
+            assert!(gain_db(&sections, 0.7) < 1e-6);
+            // The stopband starts at 1/k
+            let w_stop = 1.0 / ellipdeg(order, k1);
+            for w in [1.0, 1.1, 1.5, 2.0, 5.0, 10.0] {
+                assert!(gain_db(&sections, w * w_stop) < -60.0 + 1e-6);
+            }
+        }
+    }
+}
@@ -2,6 +2,8 @@
 extern crate alsa;
 #[cfg(target_os = "linux")]
 extern crate alsa_sys;
+#[cfg(feature = "websocket")]
+extern crate base64;
 extern crate clap;
 #[cfg(feature = "cpal-backend")]
 extern crate cpal;
@@ -45,7 +47,8 @@ extern crate wasapi;
 #[macro_use]
 extern crate log;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error;
 use std::fmt;
 use std::sync::{Arc, RwLock};
@@ -72,17 +75,21 @@ pub type Res<T> = Result<T, Box<dyn error::Error>>;
 pub mod alsadevice;
 pub mod audiodevice;
 pub mod basicfilters;
+pub mod bassenhancer;
 pub mod biquad;
 pub mod biquadcombo;
 pub mod config;
+pub mod confighistory;
 pub mod conversions;
 #[cfg(target_os = "macos")]
 pub mod coreaudiodevice;
 pub mod countertimer;
 #[cfg(feature = "cpal-backend")]
 pub mod cpaldevice;
+pub mod crossfeed;
 pub mod diffeq;
 pub mod dither;
+pub mod dynamiceq;
 #[cfg(not(feature = "FFTW"))]
 pub mod fftconv;
 #[cfg(feature = "FFTW")]
@@ -95,13 +102,22 @@ pub mod filereader;
 pub mod filereader_nonblock;
 pub mod filters;
 pub mod helpers;
+pub mod iirdesign;
 pub mod loudness;
+pub mod midside;
 pub mod mixer;
+pub mod noisegate;
 pub mod processing;
 #[cfg(feature = "pulse-backend")]
 pub mod pulsedevice;
 #[cfg(feature = "websocket")]
 pub mod socketserver;
+pub mod softclip;
+pub mod statefile;
+#[cfg(test)]
+pub mod testutils;
+pub mod upmixer;
+pub mod volumecontrol;
 #[cfg(target_os = "windows")]
 pub mod wasapidevice;
 
@@ -174,6 +190,35 @@ pub struct PlaybackStatus {
 pub struct ProcessingParameters {
     pub volume: f32,
     pub mute: bool,
+    pub faders: HashMap<String, FaderState>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct FaderState {
+    pub volume: f32,
+    pub mute: bool,
+}
+
+impl ProcessingParameters {
+    /// Get the state of a named fader, or of the main volume if no name is given.
+    /// A fader that doesn't exist yet is at 0 dB and not muted.
+    pub fn fader(&self, name: Option<&str>) -> FaderState {
+        match name {
+            Some(name) => self.faders.get(name).cloned().unwrap_or_default(),
+            None => FaderState {
+                volume: self.volume,
+                mute: self.mute,
+            },
+        }
+    }
+
+    /// Create a named fader if it doesn't already exist.
+    pub fn add_fader(&mut self, name: &str) {
+        if !self.faders.contains_key(name) {
+            debug!("Adding volume fader '{}'", name);
+            self.faders.insert(name.to_string(), FaderState::default());
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -20,6 +20,7 @@ pub struct Loudness {
     samplerate: usize,
     chunksize: usize,
     processing_status: Arc<RwLock<ProcessingParameters>>,
+    fader: Option<String>,
     reference_level: f32,
     high_boost: f32,
     low_boost: f32,
@@ -45,8 +46,15 @@ impl Loudness {
         samplerate: usize,
         processing_status: Arc<RwLock<ProcessingParameters>>,
     ) -> Self {
-        let current_volume = processing_status.read().unwrap().volume;
-        let mute = processing_status.read().unwrap().mute;
+        if let Some(fader) = &conf.fader {
+            processing_status.write().unwrap().add_fader(fader);
+        }
+        let fader = processing_status
+            .read()
+            .unwrap()
+            .fader(conf.fader.as_deref());
+        let current_volume = fader.volume;
+        let mute = fader.mute;
         let ramptime_in_chunks =
             (conf.ramp_time / (1000.0 * chunksize as f32 / samplerate as f32)).round() as usize;
         let tempgain: PrcFmt = 10.0;
@@ -85,6 +93,7 @@ impl Loudness {
             samplerate,
             chunksize,
             processing_status,
+            fader: conf.fader,
         }
     }
 
@@ -117,8 +126,13 @@ impl Filter for Loudness {
     }
 
     fn process_waveform(&mut self, waveform: &mut [PrcFmt]) -> Res<()> {
-        let shared_vol = self.processing_status.read().unwrap().volume;
-        let shared_mute = self.processing_status.read().unwrap().mute;
+        let fader = self
+            .processing_status
+            .read()
+            .unwrap()
+            .fader(self.fader.as_deref());
+        let shared_vol = fader.volume;
+        let shared_mute = fader.mute;
 
         // Volume setting changed
         if (shared_vol - self.target_volume).abs() > 0.01 || self.mute != shared_mute {
@@ -191,9 +205,11 @@ impl Filter for Loudness {
                 gain: (relboost * self.low_boost) as PrcFmt,
             });
             self.high_biquad.update_parameters(config::Filter::Biquad {
+                design: config::BiquadDesign::Cookbook,
                 parameters: highshelf_conf,
             });
             self.low_biquad.update_parameters(config::Filter::Biquad {
+                design: config::BiquadDesign::Cookbook,
                 parameters: lowshelf_conf,
             });
         }
@@ -210,7 +226,15 @@ impl Filter for Loudness {
             self.ramptime_in_chunks = (conf.ramp_time
                 / (1000.0 * self.chunksize as f32 / self.samplerate as f32))
                 .round() as usize;
-            let current_volume = self.processing_status.read().unwrap().volume;
+            if let Some(fader) = &conf.fader {
+                self.processing_status.write().unwrap().add_fader(fader);
+            }
+            let current_volume = self
+                .processing_status
+                .read()
+                .unwrap()
+                .fader(conf.fader.as_deref())
+                .volume;
             let relboost = get_rel_boost(current_volume, conf.reference_level);
             let highshelf_conf =
                 config::BiquadParameters::Highshelf(config::ShelfSteepness::Slope {
@@ -224,14 +248,17 @@ impl Filter for Loudness {
                 gain: (relboost * conf.low_boost) as PrcFmt,
             });
             self.high_biquad.update_parameters(config::Filter::Biquad {
+                design: config::BiquadDesign::Cookbook,
                 parameters: highshelf_conf,
             });
             self.low_biquad.update_parameters(config::Filter::Biquad {
+                design: config::BiquadDesign::Cookbook,
                 parameters: lowshelf_conf,
             });
             self.reference_level = conf.reference_level;
             self.high_boost = conf.high_boost;
             self.low_boost = conf.low_boost;
+            self.fader = conf.fader;
         } else {
             // This should never happen unless there is a bug somewhere else
             panic!("Invalid config change!");
@@ -0,0 +1,293 @@
+// Mid/side processing of a stereo pair.
+// The left and right channels are encoded to mid and side, processed, and then decoded back.
+
+use crate::audiodevice::AudioChunk;
+use crate::config;
+use crate::filters::{FilterGroup, Processor};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+// Sample format
+use crate::PrcFmt;
+use crate::ProcessingParameters;
+use crate::Res;
+
+// Largest allowed stereo width
+const MAX_WIDTH: PrcFmt = 4.0;
+
+pub struct MidSide {
+    pub name: String,
+    channels: [usize; 2],
+    mid_gain: PrcFmt,
+    side_gain: PrcFmt,
+    mid_filters: FilterGroup,
+    side_filters: FilterGroup,
+}
+
+impl MidSide {
+    pub fn from_config(
+        name: String,
+        parameters: config::MidSideParameters,
+        filter_configs: HashMap<String, config::Filter>,
+        waveform_length: usize,
+        sample_freq: usize,
+        processing_status: Arc<RwLock<ProcessingParameters>>,
+    ) -> Self {
+        let mid_filters = FilterGroup::from_config(
+            parameters.channels[0],
+            parameters.mid_filters.clone(),
+            filter_configs.clone(),
+            waveform_length,
+            sample_freq,
+            processing_status.clone(),
+        );
+        let side_filters = FilterGroup::from_config(
+            parameters.channels[1],
+            parameters.side_filters.clone(),
+            filter_configs,
+            waveform_length,
+            sample_freq,
+            processing_status,
+        );
+        let (mid_gain, side_gain) = MidSide::linear_gains(&parameters);
+        MidSide {
+            name,
+            channels: parameters.channels,
+            mid_gain,
+            side_gain,
+            mid_filters,
+            side_filters,
+        }
+    }
+
+    /// Get the linear gains of the mid and side components.
+    /// The gains include the factor 1/2 of the encoding, and the width is applied to the side.
+    fn linear_gains(parameters: &config::MidSideParameters) -> (PrcFmt, PrcFmt) {
+        let base: PrcFmt = 10.0;
+        let mid_gain = 0.5 * base.powf(parameters.mid_gain / 20.0);
+        let side_gain = 0.5 * parameters.width * base.powf(parameters.side_gain / 20.0);
+        (mid_gain, side_gain)
+    }
+}
+
+impl Processor for MidSide {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn process_chunk(&mut self, chunk: &mut AudioChunk) -> Res<()> {
+        let [chan_l, chan_r] = self.channels;
+        if chunk.waveforms[chan_l].is_empty() && chunk.waveforms[chan_r].is_empty() {
+            return Ok(());
+        }
+        for chan in [chan_l, chan_r] {
+            if chunk.waveforms[chan].is_empty() {
+                chunk.waveforms[chan] = vec![0.0; chunk.frames];
+            }
+        }
+        // Encode in place, left becomes mid and right becomes side
+        let mut mid = std::mem::take(&mut chunk.waveforms[chan_l]);
+        let mut side = std::mem::take(&mut chunk.waveforms[chan_r]);
+        for (value_m, value_s) in mid.iter_mut().zip(side.iter_mut()) {
+            let left = *value_m;
+            let right = *value_s;
+            *value_m = self.mid_gain * (left + right);
+            *value_s = self.side_gain * (left - right);
+        }
+        self.mid_filters.process_waveform(&mut mid)?;
+        self.side_filters.process_waveform(&mut side)?;
+        // Decode back to left and right
+        for (value_m, value_s) in mid.iter_mut().zip(side.iter_mut()) {
+            let left = *value_m + *value_s;
+            let right = *value_m - *value_s;
+            *value_m = left;
+            *value_s = right;
+        }
+        chunk.waveforms[chan_l] = mid;
+        chunk.waveforms[chan_r] = side;
+        Ok(())
+    }
+
+    fn update_parameters(&mut self, conf: config::Processor) {
+        if let config::Processor::MidSide { parameters } = conf {
+            let (mid_gain, side_gain) = MidSide::linear_gains(&parameters);
+            self.mid_gain = mid_gain;
+            self.side_gain = side_gain;
+            self.channels = parameters.channels;
+        } else {
+            // This should never happen unless there is a bug somewhere else
+            panic!("Invalid config change!");
+        }
+    }
+
+    fn update_filter_parameters(
+        &mut self,
+        filterconfigs: HashMap<String, config::Filter>,
+        changed: Vec<String>,
+    ) {
+        self.mid_filters
+            .update_parameters(filterconfigs.clone(), changed.clone());
+        self.side_filters.update_parameters(filterconfigs, changed);
+    }
+}
+
+/// Validate a MidSide config.
+pub fn validate_config(num_channels: usize, conf: &config::MidSideParameters) -> Res<()> {
+    if conf.channels.iter().any(|chan| *chan >= num_channels) {
+        let msg = format!(
+            "Invalid channels {:?}, max is {}",
+            conf.channels,
+            num_channels - 1
+        );
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    if conf.channels[0] == conf.channels[1] {
+        return Err(config::ConfigError::new("The two channels must be different").into());
+    }
+    if conf.width < 0.0 {
+        return Err(config::ConfigError::new("Width cannot be negative").into());
+    } else if conf.width > MAX_WIDTH {
+        let msg = format!("Width cannot be larger than {}", MAX_WIDTH);
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    for gain in [conf.mid_gain, conf.side_gain] {
+        if gain < -150.0 {
+            return Err(config::ConfigError::new("Gain must be larger than -150 dB").into());
+        } else if gain > 150.0 {
+            return Err(config::ConfigError::new("Gain must be less than +150 dB").into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audiodevice::AudioChunk;
+    use crate::config;
+    use crate::filters::Processor;
+    use crate::midside;
+    use crate::{PrcFmt, ProcessingParameters};
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+
+    fn is_close(left: PrcFmt, right: PrcFmt, maxdiff: PrcFmt) -> bool {
+        println!("{} - {}", left, right);
+        (left - right).abs() < maxdiff
+    }
+
+    fn make_conf(width: PrcFmt, mid_filters: Vec<String>) -> config::MidSideParameters {
+        config::MidSideParameters {
+            channels: [0, 1],
+            width,
+            mid_gain: 0.0,
+            side_gain: 0.0,
+            mid_filters,
+            side_filters: Vec::new(),
+        }
+    }
+
+    fn process(conf: config::MidSideParameters, left: PrcFmt, right: PrcFmt) -> (PrcFmt, PrcFmt) {
+        let mut filters = HashMap::new();
+        filters.insert(
+            "halfgain".to_string(),
+            config::Filter::Gain {
+                parameters: config::GainParameters {
+                    gain: -20.0 * (2.0 as PrcFmt).log10(),
+                    inverted: false,
+                    mute: false,
+                    ramp_time: 0.0,
+                },
+            },
+        );
+        let status = Arc::new(RwLock::new(ProcessingParameters {
+            volume: 0.0,
+            mute: false,
+            faders: HashMap::new(),
+        }));
+        let mut ms =
+            midside::MidSide::from_config("test".to_string(), conf, filters, 4, 44100, status);
+        let mut chunk = AudioChunk::new(vec![vec![left; 4], vec![right; 4]], 1.0, -1.0, 4, 4);
+        ms.process_chunk(&mut chunk).unwrap();
+        (chunk.waveforms[0][3], chunk.waveforms[1][3])
+    }
+
+    #[test]
+    fn midside_unity() {
+        let (left, right) = process(make_conf(1.0, Vec::new()), 0.8, -0.3);
+        assert!(is_close(left, 0.8, 1e-9));
+        assert!(is_close(right, -0.3, 1e-9));
+    }
+
+    #[test]
+    fn midside_width() {
+        let (left, right) = process(make_conf(0.0, Vec::new()), 0.8, -0.2);
+        assert!(is_close(left, 0.3, 1e-9));
+        assert!(is_close(right, 0.3, 1e-9));
+        let (left, right) = process(make_conf(2.0, Vec::new()), 0.8, -0.2);
+        assert!(is_close(left, 1.3, 1e-9));
+        assert!(is_close(right, -0.7, 1e-9));
+    }
+
+    #[test]
+    fn midside_filters() {
+        // Halving the mid leaves only half of the common signal
+        let (left, right) = process(make_conf(1.0, vec!["halfgain".to_string()]), 0.6, 0.2);
+        assert!(is_close(left, 0.4, 1e-6));
+        assert!(is_close(right, 0.0, 1e-6));
+    }
+
+    #[test]
+    fn check_midside() {
+        let okconf = make_conf(1.5, Vec::new());
+        assert!(midside::validate_config(2, &okconf).is_ok());
+        assert!(midside::validate_config(1, &okconf).is_err());
+        let badconf = make_conf(-1.0, Vec::new());
+        assert!(midside::validate_config(2, &badconf).is_err());
+    }
+
+    fn make_pipeline_conf(mid_filters: &str, side_filters: &str) -> config::Configuration {
+        let yaml = format!(
+            "
+devices:
+  samplerate: 44100
+  chunksize: 1024
+  capture:
+    type: Stdin
+    channels: 2
+    format: S16LE
+  playback:
+    type: Stdout
+    channels: 2
+    format: S16LE
+filters:
+  mono_fir:
+    type: Conv
+    parameters:
+      type: Wav
+      filename: testdata/int32.wav
+      use_pipeline_channel: true
+processors:
+  stereowidth:
+    type: MidSide
+    parameters:
+      channels: [0, 1]
+      mid_filters: {}
+      side_filters: {}
+pipeline:
+  - type: Processor
+    name: stereowidth
+",
+            mid_filters, side_filters
+        );
+        serde_yaml::from_str(&yaml).unwrap()
+    }
+
+    #[test]
+    fn check_midside_filter_channels() {
+        // The file only has one channel, so it can only be used for the mid on channel 0
+        let mut okconf = make_pipeline_conf("[mono_fir]", "[]");
+        assert!(config::validate_config(&mut okconf, None).is_ok());
+        let mut badconf = make_pipeline_conf("[]", "[mono_fir]");
+        assert!(config::validate_config(&mut badconf, None).is_err());
+    }
+}
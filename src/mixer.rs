@@ -9,6 +9,10 @@ pub struct Mixer {
     pub channels_in: usize,
     pub channels_out: usize,
     pub mapping: Vec<Vec<MixerSource>>,
+    previous_mapping: Option<Vec<Vec<MixerSource>>>,
+    samplerate: usize,
+    ramp_samples: usize,
+    ramp_position: usize,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -17,79 +21,159 @@ pub struct MixerSource {
     pub gain: PrcFmt,
 }
 
-impl Mixer {
-    /// Creates a Mixer from a config struct
-    pub fn from_config(name: String, config: config::Mixer) -> Self {
-        let ch_in = config.channels.r#in;
-        let ch_out = config.channels.out;
-        let mut mapping = vec![Vec::<MixerSource>::new(); ch_out];
-        for cfg_mapping in config.mapping {
-            if !cfg_mapping.mute {
-                let dest = cfg_mapping.dest;
-                for cfg_src in cfg_mapping.sources {
-                    if !cfg_src.mute {
-                        let mut gain: PrcFmt = 10.0;
-                        gain = gain.powf(cfg_src.gain / 20.0);
-                        if cfg_src.inverted {
-                            gain = -gain;
-                        }
-                        let src = MixerSource {
-                            channel: cfg_src.channel,
-                            gain,
-                        };
-                        mapping[dest].push(src);
+/// Build the mapping of sources for each output channel. Muted sources and mappings are left out.
+fn build_mapping(config: &config::Mixer) -> Vec<Vec<MixerSource>> {
+    let mut mapping = vec![Vec::<MixerSource>::new(); config.channels.out];
+    for cfg_mapping in config.mapping.iter() {
+        if !cfg_mapping.mute {
+            let dest = cfg_mapping.dest;
+            for cfg_src in cfg_mapping.sources.iter() {
+                if !cfg_src.mute {
+                    let mut gain: PrcFmt = 10.0;
+                    gain = gain.powf(cfg_src.gain / 20.0);
+                    if cfg_src.inverted {
+                        gain = -gain;
                     }
+                    let src = MixerSource {
+                        channel: cfg_src.channel,
+                        gain,
+                    };
+                    mapping[dest].push(src);
                 }
             }
         }
+    }
+    mapping
+}
+
+/// Blend two mappings, giving the weight `weight` to the second one.
+/// The result gives the same output as a crossfade between the outputs of the two mappings.
+/// The gains of each source channel are added up, so that an output channel never
+/// gets more sources than there are input channels, no matter how many mappings are blended.
+fn blend_mappings(
+    first: &[Vec<MixerSource>],
+    second: &[Vec<MixerSource>],
+    weight: PrcFmt,
+) -> Vec<Vec<MixerSource>> {
+    first
+        .iter()
+        .zip(second.iter())
+        .map(|(sources_first, sources_second)| {
+            let scaled_first = sources_first.iter().map(|src| MixerSource {
+                channel: src.channel,
+                gain: (1.0 - weight) * src.gain,
+            });
+            let scaled_second = sources_second.iter().map(|src| MixerSource {
+                channel: src.channel,
+                gain: weight * src.gain,
+            });
+            let mut blended: Vec<MixerSource> = Vec::new();
+            for src in scaled_first.chain(scaled_second) {
+                match blended
+                    .iter_mut()
+                    .find(|other| other.channel == src.channel)
+                {
+                    Some(other) => other.gain += src.gain,
+                    None => blended.push(src),
+                }
+            }
+            blended
+        })
+        .collect()
+}
+
+/// Mix the channels of an AudioChunk according to a mapping.
+fn mix_waveforms(mapping: &[Vec<MixerSource>], input: &AudioChunk) -> Vec<Vec<PrcFmt>> {
+    let mut waveforms = Vec::<Vec<PrcFmt>>::with_capacity(mapping.len());
+    for sources in mapping.iter() {
+        let mut waveform = vec![0.0; input.frames];
+        for source in sources.iter() {
+            let source_chan = source.channel;
+            if !input.waveforms[source_chan].is_empty() {
+                for (value, sample) in waveform.iter_mut().zip(input.waveforms[source_chan].iter())
+                {
+                    *value += source.gain * sample;
+                }
+            }
+        }
+        waveforms.push(waveform);
+    }
+    waveforms
+}
+
+impl Mixer {
+    /// Creates a Mixer from a config struct
+    pub fn from_config(name: String, config: config::Mixer, samplerate: usize) -> Self {
+        let ch_in = config.channels.r#in;
+        let ch_out = config.channels.out;
+        let mapping = build_mapping(&config);
         Mixer {
             name,
             channels_in: ch_in,
             channels_out: ch_out,
             mapping,
+            previous_mapping: None,
+            samplerate,
+            ramp_samples: 0,
+            ramp_position: 0,
         }
     }
 
+    /// Update the mixer parameters.
+    /// If the number of channels is unchanged, the gains are ramped from the old to the new values.
     pub fn update_parameters(&mut self, config: config::Mixer) {
         let ch_in = config.channels.r#in;
         let ch_out = config.channels.out;
-        let mut mapping = vec![Vec::<MixerSource>::new(); ch_out];
-        for cfg_mapping in config.mapping {
-            let dest = cfg_mapping.dest;
-            for cfg_src in cfg_mapping.sources {
-                let mut gain: PrcFmt = 10.0;
-                gain = gain.powf(cfg_src.gain / 20.0);
-                if cfg_src.inverted {
-                    gain = -gain;
-                }
-                let src = MixerSource {
-                    channel: cfg_src.channel,
-                    gain,
-                };
-                mapping[dest].push(src);
-            }
+        let mapping = build_mapping(&config);
+        let ramp_samples = (config.ramp_time / 1000.0 * self.samplerate as PrcFmt).round() as usize;
+        if ramp_samples > 0 && ch_in == self.channels_in && ch_out == self.channels_out {
+            // Start the new ramp from the current state, also if a previous ramp is still running.
+            // The weight is taken from the running ramp, before the ramp length is changed.
+            let current = match &self.previous_mapping {
+                Some(previous) => blend_mappings(previous, &self.mapping, self.ramp_weight()),
+                None => self.mapping.clone(),
+            };
+            self.previous_mapping = Some(current);
+            self.ramp_position = 0;
+        } else {
+            self.previous_mapping = None;
         }
+        self.ramp_samples = ramp_samples;
         self.channels_in = ch_in;
         self.channels_out = ch_out;
         self.mapping = mapping;
     }
 
+    /// Weight of the new mapping at the current position of the ramp.
+    fn ramp_weight(&self) -> PrcFmt {
+        if self.ramp_position >= self.ramp_samples {
+            1.0
+        } else {
+            self.ramp_position as PrcFmt / self.ramp_samples as PrcFmt
+        }
+    }
+
     /// Apply a Mixer to an AudioChunk, yielding a new AudioChunk with a possibly different number of channels.
     pub fn process_chunk(&mut self, input: &AudioChunk) -> AudioChunk {
-        let mut waveforms = Vec::<Vec<PrcFmt>>::with_capacity(self.channels_out);
-        for out_chan in 0..self.channels_out {
-            waveforms.push(vec![0.0; input.frames]);
-            for source in 0..self.mapping[out_chan].len() {
-                let source_chan = self.mapping[out_chan][source].channel;
-                if !input.waveforms[source_chan].is_empty() {
-                    let gain = self.mapping[out_chan][source].gain;
-                    for n in 0..input.frames {
-                        waveforms[out_chan][n] += gain * input.waveforms[source_chan][n];
+        let mut waveforms = mix_waveforms(&self.mapping, input);
+        if let Some(previous) = &self.previous_mapping {
+            // Crossfade linearly from the output of the previous mapping
+            let previous_waveforms = mix_waveforms(previous, input);
+            for (waveform, previous_waveform) in waveforms.iter_mut().zip(previous_waveforms) {
+                let mut position = self.ramp_position;
+                for (value, previous_value) in waveform.iter_mut().zip(previous_waveform) {
+                    position += 1;
+                    if position < self.ramp_samples {
+                        let weight = position as PrcFmt / self.ramp_samples as PrcFmt;
+                        *value = weight * *value + (1.0 - weight) * previous_value;
                     }
                 }
             }
+            self.ramp_position += input.frames;
+            if self.ramp_position >= self.ramp_samples {
+                self.previous_mapping = None;
+            }
         }
-
         AudioChunk::from(input, waveforms)
     }
 }
@@ -98,6 +182,9 @@ impl Mixer {
 pub fn validate_mixer(mixer_config: &config::Mixer) -> Res<()> {
     let chan_in = mixer_config.channels.r#in;
     let chan_out = mixer_config.channels.out;
+    if mixer_config.ramp_time < 0.0 {
+        return Err(config::ConfigError::new("Ramp time cannot be negative").into());
+    }
     for mapping in mixer_config.mapping.iter() {
         if mapping.dest >= chan_out {
             let msg = format!(
@@ -139,6 +226,7 @@ pub fn get_used_input_channels(mixer_config: &config::Mixer) -> Vec<bool> {
 
 #[cfg(test)]
 mod tests {
+    use crate::audiodevice::AudioChunk;
     use crate::config::{Mixer, MixerChannels, MixerMapping, MixerSource};
     use crate::mixer;
     use crate::mixer::get_used_input_channels;
@@ -193,6 +281,7 @@ mod tests {
         let conf = Mixer {
             channels: chans,
             mapping: vec![map0, map1, map2, map3],
+            ramp_time: 0.0,
         };
         let used = get_used_input_channels(&conf);
         assert_eq!(used, vec![true, true]);
@@ -248,6 +337,7 @@ mod tests {
         let conf = Mixer {
             channels: chans,
             mapping: vec![map0, map1, map2, map3],
+            ramp_time: 0.0,
         };
         let used = get_used_input_channels(&conf);
         assert_eq!(used, vec![false, true]);
@@ -303,6 +393,7 @@ mod tests {
         let conf = Mixer {
             channels: chans,
             mapping: vec![map0, map1, map2, map3],
+            ramp_time: 0.0,
         };
         let used = get_used_input_channels(&conf);
         assert_eq!(used, vec![false, true]);
@@ -358,6 +449,7 @@ mod tests {
         let conf = Mixer {
             channels: chans,
             mapping: vec![map0, map1, map2, map3],
+            ramp_time: 0.0,
         };
         let used = get_used_input_channels(&conf);
         assert_eq!(used, vec![false, true]);
@@ -413,8 +505,9 @@ mod tests {
         let conf = Mixer {
             channels: chans,
             mapping: vec![map0, map1, map2, map3],
+            ramp_time: 0.0,
         };
-        let mix = mixer::Mixer::from_config("dummy".to_string(), conf);
+        let mix = mixer::Mixer::from_config("dummy".to_string(), conf, 44100);
         assert_eq!(mix.channels_in, 2);
         assert_eq!(mix.channels_out, 4);
 
@@ -495,8 +588,9 @@ mod tests {
         let conf = Mixer {
             channels: chans,
             mapping: vec![map0, map1, map2, map3],
+            ramp_time: 0.0,
         };
-        let mix = mixer::Mixer::from_config("dummy".to_string(), conf);
+        let mix = mixer::Mixer::from_config("dummy".to_string(), conf, 44100);
         assert_eq!(mix.channels_in, 2);
         assert_eq!(mix.channels_out, 4);
 
@@ -515,4 +609,75 @@ mod tests {
 
         assert_eq!(mix.mapping, exp_map);
     }
+
+    fn make_mono_mixer(gain: f64, mute: bool, ramp_time: f64) -> Mixer {
+        let src = MixerSource {
+            channel: 0,
+            gain,
+            inverted: false,
+            mute,
+        };
+        let map = MixerMapping {
+            dest: 0,
+            sources: vec![src],
+            mute: false,
+        };
+        Mixer {
+            channels: MixerChannels { r#in: 1, out: 1 },
+            mapping: vec![map],
+            ramp_time,
+        }
+    }
+
+    #[test]
+    fn check_ramped_update() {
+        let mut mix =
+            mixer::Mixer::from_config("dummy".to_string(), make_mono_mixer(0.0, false, 0.1), 40000);
+        // Muting ramps the gain down over 4 samples
+        mix.update_parameters(make_mono_mixer(0.0, true, 0.1));
+        let chunk = AudioChunk::new(vec![vec![1.0; 3]], 1.0, -1.0, 3, 3);
+        let output = mix.process_chunk(&chunk);
+        assert_eq!(output.waveforms[0], vec![0.75, 0.5, 0.25]);
+        let output = mix.process_chunk(&chunk);
+        assert_eq!(output.waveforms[0], vec![0.0, 0.0, 0.0]);
+        assert_eq!(mix.mapping, vec![vec![]]);
+    }
+
+    #[test]
+    fn check_repeated_ramped_updates() {
+        let mut mix =
+            mixer::Mixer::from_config("dummy".to_string(), make_mono_mixer(0.0, false, 0.1), 40000);
+        let chunk = AudioChunk::new(vec![vec![1.0; 1]], 1.0, -1.0, 1, 1);
+        // Many updates during a running ramp don't add any sources
+        for n in 0..20 {
+            mix.update_parameters(make_mono_mixer(-(n as f64), false, 0.1));
+            mix.process_chunk(&chunk);
+        }
+        assert_eq!(mix.previous_mapping.as_ref().unwrap()[0].len(), 1);
+    }
+
+    #[test]
+    fn check_ramp_time_change() {
+        let mut mix =
+            mixer::Mixer::from_config("dummy".to_string(), make_mono_mixer(0.0, false, 0.1), 40000);
+        // Halfway through a 4 sample ramp to mute, the gain is 0.5
+        mix.update_parameters(make_mono_mixer(0.0, true, 0.1));
+        let chunk = AudioChunk::new(vec![vec![1.0; 2]], 1.0, -1.0, 2, 2);
+        let output = mix.process_chunk(&chunk);
+        assert_eq!(output.waveforms[0], vec![0.75, 0.5]);
+        // A new ramp with a different length starts from the current gain
+        mix.update_parameters(make_mono_mixer(0.0, true, 0.2));
+        let output = mix.process_chunk(&chunk);
+        assert_eq!(output.waveforms[0], vec![0.4375, 0.375]);
+    }
+
+    #[test]
+    fn check_instant_update() {
+        let mut mix =
+            mixer::Mixer::from_config("dummy".to_string(), make_mono_mixer(0.0, false, 0.0), 40000);
+        mix.update_parameters(make_mono_mixer(-20.0, false, 0.0));
+        let chunk = AudioChunk::new(vec![vec![1.0; 2]], 1.0, -1.0, 2, 2);
+        let output = mix.process_chunk(&chunk);
+        assert_eq!(output.waveforms[0], vec![0.1, 0.1]);
+    }
 }
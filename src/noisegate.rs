@@ -0,0 +1,237 @@
+// Noise gate and downward expander.
+// The gate opens when the level rises above the threshold,
+// and closes when the level has stayed below the threshold minus the hysteresis for the hold time.
+
+use crate::config;
+use crate::filters::Filter;
+use crate::helpers::time_constant;
+
+// Sample format
+use crate::PrcFmt;
+use crate::Res;
+
+// Release time constant of the level detector in seconds
+const DETECTOR_RELEASE_TIME: PrcFmt = 0.01;
+
+pub struct NoiseGate {
+    pub name: String,
+    samplerate: usize,
+    threshold: PrcFmt,
+    close_threshold: PrcFmt,
+    range: PrcFmt,
+    ratio: Option<PrcFmt>,
+    attack: PrcFmt,
+    release: PrcFmt,
+    hold_samples: usize,
+    detector_release: PrcFmt,
+    level: PrcFmt,
+    open: bool,
+    hold_counter: usize,
+    gain: PrcFmt,
+}
+
+impl NoiseGate {
+    pub fn from_config(
+        name: String,
+        samplerate: usize,
+        parameters: config::NoiseGateParameters,
+    ) -> Self {
+        let detector_release = (-1.0 / (DETECTOR_RELEASE_TIME * samplerate as PrcFmt)).exp();
+        let mut gate = NoiseGate {
+            name,
+            samplerate,
+            threshold: 0.0,
+            close_threshold: 0.0,
+            range: 0.0,
+            ratio: None,
+            attack: 0.0,
+            release: 0.0,
+            hold_samples: 0,
+            detector_release,
+            level: 0.0,
+            open: false,
+            hold_counter: 0,
+            gain: 0.0,
+        };
+        gate.set_parameters(parameters);
+        // Start closed, at the lowest gain
+        gate.gain = gate.range;
+        gate
+    }
+
+    fn set_parameters(&mut self, parameters: config::NoiseGateParameters) {
+        self.threshold = parameters.threshold;
+        self.close_threshold = parameters.threshold - parameters.hysteresis;
+        self.range = -parameters.range;
+        self.ratio = parameters.ratio;
+        self.attack = time_constant(parameters.attack, self.samplerate);
+        self.release = time_constant(parameters.release, self.samplerate);
+        self.hold_samples = (parameters.hold * self.samplerate as PrcFmt / 1000.0).round() as usize;
+    }
+
+    /// Get the target gain in dB for a closed gate.
+    /// A gate attenuates by the full range, while an expander
+    /// increases the attenuation with the distance below the threshold.
+    fn closed_gain(&self, level_db: PrcFmt) -> PrcFmt {
+        match self.ratio {
+            Some(ratio) => {
+                let gain = (level_db - self.threshold).min(0.0) * (ratio - 1.0);
+                gain.max(self.range)
+            }
+            None => self.range,
+        }
+    }
+
+    /// Process a single sample
+    fn process_single(&mut self, input: PrcFmt) -> PrcFmt {
+        self.level = input.abs().max(self.level * self.detector_release);
+        let level_db = 20.0 * (self.level + 1.0e-30).log10();
+        if level_db >= self.threshold {
+            self.open = true;
+            self.hold_counter = self.hold_samples;
+        } else if self.open && level_db < self.close_threshold {
+            if self.hold_counter > 0 {
+                self.hold_counter -= 1;
+            } else {
+                self.open = false;
+            }
+        }
+        let target = if self.open {
+            0.0
+        } else {
+            self.closed_gain(level_db)
+        };
+        let coeff = if target > self.gain {
+            self.attack
+        } else {
+            self.release
+        };
+        self.gain = target + coeff * (self.gain - target);
+        let base: PrcFmt = 10.0;
+        input * base.powf(self.gain / 20.0)
+    }
+}
+
+impl Filter for NoiseGate {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn process_waveform(&mut self, waveform: &mut [PrcFmt]) -> Res<()> {
+        for item in waveform.iter_mut() {
+            *item = self.process_single(*item);
+        }
+        Ok(())
+    }
+
+    fn update_parameters(&mut self, conf: config::Filter) {
+        if let config::Filter::NoiseGate { parameters } = conf {
+            self.set_parameters(parameters);
+        } else {
+            // This should never happen unless there is a bug somewhere else
+            panic!("Invalid config change!");
+        }
+    }
+}
+
+/// Validate a NoiseGate config.
+pub fn validate_config(conf: &config::NoiseGateParameters) -> Res<()> {
+    if conf.threshold > 0.0 {
+        return Err(config::ConfigError::new("Threshold must be less than 0 dB").into());
+    } else if conf.threshold < -150.0 {
+        return Err(config::ConfigError::new("Threshold must be higher than -150 dB").into());
+    }
+    if conf.range <= 0.0 {
+        return Err(config::ConfigError::new("Range must be larger than 0 dB").into());
+    } else if conf.range > 150.0 {
+        return Err(config::ConfigError::new("Range must be less than 150 dB").into());
+    }
+    if let Some(ratio) = conf.ratio {
+        if ratio <= 1.0 {
+            return Err(config::ConfigError::new("Ratio must be larger than 1").into());
+        }
+    }
+    if conf.hysteresis < 0.0 {
+        return Err(config::ConfigError::new("Hysteresis cannot be negative").into());
+    }
+    if conf.attack < 0.0 || conf.hold < 0.0 || conf.release < 0.0 {
+        return Err(
+            config::ConfigError::new("Attack, hold and release times cannot be negative").into(),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config;
+    use crate::filters::Filter;
+    use crate::noisegate;
+    use crate::PrcFmt;
+
+    fn is_close(left: PrcFmt, right: PrcFmt, maxdiff: PrcFmt) -> bool {
+        println!("{} - {}", left, right);
+        (left - right).abs() < maxdiff
+    }
+
+    fn make_conf(ratio: Option<PrcFmt>) -> config::NoiseGateParameters {
+        config::NoiseGateParameters {
+            threshold: -40.0,
+            range: 60.0,
+            ratio,
+            hysteresis: 6.0,
+            attack: 1.0,
+            hold: 10.0,
+            release: 10.0,
+        }
+    }
+
+    // Run a constant level through the gate, and return the last output sample.
+    fn run_level(gate: &mut noisegate::NoiseGate, level_db: PrcFmt) -> PrcFmt {
+        let value = (10.0 as PrcFmt).powf(level_db / 20.0);
+        let mut waveform = vec![value; 48000];
+        gate.process_waveform(&mut waveform).unwrap();
+        20.0 * waveform.last().unwrap().log10()
+    }
+
+    #[test]
+    fn gate_open_closed() {
+        let mut gate =
+            noisegate::NoiseGate::from_config("test".to_string(), 48000, make_conf(None));
+        assert!(is_close(run_level(&mut gate, -20.0), -20.0, 0.01));
+        assert!(is_close(run_level(&mut gate, -70.0), -130.0, 0.01));
+    }
+
+    #[test]
+    fn gate_hysteresis() {
+        let mut gate =
+            noisegate::NoiseGate::from_config("test".to_string(), 48000, make_conf(None));
+        // Below the threshold, but within the hysteresis. A closed gate stays closed
+        assert!(is_close(run_level(&mut gate, -43.0), -103.0, 0.01));
+        // An open gate stays open
+        run_level(&mut gate, -20.0);
+        assert!(is_close(run_level(&mut gate, -43.0), -43.0, 0.01));
+    }
+
+    #[test]
+    fn expander() {
+        let mut gate =
+            noisegate::NoiseGate::from_config("test".to_string(), 48000, make_conf(Some(2.0)));
+        // 10 dB under the threshold with ratio 2 gives 10 dB attenuation
+        assert!(is_close(run_level(&mut gate, -50.0), -60.0, 0.01));
+        // Attenuation is limited by the range
+        assert!(is_close(run_level(&mut gate, -120.0), -180.0, 0.01));
+    }
+
+    #[test]
+    fn check_noisegate() {
+        assert!(noisegate::validate_config(&make_conf(None)).is_ok());
+        assert!(noisegate::validate_config(&make_conf(Some(0.5))).is_err());
+        let mut badconf = make_conf(None);
+        badconf.threshold = 10.0;
+        assert!(noisegate::validate_config(&badconf).is_err());
+        let mut badconf = make_conf(None);
+        badconf.hysteresis = -1.0;
+        assert!(noisegate::validate_config(&badconf).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::audiodevice::*;
 use crate::config;
 use crate::filters;
+use crate::volumecontrol;
 use crate::ProcessingParameters;
 use std::sync::mpsc;
 use std::sync::{Arc, Barrier, RwLock};
@@ -15,7 +16,10 @@ pub fn run_processing(
     processing_status: Arc<RwLock<ProcessingParameters>>,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
+        let volume_settings = conf_proc.volume.clone();
         let mut pipeline = filters::Pipeline::from_config(conf_proc, processing_status.clone());
+        // The filters have registered their faders, limit them and the main volume to the new config
+        volumecontrol::clamp_volumes(&volume_settings, &mut processing_status.write().unwrap());
         debug!("build filters, waiting to start processing loop");
         barrier_proc.wait();
         debug!("Processing loop starts now!");
@@ -57,6 +61,7 @@ pub fn run_processing(
             }
             if let Ok((diff, new_config)) = rx_pipeconf.try_recv() {
                 trace!("Message received on config channel");
+                let volume_settings = new_config.volume.clone();
                 match diff {
                     config::ConfigChange::Pipeline | config::ConfigChange::MixerParameters => {
                         debug!("Rebuilding pipeline.");
@@ -64,12 +69,16 @@ pub fn run_processing(
                             filters::Pipeline::from_config(new_config, processing_status.clone());
                         pipeline = new_pipeline;
                     }
-                    config::ConfigChange::FilterParameters { filters, mixers } => {
+                    config::ConfigChange::FilterParameters {
+                        filters,
+                        mixers,
+                        processors,
+                    } => {
                         debug!(
-                            "Updating parameters of filters: {:?}, mixers: {:?}.",
-                            filters, mixers
+                            "Updating parameters of filters: {:?}, mixers: {:?}, processors: {:?}.",
+                            filters, mixers, processors
                         );
-                        pipeline.update_parameters(new_config, filters, mixers);
+                        pipeline.update_parameters(new_config, filters, mixers, processors);
                     }
                     config::ConfigChange::Devices => {
                         let msg = AudioMessage::EndOfStream;
@@ -78,6 +87,10 @@ pub fn run_processing(
                     }
                     _ => {}
                 };
+                volumecontrol::clamp_volumes(
+                    &volume_settings,
+                    &mut processing_status.write().unwrap(),
+                );
             };
         }
     })
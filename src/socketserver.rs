@@ -2,25 +2,32 @@ use clap::crate_version;
 #[cfg(feature = "secure-websocket")]
 use native_tls::{Identity, TlsAcceptor, TlsStream};
 use serde::{Deserialize, Serialize};
-#[cfg(feature = "secure-websocket")]
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
 use std::fs::File;
-#[cfg(feature = "secure-websocket")]
 use std::io::Read;
-use std::net::{TcpListener, TcpStream};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
+use std::time::{Duration, Instant};
 use tungstenite::accept;
 use tungstenite::Message;
 use tungstenite::WebSocket;
 
 use crate::config;
+use crate::confighistory::{ConfigHistory, HistoryEntryInfo};
+use crate::filters;
+use crate::volumecontrol;
 use crate::ExitRequest;
+use crate::PrcFmt;
 use crate::ProcessingState;
 use crate::Res;
 use crate::{
-    list_supported_devices, CaptureStatus, PlaybackStatus, ProcessingParameters, ProcessingStatus,
-    StopReason,
+    list_supported_devices, CaptureStatus, FaderState, PlaybackStatus, ProcessingParameters,
+    ProcessingStatus, StopReason,
 };
 
 #[derive(Debug, Clone)]
@@ -28,9 +35,14 @@ pub struct SharedData {
     pub signal_reload: Arc<AtomicBool>,
     pub signal_exit: Arc<AtomicUsize>,
     pub active_config: Arc<Mutex<Option<config::Configuration>>>,
+    // Incremented every time the active config is replaced
+    pub config_generation: Arc<AtomicUsize>,
     pub active_config_path: Arc<Mutex<Option<String>>>,
+    // Name of the config file that was last loaded from the config library
+    pub last_preset: Arc<Mutex<Option<String>>>,
     pub new_config: Arc<Mutex<Option<config::Configuration>>>,
     pub previous_config: Arc<Mutex<Option<config::Configuration>>>,
+    pub config_history: Arc<Mutex<ConfigHistory>>,
     pub capture_status: Arc<RwLock<CaptureStatus>>,
     pub playback_status: Arc<RwLock<PlaybackStatus>>,
     pub processing_status: Arc<RwLock<ProcessingParameters>>,
@@ -45,18 +57,472 @@ pub struct ServerParameters<'a> {
     pub cert_file: Option<&'a str>,
     #[cfg(feature = "secure-websocket")]
     pub cert_pass: Option<&'a str>,
+    pub control_token: Option<&'a str>,
+    pub monitor_token: Option<&'a str>,
+    pub config_dir: Option<&'a str>,
+    pub coeff_dir: Option<&'a str>,
+}
+
+/// Access control settings of the server, shared by all connections.
+#[derive(Debug, Default)]
+struct ServerSettings {
+    control_token: Option<String>,
+    monitor_token: Option<String>,
+    config_dir: Option<PathBuf>,
+    coeff_dir: Option<PathBuf>,
+    // Failed authentication attempts per client address
+    auth_failures: Mutex<HashMap<Option<IpAddr>, AuthFailures>>,
+}
+
+/// Recent failed authentication attempts from one address.
+#[derive(Debug)]
+struct AuthFailures {
+    count: u32,
+    last: Instant,
+}
+
+impl AuthFailures {
+    /// Time to wait after the last failure before a new attempt is allowed.
+    /// The first few attempts are free, to allow for typos, then the delay doubles for each failure.
+    fn delay(&self) -> Duration {
+        if self.count < AUTH_FREE_ATTEMPTS {
+            return Duration::ZERO;
+        }
+        let doublings = (self.count - AUTH_FREE_ATTEMPTS).min(16);
+        Duration::from_millis(AUTH_MIN_DELAY << doublings)
+            .min(Duration::from_millis(AUTH_MAX_DELAY))
+    }
+}
+
+impl ServerSettings {
+    fn from_parameters(parameters: &ServerParameters) -> Res<Self> {
+        let config_dir = match parameters.config_dir {
+            Some(dir) => Some(PathBuf::from(dir).canonicalize()?),
+            None => None,
+        };
+        let coeff_dir = match parameters.coeff_dir {
+            Some(dir) => Some(PathBuf::from(dir).canonicalize()?),
+            None => None,
+        };
+        Ok(ServerSettings {
+            control_token: parameters.control_token.map(|token| token.to_string()),
+            monitor_token: parameters.monitor_token.map(|token| token.to_string()),
+            config_dir,
+            coeff_dir,
+            auth_failures: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Check a token given by a client, and get the role it gives.
+    /// Attempts from an address that recently failed several times are rejected
+    /// without checking the token, to slow down guessing.
+    fn authenticate(&self, token: &str, address: Option<IpAddr>) -> Result<ClientRole, String> {
+        let mut failures = self.auth_failures.lock().unwrap_or_else(|e| e.into_inner());
+        let reset = Duration::from_millis(AUTH_FAILURE_RESET);
+        failures.retain(|_, failure| failure.last.elapsed() < reset);
+        if let Some(failure) = failures.get(&address) {
+            if failure.last.elapsed() < failure.delay() {
+                return Err("Too many failed attempts, try again later".to_string());
+            }
+        }
+        match self.role_for_token(token) {
+            Some(role) => {
+                failures.remove(&address);
+                Ok(role)
+            }
+            None => {
+                let failure = failures.entry(address).or_insert(AuthFailures {
+                    count: 0,
+                    last: Instant::now(),
+                });
+                failure.count += 1;
+                failure.last = Instant::now();
+                Err("Wrong token".to_string())
+            }
+        }
+    }
+
+    /// Get the role of a new connection.
+    /// Without a control token all clients can control, and without
+    /// a monitor token all clients can read.
+    fn initial_role(&self) -> ClientRole {
+        if self.control_token.is_none() {
+            ClientRole::Controller
+        } else if self.monitor_token.is_none() {
+            ClientRole::Monitor
+        } else {
+            ClientRole::Unauthenticated
+        }
+    }
+
+    /// Get the role given by a token, if it matches any of the configured tokens.
+    fn role_for_token(&self, token: &str) -> Option<ClientRole> {
+        if let Some(control_token) = &self.control_token {
+            if tokens_match(token, control_token) {
+                return Some(ClientRole::Controller);
+            }
+        }
+        if let Some(monitor_token) = &self.monitor_token {
+            if tokens_match(token, monitor_token) {
+                return Some(ClientRole::Monitor);
+            }
+        }
+        None
+    }
+
+    /// Resolve the path of a config file.
+    /// When a config directory is set, relative paths are relative to it,
+    /// and paths that end up outside of it are rejected.
+    fn resolve_config_path(&self, path: &str) -> Res<String> {
+        match &self.config_dir {
+            Some(dir) => {
+                let full_path = dir.join(path).canonicalize()?;
+                if !full_path.starts_with(dir) {
+                    let msg = format!("Config file '{}' is outside the config directory", path);
+                    return Err(config::ConfigError::new(&msg).into());
+                }
+                Ok(full_path.to_string_lossy().to_string())
+            }
+            None => Ok(path.to_string()),
+        }
+    }
+
+    /// Get the config directory, that holds the library of config files.
+    fn library_dir(&self) -> Res<&PathBuf> {
+        self.config_dir
+            .as_ref()
+            .ok_or_else(|| config::ConfigError::new("No config directory is set").into())
+    }
+
+    /// Get the path of a file in the config library.
+    /// Only plain yaml file names are accepted, without any directory part.
+    fn library_path(&self, name: &str) -> Res<PathBuf> {
+        let dir = self.library_dir()?;
+        if !is_plain_file_name(name) {
+            let msg = format!("Invalid config file name '{}'", name);
+            return Err(config::ConfigError::new(&msg).into());
+        }
+        if !is_yaml_file_name(name) {
+            let msg = format!("Config file name '{}' must end with .yml or .yaml", name);
+            return Err(config::ConfigError::new(&msg).into());
+        }
+        Ok(dir.join(name))
+    }
+
+    /// List the names of the config files in the config library, in alphabetical order.
+    fn list_library(&self) -> Res<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.library_dir()?)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                if is_yaml_file_name(name) && !name.starts_with('.') {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Get the path of a file in the coefficient directory.
+    /// Only plain file names are accepted, without any directory part.
+    fn coeff_path(&self, name: &str) -> Res<PathBuf> {
+        let dir = self
+            .coeff_dir
+            .as_ref()
+            .ok_or_else(|| config::ConfigError::new("No coefficient directory is set"))?;
+        if !is_plain_file_name(name) {
+            let msg = format!("Invalid coefficient file name '{}'", name);
+            return Err(config::ConfigError::new(&msg).into());
+        }
+        Ok(dir.join(name))
+    }
+
+    /// List the files in the coefficient directory, in alphabetical order.
+    fn list_coeff_files(&self) -> Res<Vec<CoeffFileInfo>> {
+        let dir = self
+            .coeff_dir
+            .as_ref()
+            .ok_or_else(|| config::ConfigError::new("No coefficient directory is set"))?;
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                if !name.starts_with('.') {
+                    // A broken file is listed with the error, so that it can be found and replaced
+                    let path = entry.path();
+                    let info = CoeffFileInfo::from_file(name, &path).unwrap_or_else(|err| {
+                        warn!("Could not read coefficient file '{}': {}", name, err);
+                        CoeffFileInfo::unreadable(name, &path, err.to_string())
+                    });
+                    files.push(info);
+                }
+            }
+        }
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(files)
+    }
+
+    /// Store a coefficient file. The data is first written to a temporary file,
+    /// that is renamed when the data has been checked.
+    fn save_coeff_file(&self, name: &str, data: &[u8]) -> Res<CoeffFileInfo> {
+        let path = self.coeff_path(name)?;
+        let temp_path = path.with_file_name(format!(".{}.part", name));
+        fs::write(&temp_path, data)?;
+        match CoeffFileInfo::from_file(name, &temp_path) {
+            Ok(mut info) => {
+                fs::rename(&temp_path, &path)?;
+                info.path = path.to_string_lossy().to_string();
+                Ok(info)
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Information about a coefficient file.
+/// The format, sample rate, channels and length are only known for wav files.
+/// The error is set for files that could not be read.
+#[derive(Debug, PartialEq, Serialize)]
+struct CoeffFileInfo {
+    name: String,
+    path: String,
+    size: u64,
+    format: Option<config::FileFormat>,
+    samplerate: Option<usize>,
+    channels: Option<usize>,
+    length: Option<usize>,
+    error: Option<String>,
+}
+
+impl CoeffFileInfo {
+    /// Read the information of a file. Files that look like wav files must have a valid header.
+    fn from_file(name: &str, path: &std::path::Path) -> Res<Self> {
+        let size = fs::metadata(path)?.len();
+        let mut info = CoeffFileInfo {
+            name: name.to_string(),
+            path: path.to_string_lossy().to_string(),
+            size,
+            format: None,
+            samplerate: None,
+            channels: None,
+            length: None,
+            error: None,
+        };
+        let mut header = [0; 4];
+        let is_wav = match File::open(path).and_then(|mut file| file.read_exact(&mut header)) {
+            Ok(()) => matches!(&header, b"RIFF" | b"RF64" | b"BW64" | b"riff" | b"FORM"),
+            Err(_) => false,
+        };
+        if is_wav {
+            let params = filters::find_data_in_wav(&info.path)?;
+            let frame_size = params.sample_format.bytes_per_sample() * params.channels;
+            info.length = match params.data_length.checked_div(frame_size) {
+                Some(length) => Some(length),
+                None => return Err(config::ConfigError::new("Invalid wav header").into()),
+            };
+            info.format = Some(params.sample_format);
+            info.samplerate = Some(params.sample_rate);
+            info.channels = Some(params.channels);
+        }
+        Ok(info)
+    }
+
+    /// Information for a file that could not be read.
+    fn unreadable(name: &str, path: &std::path::Path, error: String) -> Self {
+        CoeffFileInfo {
+            name: name.to_string(),
+            path: path.to_string_lossy().to_string(),
+            size: fs::metadata(path)
+                .map(|meta| meta.len())
+                .unwrap_or_default(),
+            format: None,
+            samplerate: None,
+            channels: None,
+            length: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Check if a file is used by a Conv filter of the active config.
+fn is_used_by_active_config(shared_data_inst: &SharedData, path: &Path) -> bool {
+    let path = match path.canonicalize() {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    match &*shared_data_inst.active_config.lock().unwrap() {
+        Some(conf) => conf.filters.values().any(|filter| match filter {
+            config::Filter::Conv {
+                parameters:
+                    config::ConvParameters::Raw { filename, .. }
+                    | config::ConvParameters::Wav { filename, .. },
+            } => Path::new(filename)
+                .canonicalize()
+                .map(|filter_path| filter_path == path)
+                .unwrap_or(false),
+            _ => false,
+        }),
+        None => false,
+    }
+}
+
+/// Get the path of a coefficient file that is going to be replaced or deleted.
+/// Files used by the active config are refused, since the filters would read them again
+/// when the config is reloaded.
+fn unused_coeff_path(
+    shared_data_inst: &SharedData,
+    settings: &ServerSettings,
+    name: &str,
+) -> Res<PathBuf> {
+    let path = settings.coeff_path(name)?;
+    if is_used_by_active_config(shared_data_inst, &path) {
+        let msg = format!("Coefficient file '{}' is in use", name);
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    Ok(path)
+}
+
+/// Check that a name given by a client is a plain, visible file name,
+/// that stays in the directory it is joined to on all platforms.
+/// Separators of any platform, and colons that would give a drive prefix on Windows, are rejected.
+fn is_plain_file_name(name: &str) -> bool {
+    let path = Path::new(name);
+    !name.starts_with('.')
+        && !name.contains(&['/', '\\', ':'][..])
+        && path.file_name() == Some(OsStr::new(name))
+        && !path
+            .components()
+            .any(|component| matches!(component, Component::Prefix(_)))
+}
+
+fn is_yaml_file_name(name: &str) -> bool {
+    name.ends_with(".yml") || name.ends_with(".yaml")
+}
+
+/// Compare tokens in a time that doesn't depend on where they differ.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// The access level of a websocket client. Monitors can only read values,
+/// while controllers can also change settings and stop processing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+enum ClientRole {
+    Unauthenticated,
+    Monitor,
+    Controller,
+}
+
+/// The state of a websocket connection.
+#[derive(Debug)]
+struct ClientState {
+    role: ClientRole,
+    subscriptions: Subscriptions,
+    settings: Arc<ServerSettings>,
+    // Name of a coefficient file whose data is expected in the next binary message
+    pending_upload: Option<String>,
+    // Address of the client, used for limiting failed authentication attempts
+    address: Option<IpAddr>,
+}
+
+impl ClientState {
+    fn new(settings: Arc<ServerSettings>) -> Self {
+        ClientState {
+            role: settings.initial_role(),
+            subscriptions: Subscriptions::default(),
+            settings,
+            pending_upload: None,
+            address: None,
+        }
+    }
+}
+
+// Interval in ms for checking subscribed values for changes
+const EVENT_POLL_INTERVAL: u64 = 20;
+// Default interval in ms for meter events
+const DEFAULT_METER_INTERVAL: u64 = 100;
+// Number of failed authentication attempts from an address before they are delayed
+const AUTH_FREE_ATTEMPTS: u32 = 3;
+// Delay in ms after the first delayed failure, doubled for each further failure
+const AUTH_MIN_DELAY: u64 = 1000;
+// Longest delay in ms between authentication attempts
+const AUTH_MAX_DELAY: u64 = 60000;
+// Time in ms after the last failure when the failures of an address are forgotten
+const AUTH_FAILURE_RESET: u64 = 600000;
+
+lazy_static! {
+    // Held for writing while executing a batch, so that its commands run without
+    // interleaving commands from other connections. Single commands only take it for reading,
+    // and can run at the same time as each other.
+    static ref COMMAND_LOCK: RwLock<()> = RwLock::new(());
 }
 
 #[derive(Debug, PartialEq, Deserialize)]
 enum WsCommand {
+    Authenticate(String),
+    Subscribe {
+        event: WsEventType,
+        #[serde(default)]
+        interval: Option<u64>,
+    },
+    Unsubscribe(WsEventType),
+    GetStatus,
+    Batch(Vec<WsCommand>),
     SetConfigName(String),
     SetConfig(String),
     SetConfigJson(String),
     Reload,
     GetConfig,
     GetPreviousConfig,
+    GetConfigHistory,
+    RevertToConfig(usize),
+    Undo,
+    Redo,
+    SetConfigLabel {
+        #[serde(default)]
+        id: Option<usize>,
+        #[serde(default)]
+        label: Option<String>,
+    },
     ReadConfig(String),
     ReadConfigFile(String),
+    ListConfigFiles,
+    GetConfigFile(String),
+    SaveConfigFile {
+        name: String,
+        config: String,
+    },
+    RenameConfigFile {
+        name: String,
+        new_name: String,
+    },
+    DeleteConfigFile(String),
+    LoadConfigFile(String),
+    GetLastPreset,
+    ListCoeffFiles,
+    UploadCoeffFile {
+        name: String,
+        #[serde(default)]
+        data: Option<String>,
+    },
+    DeleteCoeffFile(String),
+    #[serde(skip)]
+    Binary(Vec<u8>),
     ValidateConfig(String),
     GetConfigJson,
     GetConfigName,
@@ -70,8 +536,28 @@ enum WsCommand {
     SetUpdateInterval(usize),
     GetVolume,
     SetVolume(f32),
+    AdjustVolume(f32),
+    GetVolumePercent,
+    SetVolumePercent(f32),
     GetMute,
     SetMute(bool),
+    ToggleMute,
+    GetFaders,
+    GetFaderVolume(String),
+    SetFaderVolume {
+        name: String,
+        volume: f32,
+    },
+    AdjustFaderVolume {
+        name: String,
+        step: f32,
+    },
+    GetFaderMute(String),
+    SetFaderMute {
+        name: String,
+        mute: bool,
+    },
+    ToggleFaderMute(String),
     GetVersion,
     GetState,
     GetStopReason,
@@ -79,6 +565,28 @@ enum WsCommand {
     GetClippedSamples,
     GetBufferLevel,
     GetSupportedDeviceTypes,
+    SetTilt {
+        name: String,
+        gain: PrcFmt,
+    },
+    SetFilterParameters {
+        name: String,
+        parameters: serde_json::Value,
+    },
+    SetMixerMapping {
+        name: String,
+        mapping: serde_json::Value,
+    },
+    SetPipelineStep {
+        index: usize,
+        step: serde_json::Value,
+    },
+    PatchConfig(serde_json::Value),
+    SetToneControls {
+        name: String,
+        bass: PrcFmt,
+        treble: PrcFmt,
+    },
     Exit,
     Stop,
     None,
@@ -90,8 +598,188 @@ enum WsResult {
     Error,
 }
 
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize)]
+enum WsEventType {
+    State,
+    StopReason,
+    Config,
+    Volume,
+    Meters,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+enum WsEvent {
+    State(ProcessingState),
+    StopReason(StopReason),
+    Config(Option<String>),
+    Volume {
+        volume: f32,
+        mute: bool,
+        faders: HashMap<String, FaderState>,
+    },
+    Meters {
+        capture_rms: Vec<f32>,
+        capture_peak: Vec<f32>,
+        playback_rms: Vec<f32>,
+        playback_peak: Vec<f32>,
+    },
+}
+
+impl WsReply {
+    /// Check if a reply reports a failed command.
+    fn is_error(&self) -> bool {
+        match self {
+            WsReply::ListCoeffFiles { result, .. }
+            | WsReply::UploadCoeffFile { result, .. }
+            | WsReply::DeleteCoeffFile { result, .. }
+            | WsReply::ListConfigFiles { result, .. }
+            | WsReply::GetConfigFile { result, .. }
+            | WsReply::SaveConfigFile { result, .. }
+            | WsReply::RenameConfigFile { result, .. }
+            | WsReply::DeleteConfigFile { result, .. }
+            | WsReply::LoadConfigFile { result, .. }
+            | WsReply::GetLastPreset { result, .. }
+            | WsReply::Authenticate { result, .. }
+            | WsReply::GetStatus { result, .. }
+            | WsReply::Batch { result, .. }
+            | WsReply::Subscribe { result, .. }
+            | WsReply::Unsubscribe { result, .. }
+            | WsReply::SetConfigName { result, .. }
+            | WsReply::SetConfig { result, .. }
+            | WsReply::SetConfigJson { result, .. }
+            | WsReply::Reload { result, .. }
+            | WsReply::GetConfig { result, .. }
+            | WsReply::GetPreviousConfig { result, .. }
+            | WsReply::GetConfigHistory { result, .. }
+            | WsReply::RevertToConfig { result, .. }
+            | WsReply::Undo { result, .. }
+            | WsReply::Redo { result, .. }
+            | WsReply::SetConfigLabel { result, .. }
+            | WsReply::ReadConfig { result, .. }
+            | WsReply::ReadConfigFile { result, .. }
+            | WsReply::ValidateConfig { result, .. }
+            | WsReply::GetConfigJson { result, .. }
+            | WsReply::GetConfigName { result, .. }
+            | WsReply::GetSignalRange { result, .. }
+            | WsReply::GetPlaybackSignalRms { result, .. }
+            | WsReply::GetPlaybackSignalPeak { result, .. }
+            | WsReply::GetCaptureSignalRms { result, .. }
+            | WsReply::GetCaptureSignalPeak { result, .. }
+            | WsReply::GetCaptureRate { result, .. }
+            | WsReply::GetUpdateInterval { result, .. }
+            | WsReply::SetUpdateInterval { result, .. }
+            | WsReply::SetVolume { result, .. }
+            | WsReply::GetVolume { result, .. }
+            | WsReply::AdjustVolume { result, .. }
+            | WsReply::GetVolumePercent { result, .. }
+            | WsReply::SetVolumePercent { result, .. }
+            | WsReply::SetMute { result, .. }
+            | WsReply::GetMute { result, .. }
+            | WsReply::ToggleMute { result, .. }
+            | WsReply::GetFaders { result, .. }
+            | WsReply::GetFaderVolume { result, .. }
+            | WsReply::SetFaderVolume { result, .. }
+            | WsReply::AdjustFaderVolume { result, .. }
+            | WsReply::GetFaderMute { result, .. }
+            | WsReply::SetFaderMute { result, .. }
+            | WsReply::ToggleFaderMute { result, .. }
+            | WsReply::GetVersion { result, .. }
+            | WsReply::GetState { result, .. }
+            | WsReply::GetStopReason { result, .. }
+            | WsReply::GetRateAdjust { result, .. }
+            | WsReply::GetBufferLevel { result, .. }
+            | WsReply::GetClippedSamples { result, .. }
+            | WsReply::GetSupportedDeviceTypes { result, .. }
+            | WsReply::SetTilt { result, .. }
+            | WsReply::SetToneControls { result, .. }
+            | WsReply::SetFilterParameters { result, .. }
+            | WsReply::SetMixerMapping { result, .. }
+            | WsReply::SetPipelineStep { result, .. }
+            | WsReply::PatchConfig { result, .. }
+            | WsReply::Exit { result, .. }
+            | WsReply::Stop { result, .. } => *result == WsResult::Error,
+            WsReply::Invalid { .. } | WsReply::Unauthorized { .. } => true,
+            WsReply::Event(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct WsStatus {
+    state: ProcessingState,
+    stop_reason: StopReason,
+    capture_rate: usize,
+    rate_adjust: f32,
+    signal_range: f32,
+    buffer_level: usize,
+    clipped_samples: usize,
+    volume: f32,
+    mute: bool,
+    capture_signal_rms: Vec<f32>,
+    capture_signal_peak: Vec<f32>,
+    playback_signal_rms: Vec<f32>,
+    playback_signal_peak: Vec<f32>,
+}
+
 #[derive(Debug, PartialEq, Serialize)]
 enum WsReply {
+    ListCoeffFiles {
+        result: WsResult,
+        value: Vec<CoeffFileInfo>,
+    },
+    UploadCoeffFile {
+        result: WsResult,
+        value: Option<CoeffFileInfo>,
+    },
+    DeleteCoeffFile {
+        result: WsResult,
+    },
+    ListConfigFiles {
+        result: WsResult,
+        value: Vec<String>,
+    },
+    GetConfigFile {
+        result: WsResult,
+        value: String,
+    },
+    SaveConfigFile {
+        result: WsResult,
+    },
+    RenameConfigFile {
+        result: WsResult,
+    },
+    DeleteConfigFile {
+        result: WsResult,
+    },
+    LoadConfigFile {
+        result: WsResult,
+    },
+    GetLastPreset {
+        result: WsResult,
+        value: Option<String>,
+    },
+    Authenticate {
+        result: WsResult,
+        value: ClientRole,
+    },
+    Unauthorized {
+        error: String,
+    },
+    GetStatus {
+        result: WsResult,
+        value: WsStatus,
+    },
+    Batch {
+        result: WsResult,
+        value: Vec<WsReply>,
+    },
+    Subscribe {
+        result: WsResult,
+    },
+    Unsubscribe {
+        result: WsResult,
+    },
+    Event(WsEvent),
     SetConfigName {
         result: WsResult,
     },
@@ -112,6 +800,22 @@ enum WsReply {
         result: WsResult,
         value: String,
     },
+    GetConfigHistory {
+        result: WsResult,
+        value: Vec<HistoryEntryInfo>,
+    },
+    RevertToConfig {
+        result: WsResult,
+    },
+    Undo {
+        result: WsResult,
+    },
+    Redo {
+        result: WsResult,
+    },
+    SetConfigLabel {
+        result: WsResult,
+    },
     ReadConfig {
         result: WsResult,
         value: String,
@@ -170,6 +874,17 @@ enum WsReply {
         result: WsResult,
         value: f32,
     },
+    AdjustVolume {
+        result: WsResult,
+        value: f32,
+    },
+    GetVolumePercent {
+        result: WsResult,
+        value: f32,
+    },
+    SetVolumePercent {
+        result: WsResult,
+    },
     SetMute {
         result: WsResult,
     },
@@ -177,6 +892,36 @@ enum WsReply {
         result: WsResult,
         value: bool,
     },
+    ToggleMute {
+        result: WsResult,
+        value: bool,
+    },
+    GetFaders {
+        result: WsResult,
+        value: HashMap<String, FaderState>,
+    },
+    GetFaderVolume {
+        result: WsResult,
+        value: f32,
+    },
+    SetFaderVolume {
+        result: WsResult,
+    },
+    AdjustFaderVolume {
+        result: WsResult,
+        value: f32,
+    },
+    GetFaderMute {
+        result: WsResult,
+        value: bool,
+    },
+    SetFaderMute {
+        result: WsResult,
+    },
+    ToggleFaderMute {
+        result: WsResult,
+        value: bool,
+    },
     GetVersion {
         result: WsResult,
         value: String,
@@ -205,6 +950,24 @@ enum WsReply {
         result: WsResult,
         value: (Vec<String>, Vec<String>),
     },
+    SetTilt {
+        result: WsResult,
+    },
+    SetToneControls {
+        result: WsResult,
+    },
+    SetFilterParameters {
+        result: WsResult,
+    },
+    SetMixerMapping {
+        result: WsResult,
+    },
+    SetPipelineStep {
+        result: WsResult,
+    },
+    PatchConfig {
+        result: WsResult,
+    },
     Exit {
         result: WsResult,
     },
@@ -216,12 +979,151 @@ enum WsReply {
     },
 }
 
+/// The event subscriptions of a websocket connection.
+/// The last value sent for each event type is kept, so that events are only sent on changes.
+/// Meters are sent at the requested interval.
+#[derive(Debug, Default)]
+struct Subscriptions {
+    events: HashSet<WsEventType>,
+    meter_interval: Duration,
+    last_meters: Option<Instant>,
+    state: Option<ProcessingState>,
+    stop_reason: Option<StopReason>,
+    // Generation of the active config when the last config event was sent
+    config: Option<usize>,
+    volume: Option<(f32, bool, HashMap<String, FaderState>)>,
+}
+
+impl Subscriptions {
+    /// Subscribe to an event type. The current value is sent at the next poll.
+    fn subscribe(&mut self, event: WsEventType, interval: Option<u64>) -> Res<()> {
+        match (event, interval) {
+            (WsEventType::Meters, interval) => {
+                let interval = interval.unwrap_or(DEFAULT_METER_INTERVAL);
+                if interval < EVENT_POLL_INTERVAL {
+                    let msg = format!("Meter interval must be at least {} ms", EVENT_POLL_INTERVAL);
+                    return Err(config::ConfigError::new(&msg).into());
+                }
+                self.meter_interval = Duration::from_millis(interval);
+                self.last_meters = None;
+            }
+            (_, Some(_)) => {
+                return Err(
+                    config::ConfigError::new("An interval can only be given for meters").into(),
+                );
+            }
+            (WsEventType::State, None) => self.state = None,
+            (WsEventType::StopReason, None) => self.stop_reason = None,
+            (WsEventType::Config, None) => self.config = None,
+            (WsEventType::Volume, None) => self.volume = None,
+        }
+        self.events.insert(event);
+        Ok(())
+    }
+
+    fn unsubscribe(&mut self, event: WsEventType) {
+        self.events.remove(&event);
+    }
+
+    /// Get the read timeout to use for the connection.
+    /// Reads block without timeout when there are no subscriptions.
+    fn read_timeout(&self) -> Option<Duration> {
+        if self.events.is_empty() {
+            None
+        } else {
+            Some(Duration::from_millis(EVENT_POLL_INTERVAL))
+        }
+    }
+
+    /// Check the subscribed values, and return events for the ones that changed.
+    fn poll(&mut self, shared_data_inst: &SharedData) -> Vec<WsEvent> {
+        let mut events = Vec::new();
+        if self.events.contains(&WsEventType::State) {
+            let state = shared_data_inst.capture_status.read().unwrap().state;
+            if self.state != Some(state) {
+                self.state = Some(state);
+                events.push(WsEvent::State(state));
+            }
+        }
+        if self.events.contains(&WsEventType::StopReason) {
+            let stop_reason = shared_data_inst.status.read().unwrap().stop_reason.clone();
+            if self.stop_reason.as_ref() != Some(&stop_reason) {
+                self.stop_reason = Some(stop_reason.clone());
+                events.push(WsEvent::StopReason(stop_reason));
+            }
+        }
+        if self.events.contains(&WsEventType::Config) {
+            let generation = shared_data_inst.config_generation.load(Ordering::Relaxed);
+            if self.config != Some(generation) {
+                self.config = Some(generation);
+                let path = shared_data_inst.active_config_path.lock().unwrap().clone();
+                events.push(WsEvent::Config(path));
+            }
+        }
+        if self.events.contains(&WsEventType::Volume) {
+            let procstat = shared_data_inst.processing_status.read().unwrap();
+            let volume = (procstat.volume, procstat.mute, procstat.faders.clone());
+            if self.volume.as_ref() != Some(&volume) {
+                events.push(WsEvent::Volume {
+                    volume: volume.0,
+                    mute: volume.1,
+                    faders: volume.2.clone(),
+                });
+                self.volume = Some(volume);
+            }
+        }
+        if self.events.contains(&WsEventType::Meters)
+            && self
+                .last_meters
+                .map(|time| time.elapsed() >= self.meter_interval)
+                .unwrap_or(true)
+        {
+            self.last_meters = Some(Instant::now());
+            let capstat = shared_data_inst.capture_status.read().unwrap();
+            let pbstat = shared_data_inst.playback_status.read().unwrap();
+            events.push(WsEvent::Meters {
+                capture_rms: capstat.signal_rms.clone(),
+                capture_peak: capstat.signal_peak.clone(),
+                playback_rms: pbstat.signal_rms.clone(),
+                playback_peak: pbstat.signal_peak.clone(),
+            });
+        }
+        events
+    }
+}
+
+/// Access to the tcp stream below a websocket stream, for setting socket options.
+trait TcpStreamRef {
+    fn tcp_stream(&self) -> &TcpStream;
+}
+
+impl TcpStreamRef for TcpStream {
+    fn tcp_stream(&self) -> &TcpStream {
+        self
+    }
+}
+
+#[cfg(feature = "secure-websocket")]
+impl TcpStreamRef for TlsStream<TcpStream> {
+    fn tcp_stream(&self) -> &TcpStream {
+        self.get_ref()
+    }
+}
+
+fn is_timeout(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
+}
+
 fn parse_command(cmd: Message) -> Res<WsCommand> {
     match cmd {
         Message::Text(command_str) => {
             let command = serde_json::from_str::<WsCommand>(&command_str)?;
             Ok(command)
         }
+        Message::Binary(data) => Ok(WsCommand::Binary(data)),
         _ => Ok(WsCommand::None),
     }
 }
@@ -260,12 +1162,20 @@ pub fn start_server(parameters: ServerParameters, shared_data: SharedData) {
     debug!("Start websocket server on {}:{}", address, parameters.port);
     #[cfg(feature = "secure-websocket")]
     let acceptor = make_acceptor(&parameters.cert_file, &parameters.cert_pass);
+    let settings = match ServerSettings::from_parameters(&parameters) {
+        Ok(settings) => Arc::new(settings),
+        Err(err) => {
+            error!("Failed to start websocket server: {}", err);
+            return;
+        }
+    };
 
     thread::spawn(move || {
         let ws_result = TcpListener::bind(format!("{}:{}", address, port));
         if let Ok(server) = ws_result {
             for stream in server.incoming() {
                 let shared_data_inst = shared_data.clone();
+                let settings_inst = settings.clone();
                 #[cfg(feature = "secure-websocket")]
                 let acceptor_inst = acceptor.clone();
 
@@ -273,17 +1183,17 @@ pub fn start_server(parameters: ServerParameters, shared_data: SharedData) {
                 thread::spawn(move || match acceptor_inst {
                     None => {
                         let websocket_res = accept_plain_stream(stream);
-                        handle_tcp(websocket_res, &shared_data_inst);
+                        handle_tcp(websocket_res, &shared_data_inst, settings_inst);
                     }
                     Some(acc) => {
                         let websocket_res = accept_secure_stream(acc, stream);
-                        handle_tls(websocket_res, &shared_data_inst);
+                        handle_tls(websocket_res, &shared_data_inst, settings_inst);
                     }
                 });
                 #[cfg(not(feature = "secure-websocket"))]
                 thread::spawn(move || {
                     let websocket_res = accept_plain_stream(stream);
-                    handle_tcp(websocket_res, &shared_data_inst);
+                    handle_tcp(websocket_res, &shared_data_inst, settings_inst);
                 });
             }
         } else if let Err(err) = ws_result {
@@ -294,43 +1204,73 @@ pub fn start_server(parameters: ServerParameters, shared_data: SharedData) {
 
 macro_rules! make_handler {
     ($t:ty, $n:ident) => {
-        fn $n(websocket_res: Res<WebSocket<$t>>, shared_data_inst: &SharedData) {
+        fn $n(
+            websocket_res: Res<WebSocket<$t>>,
+            shared_data_inst: &SharedData,
+            settings: Arc<ServerSettings>,
+        ) {
             match websocket_res {
-                Ok(mut websocket) => loop {
-                    let msg_res = websocket.read_message();
-                    match msg_res {
-                        Ok(msg) => {
-                            trace!("received: {:?}", msg);
-                            let command = parse_command(msg);
-                            debug!("parsed command: {:?}", command);
-                            let reply = match command {
-                                Ok(cmd) => handle_command(cmd, &shared_data_inst),
-                                Err(err) => Some(WsReply::Invalid {
-                                    error: err.to_string(),
-                                }),
-                            };
-                            if let Some(rep) = reply {
-                                let write_result = websocket.write_message(Message::text(
-                                    serde_json::to_string(&rep).unwrap(),
-                                ));
-                                if let Err(err) = write_result {
-                                    warn!("Failed to write: {}", err);
-                                    break;
+                Ok(mut websocket) => {
+                    let mut client = ClientState::new(settings);
+                    client.address = websocket
+                        .get_ref()
+                        .tcp_stream()
+                        .peer_addr()
+                        .ok()
+                        .map(|addr| addr.ip());
+                    'connection: loop {
+                        let msg_res = websocket.read_message();
+                        match msg_res {
+                            Ok(msg) => {
+                                trace!("received: {:?}", msg);
+                                let command = parse_command(msg);
+                                debug!("parsed command: {:?}", command);
+                                let reply = match command {
+                                    Ok(cmd) => execute_command(cmd, &shared_data_inst, &mut client),
+                                    Err(err) => Some(WsReply::Invalid {
+                                        error: err.to_string(),
+                                    }),
+                                };
+                                if let Some(rep) = reply {
+                                    let write_result = websocket.write_message(Message::text(
+                                        serde_json::to_string(&rep).unwrap(),
+                                    ));
+                                    if let Err(err) = write_result {
+                                        warn!("Failed to write: {}", err);
+                                        break;
+                                    }
+                                } else {
+                                    debug!("Sending no reply");
+                                }
+                                if let Err(err) = websocket
+                                    .get_ref()
+                                    .tcp_stream()
+                                    .set_read_timeout(client.subscriptions.read_timeout())
+                                {
+                                    warn!("Failed to set read timeout: {}", err);
                                 }
-                            } else {
-                                debug!("Sending no reply");
+                            }
+                            Err(tungstenite::error::Error::Io(err)) if is_timeout(&err) => {}
+                            Err(tungstenite::error::Error::ConnectionClosed) => {
+                                debug!("Connection was closed");
+                                break;
+                            }
+                            Err(err) => {
+                                warn!("Lost connection: {}", err);
+                                break;
                             }
                         }
-                        Err(tungstenite::error::Error::ConnectionClosed) => {
-                            debug!("Connection was closed");
-                            break;
-                        }
-                        Err(err) => {
-                            warn!("Lost connection: {}", err);
-                            break;
+                        for event in client.subscriptions.poll(&shared_data_inst) {
+                            let write_result = websocket.write_message(Message::text(
+                                serde_json::to_string(&WsReply::Event(event)).unwrap(),
+                            ));
+                            if let Err(err) = write_result {
+                                warn!("Failed to write: {}", err);
+                                break 'connection;
+                            }
                         }
                     }
-                },
+                }
                 Err(err) => warn!("Connection failed: {}", err),
             };
         }
@@ -357,8 +1297,384 @@ fn accept_plain_stream(
     Ok(ws)
 }
 
-fn handle_command(command: WsCommand, shared_data_inst: &SharedData) -> Option<WsReply> {
+/// Apply a change to the active config, and load the result as a new config.
+/// If a new config is waiting to be loaded, the change is applied to that one instead,
+/// so that quick successive changes build on each other.
+/// The pending config stays locked until the change is stored, so that a change made at the same time
+/// by another client isn't lost.
+/// The reload compares the configs with `config_diff`, so changes that only affect
+/// filter or mixer parameters are applied without a restart.
+fn update_active_config<F>(shared_data_inst: &SharedData, update: F) -> Res<()>
+where
+    F: FnOnce(&mut config::Configuration) -> Res<()>,
+{
+    let mut pending_config = shared_data_inst.new_config.lock().unwrap();
+    let mut conf = match pending_config
+        .clone()
+        .or_else(|| shared_data_inst.active_config.lock().unwrap().clone())
+    {
+        Some(conf) => conf,
+        None => return Err(config::ConfigError::new("No active config").into()),
+    };
+    update(&mut conf)?;
+    config::validate_config(&mut conf, None)?;
+    *pending_config = Some(conf);
+    shared_data_inst
+        .signal_reload
+        .store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Apply a change to a filter of the active config, and load the result as a new config.
+/// Only the parameters of the changed filter differ, so the filter is updated without a restart.
+/// A change is made to the config that is waiting to be loaded, if there is one,
+/// so that quick changes to several filters don't replace each other.
+fn update_active_filter<F>(shared_data_inst: &SharedData, name: &str, update: F) -> Res<()>
+where
+    F: FnOnce(&mut config::Filter) -> Res<()>,
+{
+    update_active_config(shared_data_inst, |conf| match conf.filters.get_mut(name) {
+        Some(filter) => update(filter),
+        None => {
+            let msg = format!("No filter named '{}' in the active config", name);
+            Err(config::ConfigError::new(&msg).into())
+        }
+    })
+}
+
+/// Apply a JSON merge patch (RFC 7386) to a value.
+/// Objects are merged recursively, null values remove fields, and anything else replaces the target.
+fn merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    if let serde_json::Value::Object(patch_map) = patch {
+        if !target.is_object() {
+            *target = serde_json::Value::Object(serde_json::Map::new());
+        }
+        let target_map = target.as_object_mut().unwrap();
+        for (key, value) in patch_map {
+            if value.is_null() {
+                target_map.remove(key);
+            } else {
+                merge_patch(
+                    target_map
+                        .entry(key.clone())
+                        .or_insert(serde_json::Value::Null),
+                    value,
+                );
+            }
+        }
+    } else {
+        *target = patch.clone();
+    }
+}
+
+/// Apply a merge patch to the part of a config given by a JSON pointer, like "/filters/lowpass".
+fn patch_config(
+    conf: &mut config::Configuration,
+    pointer: &str,
+    patch: &serde_json::Value,
+) -> Res<()> {
+    let mut value = serde_json::to_value(&*conf)?;
+    match value.pointer_mut(pointer) {
+        Some(target) => merge_patch(target, patch),
+        None => {
+            let msg = format!("Nothing to patch at '{}' in the active config", pointer);
+            return Err(config::ConfigError::new(&msg).into());
+        }
+    }
+    *conf = serde_json::from_value(value)?;
+    Ok(())
+}
+
+/// Get the result of a config change for a reply, logging any error.
+fn change_result(result: Res<()>, description: &str) -> WsResult {
+    match result {
+        Ok(()) => WsResult::Ok,
+        Err(error) => {
+            error!("Error {}: {}", description, error);
+            WsResult::Error
+        }
+    }
+}
+
+/// Apply a config from the config history, together with the config file path it was loaded from.
+fn apply_history_entry(
+    shared_data_inst: &SharedData,
+    entry: Res<(config::Configuration, Option<String>)>,
+) -> Res<()> {
+    let (conf, path) = entry?;
+    *shared_data_inst.active_config_path.lock().unwrap() = path;
+    *shared_data_inst.new_config.lock().unwrap() = Some(conf);
+    shared_data_inst
+        .signal_reload
+        .store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Get the volume control settings of the active config, or the defaults if no config is loaded.
+fn volume_settings(shared_data_inst: &SharedData) -> config::VolumeSettings {
+    match &*shared_data_inst.active_config.lock().unwrap() {
+        Some(conf) => conf.volume.clone(),
+        None => config::VolumeSettings::default(),
+    }
+}
+
+/// Read all status values in one go.
+fn get_status(shared_data_inst: &SharedData) -> WsStatus {
+    let capstat = shared_data_inst.capture_status.read().unwrap().clone();
+    let pbstat = shared_data_inst.playback_status.read().unwrap().clone();
+    let procstat = shared_data_inst.processing_status.read().unwrap().clone();
+    let stop_reason = shared_data_inst.status.read().unwrap().stop_reason.clone();
+    WsStatus {
+        state: capstat.state,
+        stop_reason,
+        capture_rate: capstat.measured_samplerate,
+        rate_adjust: capstat.rate_adjust,
+        signal_range: capstat.signal_range,
+        buffer_level: pbstat.buffer_level,
+        clipped_samples: pbstat.clipped_samples,
+        volume: procstat.volume,
+        mute: procstat.mute,
+        capture_signal_rms: capstat.signal_rms,
+        capture_signal_peak: capstat.signal_peak,
+        playback_signal_rms: pbstat.signal_rms,
+        playback_signal_peak: pbstat.signal_peak,
+    }
+}
+
+/// Get the role a client needs for running a command.
+/// Commands that only read values need a monitor, everything else needs a controller.
+fn required_role(command: &WsCommand) -> ClientRole {
+    match command {
+        WsCommand::Authenticate(_) | WsCommand::None => ClientRole::Unauthenticated,
+        // Only accepted after an upload command, that needs a controller
+        WsCommand::Binary(_) => ClientRole::Unauthenticated,
+        WsCommand::Batch(commands) => commands
+            .iter()
+            .map(required_role)
+            .max()
+            .unwrap_or(ClientRole::Unauthenticated),
+        WsCommand::Subscribe { .. }
+        | WsCommand::Unsubscribe(_)
+        | WsCommand::GetStatus
+        | WsCommand::GetConfig
+        | WsCommand::GetPreviousConfig
+        | WsCommand::GetConfigHistory
+        | WsCommand::ReadConfig(_)
+        | WsCommand::ReadConfigFile(_)
+        | WsCommand::ListConfigFiles
+        | WsCommand::GetLastPreset
+        | WsCommand::ListCoeffFiles
+        | WsCommand::GetConfigFile(_)
+        | WsCommand::ValidateConfig(_)
+        | WsCommand::GetConfigJson
+        | WsCommand::GetConfigName
+        | WsCommand::GetSignalRange
+        | WsCommand::GetCaptureSignalRms
+        | WsCommand::GetCaptureSignalPeak
+        | WsCommand::GetPlaybackSignalRms
+        | WsCommand::GetPlaybackSignalPeak
+        | WsCommand::GetCaptureRate
+        | WsCommand::GetUpdateInterval
+        | WsCommand::GetVolume
+        | WsCommand::GetVolumePercent
+        | WsCommand::GetMute
+        | WsCommand::GetFaders
+        | WsCommand::GetFaderVolume(_)
+        | WsCommand::GetFaderMute(_)
+        | WsCommand::GetVersion
+        | WsCommand::GetState
+        | WsCommand::GetStopReason
+        | WsCommand::GetRateAdjust
+        | WsCommand::GetClippedSamples
+        | WsCommand::GetBufferLevel
+        | WsCommand::GetSupportedDeviceTypes => ClientRole::Monitor,
+        _ => ClientRole::Controller,
+    }
+}
+
+/// Check that the client is allowed to run a command, and execute it while holding the command lock.
+fn execute_command(
+    command: WsCommand,
+    shared_data_inst: &SharedData,
+    client: &mut ClientState,
+) -> Option<WsReply> {
+    let role = required_role(&command);
+    if role > client.role {
+        warn!(
+            "Rejected command {:?} from client with role {:?}",
+            command, client.role
+        );
+        return Some(WsReply::Unauthorized {
+            error: format!("This command requires the {:?} role", role),
+        });
+    }
+    // The lock doesn't protect any data, so it is fine to keep using it after a panic in a command
+    if matches!(command, WsCommand::Batch(_)) {
+        let _guard = COMMAND_LOCK.write().unwrap_or_else(|e| e.into_inner());
+        handle_command(command, shared_data_inst, client)
+    } else {
+        let _guard = COMMAND_LOCK.read().unwrap_or_else(|e| e.into_inner());
+        handle_command(command, shared_data_inst, client)
+    }
+}
+
+/// Check if a command can be part of a batch.
+/// Commands that don't reply immediately, or that continue in a following message, can't.
+fn allowed_in_batch(command: &WsCommand) -> bool {
+    !matches!(
+        command,
+        WsCommand::Batch(_)
+            | WsCommand::Binary(_)
+            | WsCommand::None
+            | WsCommand::UploadCoeffFile { data: None, .. }
+    )
+}
+
+/// Check a command of a batch before any command of the batch is executed.
+/// The role of the client is already checked for the whole batch.
+/// Faders, filters, mixers and files that a command uses must exist when the batch starts.
+fn check_batch_command(
+    command: &WsCommand,
+    shared_data_inst: &SharedData,
+    client: &ClientState,
+) -> Res<()> {
+    if !allowed_in_batch(command) {
+        let msg = format!("A batch can not contain the command {:?}", command);
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    match command {
+        WsCommand::GetFaderVolume(name)
+        | WsCommand::SetFaderVolume { name, .. }
+        | WsCommand::AdjustFaderVolume { name, .. }
+        | WsCommand::GetFaderMute(name)
+        | WsCommand::SetFaderMute { name, .. }
+        | WsCommand::ToggleFaderMute(name) => {
+            let procstat = shared_data_inst.processing_status.read().unwrap();
+            if !procstat.faders.contains_key(name) {
+                let msg = format!("No volume fader named '{}'", name);
+                return Err(config::ConfigError::new(&msg).into());
+            }
+        }
+        WsCommand::SetTilt { name, .. }
+        | WsCommand::SetToneControls { name, .. }
+        | WsCommand::SetFilterParameters { name, .. }
+        | WsCommand::SetMixerMapping { name, .. } => {
+            let pending_config = shared_data_inst.new_config.lock().unwrap().clone();
+            let conf = pending_config
+                .or_else(|| shared_data_inst.active_config.lock().unwrap().clone())
+                .ok_or_else(|| config::ConfigError::new("No active config"))?;
+            let exists = if matches!(command, WsCommand::SetMixerMapping { .. }) {
+                conf.mixers.contains_key(name)
+            } else {
+                conf.filters.contains_key(name)
+            };
+            if !exists {
+                let msg = format!("No filter or mixer named '{}'", name);
+                return Err(config::ConfigError::new(&msg).into());
+            }
+        }
+        WsCommand::GetConfigFile(name)
+        | WsCommand::RenameConfigFile { name, .. }
+        | WsCommand::DeleteConfigFile(name)
+        | WsCommand::LoadConfigFile(name) => {
+            let path = client.settings.library_path(name)?;
+            if !path.is_file() {
+                let msg = format!("No config file named '{}'", name);
+                return Err(config::ConfigError::new(&msg).into());
+            }
+        }
+        WsCommand::DeleteCoeffFile(name) => {
+            let path = client.settings.coeff_path(name)?;
+            if !path.is_file() {
+                let msg = format!("No coefficient file named '{}'", name);
+                return Err(config::ConfigError::new(&msg).into());
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_command(
+    command: WsCommand,
+    shared_data_inst: &SharedData,
+    client: &mut ClientState,
+) -> Option<WsReply> {
     match command {
+        WsCommand::Authenticate(token) => {
+            match client.settings.authenticate(&token, client.address) {
+                Ok(role) => {
+                    client.role = client.role.max(role);
+                    Some(WsReply::Authenticate {
+                        result: WsResult::Ok,
+                        value: client.role,
+                    })
+                }
+                Err(reason) => {
+                    warn!(
+                        "Authentication from {:?} failed: {}",
+                        client.address, reason
+                    );
+                    Some(WsReply::Authenticate {
+                        result: WsResult::Error,
+                        value: client.role,
+                    })
+                }
+            }
+        }
+        WsCommand::Subscribe { event, interval } => {
+            match client.subscriptions.subscribe(event, interval) {
+                Ok(()) => Some(WsReply::Subscribe {
+                    result: WsResult::Ok,
+                }),
+                Err(err) => {
+                    error!("Invalid subscription: {}", err);
+                    Some(WsReply::Subscribe {
+                        result: WsResult::Error,
+                    })
+                }
+            }
+        }
+        WsCommand::Unsubscribe(event) => {
+            client.subscriptions.unsubscribe(event);
+            Some(WsReply::Unsubscribe {
+                result: WsResult::Ok,
+            })
+        }
+        WsCommand::GetStatus => Some(WsReply::GetStatus {
+            result: WsResult::Ok,
+            value: get_status(shared_data_inst),
+        }),
+        WsCommand::Batch(commands) => {
+            let checked: Res<()> = commands
+                .iter()
+                .try_for_each(|cmd| check_batch_command(cmd, shared_data_inst, client));
+            if let Err(err) = checked {
+                error!("Invalid batch: {}", err);
+                return Some(WsReply::Batch {
+                    result: WsResult::Error,
+                    value: Vec::new(),
+                });
+            }
+            // Every command that is allowed in a batch gives a reply.
+            // The batch stops at the first failing command, so that the rest isn't applied.
+            let mut replies = Vec::new();
+            let mut result = WsResult::Ok;
+            for cmd in commands {
+                if let Some(reply) = handle_command(cmd, shared_data_inst, client) {
+                    let failed = reply.is_error();
+                    replies.push(reply);
+                    if failed {
+                        result = WsResult::Error;
+                        break;
+                    }
+                }
+            }
+            Some(WsReply::Batch {
+                result,
+                value: replies,
+            })
+        }
         WsCommand::Reload => {
             shared_data_inst
                 .signal_reload
@@ -479,12 +1795,38 @@ fn handle_command(command: WsCommand, shared_data_inst: &SharedData) -> Option<W
             })
         }
         WsCommand::SetVolume(nbr) => {
+            let settings = volume_settings(shared_data_inst);
             let mut procstat = shared_data_inst.processing_status.write().unwrap();
-            procstat.volume = nbr;
+            procstat.volume = volumecontrol::clamp_volume(&settings, nbr);
             Some(WsReply::SetVolume {
                 result: WsResult::Ok,
             })
         }
+        WsCommand::AdjustVolume(step) => {
+            let settings = volume_settings(shared_data_inst);
+            let mut procstat = shared_data_inst.processing_status.write().unwrap();
+            procstat.volume = volumecontrol::clamp_volume(&settings, procstat.volume + step);
+            Some(WsReply::AdjustVolume {
+                result: WsResult::Ok,
+                value: procstat.volume,
+            })
+        }
+        WsCommand::GetVolumePercent => {
+            let settings = volume_settings(shared_data_inst);
+            let procstat = shared_data_inst.processing_status.read().unwrap();
+            Some(WsReply::GetVolumePercent {
+                result: WsResult::Ok,
+                value: volumecontrol::volume_to_percent(&settings, procstat.volume),
+            })
+        }
+        WsCommand::SetVolumePercent(percent) => {
+            let settings = volume_settings(shared_data_inst);
+            let mut procstat = shared_data_inst.processing_status.write().unwrap();
+            procstat.volume = volumecontrol::percent_to_volume(&settings, percent);
+            Some(WsReply::SetVolumePercent {
+                result: WsResult::Ok,
+            })
+        }
         WsCommand::GetMute => {
             let procstat = shared_data_inst.processing_status.read().unwrap();
             Some(WsReply::GetMute {
@@ -499,6 +1841,127 @@ fn handle_command(command: WsCommand, shared_data_inst: &SharedData) -> Option<W
                 result: WsResult::Ok,
             })
         }
+        WsCommand::ToggleMute => {
+            let mut procstat = shared_data_inst.processing_status.write().unwrap();
+            procstat.mute = !procstat.mute;
+            Some(WsReply::ToggleMute {
+                result: WsResult::Ok,
+                value: procstat.mute,
+            })
+        }
+        WsCommand::GetFaders => {
+            let procstat = shared_data_inst.processing_status.read().unwrap();
+            Some(WsReply::GetFaders {
+                result: WsResult::Ok,
+                value: procstat.faders.clone(),
+            })
+        }
+        WsCommand::GetFaderVolume(name) => {
+            let procstat = shared_data_inst.processing_status.read().unwrap();
+            match procstat.faders.get(&name) {
+                Some(fader) => Some(WsReply::GetFaderVolume {
+                    result: WsResult::Ok,
+                    value: fader.volume,
+                }),
+                None => {
+                    error!("No volume fader named '{}'", name);
+                    Some(WsReply::GetFaderVolume {
+                        result: WsResult::Error,
+                        value: 0.0,
+                    })
+                }
+            }
+        }
+        WsCommand::SetFaderVolume { name, volume } => {
+            let settings = volume_settings(shared_data_inst);
+            let mut procstat = shared_data_inst.processing_status.write().unwrap();
+            match procstat.faders.get_mut(&name) {
+                Some(fader) => {
+                    fader.volume = volumecontrol::clamp_volume(&settings, volume);
+                    Some(WsReply::SetFaderVolume {
+                        result: WsResult::Ok,
+                    })
+                }
+                None => {
+                    error!("No volume fader named '{}'", name);
+                    Some(WsReply::SetFaderVolume {
+                        result: WsResult::Error,
+                    })
+                }
+            }
+        }
+        WsCommand::AdjustFaderVolume { name, step } => {
+            let settings = volume_settings(shared_data_inst);
+            let mut procstat = shared_data_inst.processing_status.write().unwrap();
+            match procstat.faders.get_mut(&name) {
+                Some(fader) => {
+                    fader.volume = volumecontrol::clamp_volume(&settings, fader.volume + step);
+                    Some(WsReply::AdjustFaderVolume {
+                        result: WsResult::Ok,
+                        value: fader.volume,
+                    })
+                }
+                None => {
+                    error!("No volume fader named '{}'", name);
+                    Some(WsReply::AdjustFaderVolume {
+                        result: WsResult::Error,
+                        value: 0.0,
+                    })
+                }
+            }
+        }
+        WsCommand::GetFaderMute(name) => {
+            let procstat = shared_data_inst.processing_status.read().unwrap();
+            match procstat.faders.get(&name) {
+                Some(fader) => Some(WsReply::GetFaderMute {
+                    result: WsResult::Ok,
+                    value: fader.mute,
+                }),
+                None => {
+                    error!("No volume fader named '{}'", name);
+                    Some(WsReply::GetFaderMute {
+                        result: WsResult::Error,
+                        value: false,
+                    })
+                }
+            }
+        }
+        WsCommand::SetFaderMute { name, mute } => {
+            let mut procstat = shared_data_inst.processing_status.write().unwrap();
+            match procstat.faders.get_mut(&name) {
+                Some(fader) => {
+                    fader.mute = mute;
+                    Some(WsReply::SetFaderMute {
+                        result: WsResult::Ok,
+                    })
+                }
+                None => {
+                    error!("No volume fader named '{}'", name);
+                    Some(WsReply::SetFaderMute {
+                        result: WsResult::Error,
+                    })
+                }
+            }
+        }
+        WsCommand::ToggleFaderMute(name) => {
+            let mut procstat = shared_data_inst.processing_status.write().unwrap();
+            match procstat.faders.get_mut(&name) {
+                Some(fader) => {
+                    fader.mute = !fader.mute;
+                    Some(WsReply::ToggleFaderMute {
+                        result: WsResult::Ok,
+                        value: fader.mute,
+                    })
+                }
+                None => {
+                    error!("No volume fader named '{}'", name);
+                    Some(WsReply::ToggleFaderMute {
+                        result: WsResult::Error,
+                        value: false,
+                    })
+                }
+            }
+        }
         WsCommand::GetConfig => Some(WsReply::GetConfig {
             result: WsResult::Ok,
             value: serde_yaml::to_string(&*shared_data_inst.active_config.lock().unwrap()).unwrap(),
@@ -508,6 +1971,47 @@ fn handle_command(command: WsCommand, shared_data_inst: &SharedData) -> Option<W
             value: serde_yaml::to_string(&*shared_data_inst.previous_config.lock().unwrap())
                 .unwrap(),
         }),
+        WsCommand::GetConfigHistory => Some(WsReply::GetConfigHistory {
+            result: WsResult::Ok,
+            value: shared_data_inst.config_history.lock().unwrap().list(),
+        }),
+        WsCommand::RevertToConfig(id) => {
+            let entry = shared_data_inst.config_history.lock().unwrap().revert(id);
+            Some(WsReply::RevertToConfig {
+                result: change_result(
+                    apply_history_entry(shared_data_inst, entry),
+                    "reverting config",
+                ),
+            })
+        }
+        WsCommand::Undo => {
+            let entry = shared_data_inst.config_history.lock().unwrap().undo();
+            Some(WsReply::Undo {
+                result: change_result(
+                    apply_history_entry(shared_data_inst, entry),
+                    "undoing config change",
+                ),
+            })
+        }
+        WsCommand::Redo => {
+            let entry = shared_data_inst.config_history.lock().unwrap().redo();
+            Some(WsReply::Redo {
+                result: change_result(
+                    apply_history_entry(shared_data_inst, entry),
+                    "redoing config change",
+                ),
+            })
+        }
+        WsCommand::SetConfigLabel { id, label } => {
+            let result = shared_data_inst
+                .config_history
+                .lock()
+                .unwrap()
+                .set_label(id, label);
+            Some(WsReply::SetConfigLabel {
+                result: change_result(result, "setting config label"),
+            })
+        }
         WsCommand::GetConfigJson => Some(WsReply::GetConfigJson {
             result: WsResult::Ok,
             value: serde_json::to_string(&*shared_data_inst.active_config.lock().unwrap()).unwrap(),
@@ -522,9 +2026,13 @@ fn handle_command(command: WsCommand, shared_data_inst: &SharedData) -> Option<W
                 .unwrap_or(&"NONE".to_string())
                 .to_string(),
         }),
-        WsCommand::SetConfigName(path) => match config::load_validate_config(&path) {
-            Ok(_) => {
-                *shared_data_inst.active_config_path.lock().unwrap() = Some(path.clone());
+        WsCommand::SetConfigName(path) => match client
+            .settings
+            .resolve_config_path(&path)
+            .and_then(|path| config::load_validate_config(&path).map(|_| path))
+        {
+            Ok(path) => {
+                *shared_data_inst.active_config_path.lock().unwrap() = Some(path);
                 Some(WsReply::SetConfigName {
                     result: WsResult::Ok,
                 })
@@ -605,7 +2113,11 @@ fn handle_command(command: WsCommand, shared_data_inst: &SharedData) -> Option<W
                 }
             }
         }
-        WsCommand::ReadConfigFile(path) => match config::load_config(&path) {
+        WsCommand::ReadConfigFile(path) => match client
+            .settings
+            .resolve_config_path(&path)
+            .and_then(|path| config::load_config(&path))
+        {
             Ok(conf) => Some(WsReply::ReadConfigFile {
                 result: WsResult::Ok,
                 value: serde_yaml::to_string(&conf).unwrap(),
@@ -618,6 +2130,182 @@ fn handle_command(command: WsCommand, shared_data_inst: &SharedData) -> Option<W
                 })
             }
         },
+        WsCommand::ListCoeffFiles => match client.settings.list_coeff_files() {
+            Ok(files) => Some(WsReply::ListCoeffFiles {
+                result: WsResult::Ok,
+                value: files,
+            }),
+            Err(error) => {
+                error!("Error listing coefficient files: {}", error);
+                Some(WsReply::ListCoeffFiles {
+                    result: WsResult::Error,
+                    value: Vec::new(),
+                })
+            }
+        },
+        WsCommand::UploadCoeffFile { name, data } => {
+            let result = match data {
+                Some(data) => base64::decode(&data)
+                    .map_err(|err| err.into())
+                    .and_then(|data| {
+                        unused_coeff_path(shared_data_inst, &client.settings, &name)?;
+                        client.settings.save_coeff_file(&name, &data)
+                    }),
+                None => {
+                    // The data follows in a binary message
+                    match unused_coeff_path(shared_data_inst, &client.settings, &name) {
+                        Ok(_) => {
+                            client.pending_upload = Some(name);
+                            return None;
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+            };
+            match result {
+                Ok(info) => Some(WsReply::UploadCoeffFile {
+                    result: WsResult::Ok,
+                    value: Some(info),
+                }),
+                Err(error) => {
+                    error!("Error uploading coefficient file: {}", error);
+                    Some(WsReply::UploadCoeffFile {
+                        result: WsResult::Error,
+                        value: None,
+                    })
+                }
+            }
+        }
+        WsCommand::Binary(data) => match client.pending_upload.take() {
+            Some(name) => match unused_coeff_path(shared_data_inst, &client.settings, &name)
+                .and_then(|_| client.settings.save_coeff_file(&name, &data))
+            {
+                Ok(info) => Some(WsReply::UploadCoeffFile {
+                    result: WsResult::Ok,
+                    value: Some(info),
+                }),
+                Err(error) => {
+                    error!("Error uploading coefficient file: {}", error);
+                    Some(WsReply::UploadCoeffFile {
+                        result: WsResult::Error,
+                        value: None,
+                    })
+                }
+            },
+            None => Some(WsReply::Invalid {
+                error: "Unexpected binary message".to_string(),
+            }),
+        },
+        WsCommand::DeleteCoeffFile(name) => {
+            let result = unused_coeff_path(shared_data_inst, &client.settings, &name)
+                .and_then(|path| Ok(fs::remove_file(path)?));
+            Some(WsReply::DeleteCoeffFile {
+                result: change_result(result, "deleting coefficient file"),
+            })
+        }
+        WsCommand::ListConfigFiles => match client.settings.list_library() {
+            Ok(names) => Some(WsReply::ListConfigFiles {
+                result: WsResult::Ok,
+                value: names,
+            }),
+            Err(error) => {
+                error!("Error listing config files: {}", error);
+                Some(WsReply::ListConfigFiles {
+                    result: WsResult::Error,
+                    value: Vec::new(),
+                })
+            }
+        },
+        WsCommand::GetConfigFile(name) => {
+            let contents = client
+                .settings
+                .library_path(&name)
+                .and_then(|path| Ok(fs::read_to_string(path)?));
+            match contents {
+                Ok(contents) => Some(WsReply::GetConfigFile {
+                    result: WsResult::Ok,
+                    value: contents,
+                }),
+                Err(error) => {
+                    error!("Error reading config file: {}", error);
+                    Some(WsReply::GetConfigFile {
+                        result: WsResult::Error,
+                        value: error.to_string(),
+                    })
+                }
+            }
+        }
+        WsCommand::SaveConfigFile { name, config } => {
+            let result = client.settings.library_path(&name).and_then(|path| {
+                let mut conf = serde_yaml::from_str::<config::Configuration>(&config)?;
+                config::validate_config(&mut conf, Some(&path.to_string_lossy()))?;
+                fs::write(&path, config)?;
+                Ok(())
+            });
+            Some(WsReply::SaveConfigFile {
+                result: change_result(result, "saving config file"),
+            })
+        }
+        WsCommand::RenameConfigFile { name, new_name } => {
+            let result = client.settings.library_path(&name).and_then(|path| {
+                let new_path = client.settings.library_path(&new_name)?;
+                if new_path.exists() {
+                    let msg = format!("Config file '{}' already exists", new_name);
+                    return Err(config::ConfigError::new(&msg).into());
+                }
+                fs::rename(&path, &new_path)?;
+                let mut active_path = shared_data_inst.active_config_path.lock().unwrap();
+                if active_path.as_deref() == Some(&path.to_string_lossy()) {
+                    *active_path = Some(new_path.to_string_lossy().to_string());
+                }
+                let mut last_preset = shared_data_inst.last_preset.lock().unwrap();
+                if last_preset.as_deref() == Some(&name) {
+                    *last_preset = Some(new_name.clone());
+                }
+                Ok(())
+            });
+            Some(WsReply::RenameConfigFile {
+                result: change_result(result, "renaming config file"),
+            })
+        }
+        WsCommand::DeleteConfigFile(name) => {
+            let result = client.settings.library_path(&name).and_then(|path| {
+                let active_path = shared_data_inst.active_config_path.lock().unwrap();
+                if active_path.as_deref() == Some(&path.to_string_lossy()) {
+                    let msg = format!("Config file '{}' is in use", name);
+                    return Err(config::ConfigError::new(&msg).into());
+                }
+                fs::remove_file(&path)?;
+                let mut last_preset = shared_data_inst.last_preset.lock().unwrap();
+                if last_preset.as_deref() == Some(&name) {
+                    *last_preset = None;
+                }
+                Ok(())
+            });
+            Some(WsReply::DeleteConfigFile {
+                result: change_result(result, "deleting config file"),
+            })
+        }
+        WsCommand::LoadConfigFile(name) => {
+            let result = client.settings.library_path(&name).and_then(|path| {
+                let path = path.to_string_lossy().to_string();
+                let conf = config::load_validate_config(&path)?;
+                *shared_data_inst.active_config_path.lock().unwrap() = Some(path);
+                *shared_data_inst.last_preset.lock().unwrap() = Some(name.clone());
+                *shared_data_inst.new_config.lock().unwrap() = Some(conf);
+                shared_data_inst
+                    .signal_reload
+                    .store(true, Ordering::Relaxed);
+                Ok(())
+            });
+            Some(WsReply::LoadConfigFile {
+                result: change_result(result, "loading config file"),
+            })
+        }
+        WsCommand::GetLastPreset => Some(WsReply::GetLastPreset {
+            result: WsResult::Ok,
+            value: shared_data_inst.last_preset.lock().unwrap().clone(),
+        }),
         WsCommand::ValidateConfig(config_yml) => {
             match serde_yaml::from_str::<config::Configuration>(&config_yml) {
                 Ok(mut conf) => match config::validate_config(&mut conf, None) {
@@ -666,15 +2354,212 @@ fn handle_command(command: WsCommand, shared_data_inst: &SharedData) -> Option<W
                 value: devs,
             })
         }
+        WsCommand::SetTilt { name, gain } => {
+            let result = update_active_filter(shared_data_inst, &name, |filter| match filter {
+                config::Filter::BiquadCombo {
+                    parameters: config::BiquadComboParameters::Tilt { gain: old_gain, .. },
+                } => {
+                    *old_gain = gain;
+                    Ok(())
+                }
+                _ => {
+                    let msg = format!("Filter '{}' is not a Tilt filter", name);
+                    Err(config::ConfigError::new(&msg).into())
+                }
+            });
+            match result {
+                Ok(()) => Some(WsReply::SetTilt {
+                    result: WsResult::Ok,
+                }),
+                Err(error) => {
+                    error!("Error setting tilt: {}", error);
+                    Some(WsReply::SetTilt {
+                        result: WsResult::Error,
+                    })
+                }
+            }
+        }
+        WsCommand::SetToneControls { name, bass, treble } => {
+            let result = update_active_filter(shared_data_inst, &name, |filter| match filter {
+                config::Filter::BiquadCombo {
+                    parameters:
+                        config::BiquadComboParameters::Baxandall {
+                            bass_gain,
+                            treble_gain,
+                            ..
+                        },
+                } => {
+                    *bass_gain = bass;
+                    *treble_gain = treble;
+                    Ok(())
+                }
+                _ => {
+                    let msg = format!("Filter '{}' is not a Baxandall filter", name);
+                    Err(config::ConfigError::new(&msg).into())
+                }
+            });
+            match result {
+                Ok(()) => Some(WsReply::SetToneControls {
+                    result: WsResult::Ok,
+                }),
+                Err(error) => {
+                    error!("Error setting tone controls: {}", error);
+                    Some(WsReply::SetToneControls {
+                        result: WsResult::Error,
+                    })
+                }
+            }
+        }
+        WsCommand::SetFilterParameters { name, parameters } => {
+            let result = update_active_config(shared_data_inst, |conf| {
+                let pointer = format!("/filters/{}/parameters", escape_pointer(&name));
+                patch_config(conf, &pointer, &parameters)
+            });
+            Some(WsReply::SetFilterParameters {
+                result: change_result(result, "setting filter parameters"),
+            })
+        }
+        WsCommand::SetMixerMapping { name, mapping } => {
+            let result = update_active_config(shared_data_inst, |conf| {
+                let pointer = format!("/mixers/{}/mapping", escape_pointer(&name));
+                patch_config(conf, &pointer, &mapping)
+            });
+            Some(WsReply::SetMixerMapping {
+                result: change_result(result, "setting mixer mapping"),
+            })
+        }
+        WsCommand::SetPipelineStep { index, step } => {
+            let result = update_active_config(shared_data_inst, |conf| {
+                let pointer = format!("/pipeline/{}", index);
+                patch_config(conf, &pointer, &step)
+            });
+            Some(WsReply::SetPipelineStep {
+                result: change_result(result, "setting pipeline step"),
+            })
+        }
+        WsCommand::PatchConfig(patch) => {
+            let result =
+                update_active_config(shared_data_inst, |conf| patch_config(conf, "", &patch));
+            Some(WsReply::PatchConfig {
+                result: change_result(result, "patching config"),
+            })
+        }
         WsCommand::None => None,
     }
 }
 
+/// Escape a name for use as a token in a JSON pointer.
+fn escape_pointer(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::socketserver::{parse_command, WsCommand};
+    use crate::config;
+    use crate::confighistory::ConfigHistory;
+    use crate::socketserver::{
+        execute_command, handle_command, merge_patch, parse_command, AuthFailures, ClientRole,
+        ClientState, ServerSettings, SharedData, Subscriptions, WsCommand, WsEvent, WsEventType,
+        WsReply, WsResult, AUTH_FREE_ATTEMPTS, AUTH_MAX_DELAY,
+    };
+    use crate::testutils::TempDir;
+    use crate::{
+        CaptureStatus, PlaybackStatus, ProcessingParameters, ProcessingState, ProcessingStatus,
+        StopReason,
+    };
+    use std::collections::HashMap;
+    use std::net::IpAddr;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex, RwLock};
+    use std::time::{Duration, Instant};
     use tungstenite::Message;
 
+    const CONFIG_YAML: &str = "
+devices:
+  samplerate: 44100
+  chunksize: 1024
+  capture:
+    type: Stdin
+    channels: 2
+    format: S16LE
+  playback:
+    type: Stdout
+    channels: 2
+    format: S16LE
+filters:
+  lowpass:
+    type: Biquad
+    parameters:
+      type: Lowpass
+      freq: 1000.0
+      q: 0.7
+mixers:
+  mono:
+    channels:
+      in: 2
+      out: 2
+    mapping:
+      - dest: 0
+        sources:
+          - channel: 0
+            gain: -6
+      - dest: 1
+        sources:
+          - channel: 1
+            gain: -6
+pipeline:
+  - type: Mixer
+    name: mono
+  - type: Filter
+    channel: 0
+    names:
+      - lowpass
+";
+
+    fn make_config() -> config::Configuration {
+        serde_yaml::from_str(CONFIG_YAML).unwrap()
+    }
+
+    fn make_shared_data() -> SharedData {
+        SharedData {
+            signal_reload: Arc::new(AtomicBool::new(false)),
+            signal_exit: Arc::new(AtomicUsize::new(0)),
+            active_config: Arc::new(Mutex::new(None)),
+            config_generation: Arc::new(AtomicUsize::new(0)),
+            active_config_path: Arc::new(Mutex::new(None)),
+            last_preset: Arc::new(Mutex::new(None)),
+            new_config: Arc::new(Mutex::new(None)),
+            previous_config: Arc::new(Mutex::new(None)),
+            config_history: Arc::new(Mutex::new(ConfigHistory::default())),
+            capture_status: Arc::new(RwLock::new(CaptureStatus {
+                update_interval: 1000,
+                measured_samplerate: 44100,
+                signal_range: 0.5,
+                signal_rms: vec![-20.0, -21.0],
+                signal_peak: vec![-10.0, -11.0],
+                state: ProcessingState::Running,
+                rate_adjust: 1.0,
+                used_channels: vec![true, true],
+            })),
+            playback_status: Arc::new(RwLock::new(PlaybackStatus {
+                update_interval: 1000,
+                clipped_samples: 0,
+                buffer_level: 1024,
+                signal_rms: vec![-22.0, -23.0],
+                signal_peak: vec![-12.0, -13.0],
+            })),
+            processing_status: Arc::new(RwLock::new(ProcessingParameters {
+                volume: -10.0,
+                mute: false,
+                faders: HashMap::new(),
+            })),
+            status: Arc::new(RwLock::new(ProcessingStatus {
+                stop_reason: StopReason::None,
+            })),
+        }
+    }
+
     #[test]
     fn parse_commands() {
         let cmd = Message::text("\"Reload\"");
@@ -689,5 +2574,992 @@ mod tests {
         let cmd = Message::text("{\"SetConfigName\": \"somefile\"}");
         let res = parse_command(cmd).unwrap();
         assert_eq!(res, WsCommand::SetConfigName("somefile".to_string()));
+        let cmd = Message::text("{\"SetTilt\": {\"name\": \"tilt\", \"gain\": 3.0}}");
+        let res = parse_command(cmd).unwrap();
+        assert_eq!(
+            res,
+            WsCommand::SetTilt {
+                name: "tilt".to_string(),
+                gain: 3.0
+            }
+        );
+        let cmd = Message::text("{\"SetFaderVolume\": {\"name\": \"sub\", \"volume\": -6.5}}");
+        let res = parse_command(cmd).unwrap();
+        assert_eq!(
+            res,
+            WsCommand::SetFaderVolume {
+                name: "sub".to_string(),
+                volume: -6.5
+            }
+        );
+        let cmd = Message::text("{\"GetFaderMute\": \"sub\"}");
+        let res = parse_command(cmd).unwrap();
+        assert_eq!(res, WsCommand::GetFaderMute("sub".to_string()));
+        let cmd = Message::text("{\"AdjustVolume\": -2.0}");
+        let res = parse_command(cmd).unwrap();
+        assert_eq!(res, WsCommand::AdjustVolume(-2.0));
+        let cmd = Message::text("\"ToggleMute\"");
+        let res = parse_command(cmd).unwrap();
+        assert_eq!(res, WsCommand::ToggleMute);
+        let cmd = Message::text("{\"AdjustFaderVolume\": {\"name\": \"sub\", \"step\": 1.5}}");
+        let res = parse_command(cmd).unwrap();
+        assert_eq!(
+            res,
+            WsCommand::AdjustFaderVolume {
+                name: "sub".to_string(),
+                step: 1.5
+            }
+        );
+    }
+
+    #[test]
+    fn parse_subscriptions() {
+        let cmd = Message::text("{\"Subscribe\": {\"event\": \"State\"}}");
+        let res = parse_command(cmd).unwrap();
+        assert_eq!(
+            res,
+            WsCommand::Subscribe {
+                event: WsEventType::State,
+                interval: None
+            }
+        );
+        let cmd = Message::text("{\"Subscribe\": {\"event\": \"Meters\", \"interval\": 250}}");
+        let res = parse_command(cmd).unwrap();
+        assert_eq!(
+            res,
+            WsCommand::Subscribe {
+                event: WsEventType::Meters,
+                interval: Some(250)
+            }
+        );
+        let cmd = Message::text("{\"Unsubscribe\": \"Volume\"}");
+        let res = parse_command(cmd).unwrap();
+        assert_eq!(res, WsCommand::Unsubscribe(WsEventType::Volume));
+    }
+
+    #[test]
+    fn subscription_events() {
+        let shared_data = make_shared_data();
+        let mut subscriptions = Subscriptions::default();
+        assert!(subscriptions.read_timeout().is_none());
+        subscriptions.subscribe(WsEventType::State, None).unwrap();
+        subscriptions.subscribe(WsEventType::Volume, None).unwrap();
+        assert!(subscriptions.read_timeout().is_some());
+        // The current values are sent after subscribing
+        let events = subscriptions.poll(&shared_data);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], WsEvent::State(ProcessingState::Running));
+        // Nothing is sent until a value changes
+        assert!(subscriptions.poll(&shared_data).is_empty());
+        shared_data.processing_status.write().unwrap().mute = true;
+        let events = subscriptions.poll(&shared_data);
+        assert_eq!(
+            events,
+            vec![WsEvent::Volume {
+                volume: -10.0,
+                mute: true,
+                faders: HashMap::new()
+            }]
+        );
+        subscriptions.unsubscribe(WsEventType::State);
+        shared_data.capture_status.write().unwrap().state = ProcessingState::Paused;
+        assert!(subscriptions.poll(&shared_data).is_empty());
+    }
+
+    #[test]
+    fn subscription_config() {
+        let shared_data = make_shared_data();
+        let mut subscriptions = Subscriptions::default();
+        subscriptions.subscribe(WsEventType::Config, None).unwrap();
+        assert_eq!(
+            subscriptions.poll(&shared_data),
+            vec![WsEvent::Config(None)]
+        );
+        assert!(subscriptions.poll(&shared_data).is_empty());
+        *shared_data.active_config_path.lock().unwrap() = Some("other.yml".to_string());
+        shared_data
+            .config_generation
+            .fetch_add(1, Ordering::Relaxed);
+        assert_eq!(
+            subscriptions.poll(&shared_data),
+            vec![WsEvent::Config(Some("other.yml".to_string()))]
+        );
+        assert!(subscriptions.poll(&shared_data).is_empty());
+    }
+
+    #[test]
+    fn subscription_meters() {
+        let shared_data = make_shared_data();
+        let mut subscriptions = Subscriptions::default();
+        assert!(subscriptions
+            .subscribe(WsEventType::Meters, Some(5))
+            .is_err());
+        assert!(subscriptions
+            .subscribe(WsEventType::State, Some(100))
+            .is_err());
+        subscriptions
+            .subscribe(WsEventType::Meters, Some(10000))
+            .unwrap();
+        let events = subscriptions.poll(&shared_data);
+        assert_eq!(
+            events,
+            vec![WsEvent::Meters {
+                capture_rms: vec![-20.0, -21.0],
+                capture_peak: vec![-10.0, -11.0],
+                playback_rms: vec![-22.0, -23.0],
+                playback_peak: vec![-12.0, -13.0],
+            }]
+        );
+        // The next meter event is sent when the interval has passed
+        assert!(subscriptions.poll(&shared_data).is_empty());
+    }
+
+    #[test]
+    fn parse_batch() {
+        let cmd = Message::text("{\"Batch\": [\"GetStatus\", {\"SetVolume\": -3.0}]}");
+        let res = parse_command(cmd).unwrap();
+        assert_eq!(
+            res,
+            WsCommand::Batch(vec![WsCommand::GetStatus, WsCommand::SetVolume(-3.0)])
+        );
+    }
+
+    #[test]
+    fn batch_commands() {
+        let shared_data = make_shared_data();
+        let mut client = ClientState::new(Arc::new(ServerSettings::default()));
+        let batch = WsCommand::Batch(vec![WsCommand::SetMute(true), WsCommand::GetStatus]);
+        let reply = handle_command(batch, &shared_data, &mut client).unwrap();
+        if let WsReply::Batch { result, value } = reply {
+            assert_eq!(result, WsResult::Ok);
+            assert_eq!(value.len(), 2);
+            assert_eq!(
+                value[0],
+                WsReply::SetMute {
+                    result: WsResult::Ok
+                }
+            );
+            if let WsReply::GetStatus { value: status, .. } = &value[1] {
+                assert!(status.mute);
+                assert_eq!(status.volume, -10.0);
+                assert_eq!(status.state, ProcessingState::Running);
+                assert_eq!(status.capture_signal_rms, vec![-20.0, -21.0]);
+                assert_eq!(status.playback_signal_peak, vec![-12.0, -13.0]);
+            } else {
+                panic!("Wrong reply to GetStatus");
+            }
+        } else {
+            panic!("Wrong reply to Batch");
+        }
+        let nested = WsCommand::Batch(vec![
+            WsCommand::SetMute(false),
+            WsCommand::Batch(vec![WsCommand::GetStatus]),
+        ]);
+        let reply = handle_command(nested, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::Batch {
+                result: WsResult::Error,
+                value: Vec::new()
+            }
+        );
+        // Nothing in a rejected batch is executed
+        assert!(shared_data.processing_status.read().unwrap().mute);
+        let upload = WsCommand::Batch(vec![
+            WsCommand::SetMute(false),
+            WsCommand::UploadCoeffFile {
+                name: "coeffs.txt".to_string(),
+                data: None,
+            },
+        ]);
+        let reply = handle_command(upload, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::Batch {
+                result: WsResult::Error,
+                value: Vec::new()
+            }
+        );
+        assert!(client.pending_upload.is_none());
+        assert!(shared_data.processing_status.read().unwrap().mute);
+        // A missing fader is found before anything is executed
+        let missing = WsCommand::Batch(vec![
+            WsCommand::SetMute(false),
+            WsCommand::SetFaderMute {
+                name: "nonexisting".to_string(),
+                mute: true,
+            },
+        ]);
+        let reply = handle_command(missing, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::Batch {
+                result: WsResult::Error,
+                value: Vec::new()
+            }
+        );
+        assert!(shared_data.processing_status.read().unwrap().mute);
+        // The commands after a failing one are not executed
+        *shared_data.active_config.lock().unwrap() = Some(make_config());
+        let failing = WsCommand::Batch(vec![
+            WsCommand::SetMute(false),
+            WsCommand::SetFilterParameters {
+                name: "lowpass".to_string(),
+                parameters: serde_json::json!({"freq": -100.0}),
+            },
+            WsCommand::SetVolume(-20.0),
+        ]);
+        let reply = handle_command(failing, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::Batch {
+                result: WsResult::Error,
+                value: vec![
+                    WsReply::SetMute {
+                        result: WsResult::Ok
+                    },
+                    WsReply::SetFilterParameters {
+                        result: WsResult::Error
+                    },
+                ]
+            }
+        );
+        assert!(shared_data.new_config.lock().unwrap().is_none());
+        let procstat = shared_data.processing_status.read().unwrap();
+        assert!(!procstat.mute);
+        assert_eq!(procstat.volume, -10.0);
+    }
+
+    #[test]
+    fn json_merge_patch() {
+        let mut target = serde_json::json!({"a": 1, "b": {"c": 2, "d": 3}, "e": [1, 2]});
+        let patch = serde_json::json!({"b": {"c": 5, "d": null}, "e": [3], "f": "new"});
+        merge_patch(&mut target, &patch);
+        assert_eq!(
+            target,
+            serde_json::json!({"a": 1, "b": {"c": 5}, "e": [3], "f": "new"})
+        );
+    }
+
+    #[test]
+    fn patch_active_config() {
+        let shared_data = make_shared_data();
+        let mut client = ClientState::new(Arc::new(ServerSettings::default()));
+        *shared_data.active_config.lock().unwrap() = Some(make_config());
+        let cmd = WsCommand::SetFilterParameters {
+            name: "lowpass".to_string(),
+            parameters: serde_json::json!({"freq": 500.0}),
+        };
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::SetFilterParameters {
+                result: WsResult::Ok
+            }
+        );
+        // A second change builds on the first one, that is still waiting to be loaded
+        let cmd = WsCommand::SetPipelineStep {
+            index: 1,
+            step: serde_json::json!({"channel": 1}),
+        };
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::SetPipelineStep {
+                result: WsResult::Ok
+            }
+        );
+        let new_config = shared_data.new_config.lock().unwrap().clone().unwrap();
+        if let config::Filter::Biquad {
+            parameters: config::BiquadParameters::Lowpass { freq, q },
+            ..
+        } = new_config.filters.get("lowpass").unwrap()
+        {
+            assert_eq!(*freq, 500.0);
+            assert_eq!(*q, 0.7);
+        } else {
+            panic!("Wrong filter type");
+        }
+        assert_eq!(
+            new_config.pipeline[1],
+            config::PipelineStep::Filter {
+                channel: 1,
+                names: vec!["lowpass".to_string()]
+            }
+        );
+    }
+
+    #[test]
+    fn simultaneous_patches() {
+        let shared_data = make_shared_data();
+        let mut conf = make_config();
+        let names: Vec<String> = (0..8).map(|n| format!("gain{}", n)).collect();
+        for name in names.iter() {
+            conf.filters.insert(
+                name.clone(),
+                config::Filter::Gain {
+                    parameters: config::GainParameters {
+                        gain: 0.0,
+                        inverted: false,
+                        mute: false,
+                        ramp_time: 0.0,
+                    },
+                },
+            );
+        }
+        *shared_data.active_config.lock().unwrap() = Some(conf);
+        // Each client changes its own filter, starting from the same active config
+        let handles: Vec<_> = names
+            .iter()
+            .map(|name| {
+                let shared_data = shared_data.clone();
+                let cmd = WsCommand::SetFilterParameters {
+                    name: name.clone(),
+                    parameters: serde_json::json!({"gain": -6.0}),
+                };
+                std::thread::spawn(move || {
+                    let mut client = ClientState::new(Arc::new(ServerSettings::default()));
+                    execute_command(cmd, &shared_data, &mut client).unwrap()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(
+                handle.join().unwrap(),
+                WsReply::SetFilterParameters {
+                    result: WsResult::Ok
+                }
+            );
+        }
+        let new_config = shared_data.new_config.lock().unwrap().clone().unwrap();
+        for name in names.iter() {
+            if let config::Filter::Gain { parameters } = new_config.filters.get(name).unwrap() {
+                assert_eq!(parameters.gain, -6.0);
+            } else {
+                panic!("Wrong filter type");
+            }
+        }
+    }
+
+    #[test]
+    fn tone_control_changes() {
+        let shared_data = make_shared_data();
+        let mut client = ClientState::new(Arc::new(ServerSettings::default()));
+        let mut conf = make_config();
+        conf.filters.insert(
+            "tilt".to_string(),
+            config::Filter::BiquadCombo {
+                parameters: config::BiquadComboParameters::Tilt {
+                    freq: 1000.0,
+                    gain: 0.0,
+                },
+            },
+        );
+        conf.filters.insert(
+            "tone".to_string(),
+            config::Filter::BiquadCombo {
+                parameters: config::BiquadComboParameters::Baxandall {
+                    bass_freq: 100.0,
+                    bass_gain: 0.0,
+                    treble_freq: 10000.0,
+                    treble_gain: 0.0,
+                },
+            },
+        );
+        *shared_data.active_config.lock().unwrap() = Some(conf);
+        let cmd = WsCommand::SetTilt {
+            name: "tilt".to_string(),
+            gain: 3.0,
+        };
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::SetTilt {
+                result: WsResult::Ok
+            }
+        );
+        // The second change is made before the first one has been loaded
+        let cmd = WsCommand::SetToneControls {
+            name: "tone".to_string(),
+            bass: 6.0,
+            treble: -2.0,
+        };
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::SetToneControls {
+                result: WsResult::Ok
+            }
+        );
+        let new_config = shared_data.new_config.lock().unwrap().clone().unwrap();
+        assert_eq!(
+            new_config.filters.get("tilt"),
+            Some(&config::Filter::BiquadCombo {
+                parameters: config::BiquadComboParameters::Tilt {
+                    freq: 1000.0,
+                    gain: 3.0,
+                },
+            })
+        );
+        assert_eq!(
+            new_config.filters.get("tone"),
+            Some(&config::Filter::BiquadCombo {
+                parameters: config::BiquadComboParameters::Baxandall {
+                    bass_freq: 100.0,
+                    bass_gain: 6.0,
+                    treble_freq: 10000.0,
+                    treble_gain: -2.0,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_fader() {
+        let shared_data = make_shared_data();
+        let mut client = ClientState::new(Arc::new(ServerSettings::default()));
+        let cmd = WsCommand::SetFaderVolume {
+            name: "sub".to_string(),
+            volume: -6.0,
+        };
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::SetFaderVolume {
+                result: WsResult::Error
+            }
+        );
+        let reply = handle_command(
+            WsCommand::ToggleFaderMute("sub".to_string()),
+            &shared_data,
+            &mut client,
+        )
+        .unwrap();
+        assert_eq!(
+            reply,
+            WsReply::ToggleFaderMute {
+                result: WsResult::Error,
+                value: false
+            }
+        );
+        assert!(shared_data
+            .processing_status
+            .read()
+            .unwrap()
+            .faders
+            .is_empty());
+        // Once a filter has added the fader, it can be changed
+        shared_data
+            .processing_status
+            .write()
+            .unwrap()
+            .add_fader("sub");
+        let cmd = WsCommand::AdjustFaderVolume {
+            name: "sub".to_string(),
+            step: -3.0,
+        };
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::AdjustFaderVolume {
+                result: WsResult::Ok,
+                value: -3.0
+            }
+        );
+    }
+
+    #[test]
+    fn config_history() {
+        let shared_data = make_shared_data();
+        let mut client = ClientState::new(Arc::new(ServerSettings::default()));
+        let reply = handle_command(WsCommand::Undo, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::Undo {
+                result: WsResult::Error
+            }
+        );
+        let first = make_config();
+        let mut second = make_config();
+        second.devices.chunksize = 2048;
+        {
+            let mut history = shared_data.config_history.lock().unwrap();
+            history.record(&first, &Some("first.yml".to_string()), false);
+            history.record(&second, &Some("second.yml".to_string()), false);
+        }
+        let cmd: WsCommand =
+            serde_json::from_str(r#"{"SetConfigLabel": {"label": "bigger chunks"}}"#).unwrap();
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::SetConfigLabel {
+                result: WsResult::Ok
+            }
+        );
+        let reply = handle_command(WsCommand::Undo, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::Undo {
+                result: WsResult::Ok
+            }
+        );
+        assert_eq!(
+            shared_data.new_config.lock().unwrap().clone(),
+            Some(first.clone())
+        );
+        assert_eq!(
+            *shared_data.active_config_path.lock().unwrap(),
+            Some("first.yml".to_string())
+        );
+        assert!(shared_data.signal_reload.load(Ordering::Relaxed));
+        // The entry becomes the current one when the config has been applied
+        assert!(shared_data.config_history.lock().unwrap().list()[1].current);
+        shared_data.config_history.lock().unwrap().record(
+            &first,
+            &Some("first.yml".to_string()),
+            false,
+        );
+        let reply = handle_command(WsCommand::GetConfigHistory, &shared_data, &mut client).unwrap();
+        if let WsReply::GetConfigHistory { result, value } = reply {
+            assert_eq!(result, WsResult::Ok);
+            assert_eq!(value.len(), 2);
+            assert!(value[0].current);
+            assert_eq!(value[1].id, 1);
+            assert_eq!(value[1].label, Some("bigger chunks".to_string()));
+        } else {
+            panic!("Wrong reply");
+        }
+        let cmd: WsCommand = serde_json::from_str(r#"{"RevertToConfig": 1}"#).unwrap();
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::RevertToConfig {
+                result: WsResult::Ok
+            }
+        );
+        assert_eq!(
+            shared_data.new_config.lock().unwrap().clone(),
+            Some(second.clone())
+        );
+        shared_data.config_history.lock().unwrap().record(
+            &second,
+            &Some("second.yml".to_string()),
+            false,
+        );
+        let reply = handle_command(WsCommand::Redo, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::Redo {
+                result: WsResult::Error
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_config_patches() {
+        let shared_data = make_shared_data();
+        let mut client = ClientState::new(Arc::new(ServerSettings::default()));
+        *shared_data.active_config.lock().unwrap() = Some(make_config());
+        let commands = vec![
+            // Unknown filter
+            WsCommand::SetFilterParameters {
+                name: "highpass".to_string(),
+                parameters: serde_json::json!({"freq": 500.0}),
+            },
+            // Fails validation
+            WsCommand::SetFilterParameters {
+                name: "lowpass".to_string(),
+                parameters: serde_json::json!({"freq": 50000.0}),
+            },
+            // Doesn't match the config format
+            WsCommand::SetMixerMapping {
+                name: "mono".to_string(),
+                mapping: serde_json::json!("something"),
+            },
+            // No such pipeline step
+            WsCommand::SetPipelineStep {
+                index: 5,
+                step: serde_json::json!({"channel": 1}),
+            },
+            WsCommand::PatchConfig(serde_json::json!({"devices": {"chunksize": null}})),
+        ];
+        let expected = vec![
+            WsReply::SetFilterParameters {
+                result: WsResult::Error,
+            },
+            WsReply::SetFilterParameters {
+                result: WsResult::Error,
+            },
+            WsReply::SetMixerMapping {
+                result: WsResult::Error,
+            },
+            WsReply::SetPipelineStep {
+                result: WsResult::Error,
+            },
+            WsReply::PatchConfig {
+                result: WsResult::Error,
+            },
+        ];
+        for (cmd, expected_reply) in commands.into_iter().zip(expected) {
+            let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+            assert_eq!(reply, expected_reply);
+        }
+        assert!(shared_data.new_config.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn client_roles() {
+        let shared_data = make_shared_data();
+        let settings = Arc::new(ServerSettings {
+            control_token: Some("controlsecret".to_string()),
+            monitor_token: Some("monitorsecret".to_string()),
+            config_dir: None,
+            coeff_dir: None,
+            ..Default::default()
+        });
+        let mut client = ClientState::new(settings);
+        let reply = execute_command(WsCommand::GetVolume, &shared_data, &mut client).unwrap();
+        assert!(matches!(reply, WsReply::Unauthorized { .. }));
+        let reply = execute_command(
+            WsCommand::Authenticate("wrong".to_string()),
+            &shared_data,
+            &mut client,
+        )
+        .unwrap();
+        assert_eq!(
+            reply,
+            WsReply::Authenticate {
+                result: WsResult::Error,
+                value: ClientRole::Unauthenticated
+            }
+        );
+        execute_command(
+            WsCommand::Authenticate("monitorsecret".to_string()),
+            &shared_data,
+            &mut client,
+        );
+        let reply = execute_command(WsCommand::GetVolume, &shared_data, &mut client).unwrap();
+        assert!(matches!(reply, WsReply::GetVolume { .. }));
+        // A monitor can't change anything, also not within a batch
+        let reply = execute_command(WsCommand::SetVolume(-3.0), &shared_data, &mut client).unwrap();
+        assert!(matches!(reply, WsReply::Unauthorized { .. }));
+        let batch = WsCommand::Batch(vec![WsCommand::GetStatus, WsCommand::Exit]);
+        let reply = execute_command(batch, &shared_data, &mut client).unwrap();
+        assert!(matches!(reply, WsReply::Unauthorized { .. }));
+        execute_command(
+            WsCommand::Authenticate("controlsecret".to_string()),
+            &shared_data,
+            &mut client,
+        );
+        let reply = execute_command(WsCommand::SetVolume(-3.0), &shared_data, &mut client).unwrap();
+        assert!(matches!(reply, WsReply::SetVolume { .. }));
+        assert_eq!(shared_data.processing_status.read().unwrap().volume, -3.0);
+    }
+
+    #[test]
+    fn limited_auth_attempts() {
+        let settings = ServerSettings {
+            control_token: Some("controlsecret".to_string()),
+            ..Default::default()
+        };
+        let address: Option<IpAddr> = Some("192.168.1.10".parse().unwrap());
+        let other: Option<IpAddr> = Some("192.168.1.11".parse().unwrap());
+        for _ in 0..AUTH_FREE_ATTEMPTS {
+            assert!(settings.authenticate("wrong", address).is_err());
+        }
+        // The right token is rejected while the address is delayed
+        assert!(settings.authenticate("controlsecret", address).is_err());
+        assert_eq!(
+            settings.authenticate("controlsecret", other),
+            Ok(ClientRole::Controller)
+        );
+        let failure = AuthFailures {
+            count: 20,
+            last: Instant::now(),
+        };
+        assert_eq!(failure.delay(), Duration::from_millis(AUTH_MAX_DELAY));
+    }
+
+    #[test]
+    fn initial_roles() {
+        let mut settings = ServerSettings::default();
+        assert_eq!(settings.initial_role(), ClientRole::Controller);
+        settings.control_token = Some("secret".to_string());
+        assert_eq!(settings.initial_role(), ClientRole::Monitor);
+        settings.monitor_token = Some("othersecret".to_string());
+        assert_eq!(settings.initial_role(), ClientRole::Unauthenticated);
+    }
+
+    #[test]
+    fn restricted_config_dir() {
+        let settings = ServerSettings {
+            control_token: None,
+            monitor_token: None,
+            config_dir: Some(PathBuf::from("exampleconfigs").canonicalize().unwrap()),
+            coeff_dir: None,
+            ..Default::default()
+        };
+        let path = settings.resolve_config_path("simpleconfig.yml").unwrap();
+        assert!(path.ends_with("simpleconfig.yml"));
+        assert!(settings.resolve_config_path("../Cargo.toml").is_err());
+        assert!(settings.resolve_config_path("/etc/passwd").is_err());
+        assert!(settings.resolve_config_path("nonexisting.yml").is_err());
+    }
+
+    #[test]
+    fn config_library() {
+        let temp_dir = TempDir::new("library");
+        let dir = temp_dir.path();
+        let shared_data = make_shared_data();
+        let settings = Arc::new(ServerSettings {
+            control_token: None,
+            monitor_token: None,
+            config_dir: Some(dir.canonicalize().unwrap()),
+            coeff_dir: None,
+            ..Default::default()
+        });
+        let mut client = ClientState::new(settings);
+        let config = format!("# A comment\n{}", CONFIG_YAML);
+        let cmd = WsCommand::SaveConfigFile {
+            name: "preset.yml".to_string(),
+            config: config.clone(),
+        };
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::SaveConfigFile {
+                result: WsResult::Ok
+            }
+        );
+        // Invalid configs and names are rejected
+        let cmd = WsCommand::SaveConfigFile {
+            name: "broken.yml".to_string(),
+            config: "devices: 123".to_string(),
+        };
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::SaveConfigFile {
+                result: WsResult::Error
+            }
+        );
+        let cmd = WsCommand::SaveConfigFile {
+            name: "../outside.yml".to_string(),
+            config: config.clone(),
+        };
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::SaveConfigFile {
+                result: WsResult::Error
+            }
+        );
+
+        // The file is stored as is, with comments
+        let cmd = WsCommand::GetConfigFile("preset.yml".to_string());
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::GetConfigFile {
+                result: WsResult::Ok,
+                value: config
+            }
+        );
+        let cmd = WsCommand::RenameConfigFile {
+            name: "preset.yml".to_string(),
+            new_name: "renamed.yml".to_string(),
+        };
+        handle_command(cmd, &shared_data, &mut client);
+        let reply = handle_command(WsCommand::ListConfigFiles, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::ListConfigFiles {
+                result: WsResult::Ok,
+                value: vec!["renamed.yml".to_string()]
+            }
+        );
+        let cmd = WsCommand::LoadConfigFile("renamed.yml".to_string());
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::LoadConfigFile {
+                result: WsResult::Ok
+            }
+        );
+        assert!(shared_data.new_config.lock().unwrap().is_some());
+        let reply = handle_command(WsCommand::GetLastPreset, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::GetLastPreset {
+                result: WsResult::Ok,
+                value: Some("renamed.yml".to_string())
+            }
+        );
+        // The active config can't be deleted
+        let cmd = WsCommand::DeleteConfigFile("renamed.yml".to_string());
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::DeleteConfigFile {
+                result: WsResult::Error
+            }
+        );
+        *shared_data.active_config_path.lock().unwrap() = None;
+        let cmd = WsCommand::DeleteConfigFile("renamed.yml".to_string());
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::DeleteConfigFile {
+                result: WsResult::Ok
+            }
+        );
+        assert!(shared_data.last_preset.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn config_library_names() {
+        let settings = ServerSettings {
+            control_token: None,
+            monitor_token: None,
+            config_dir: Some(PathBuf::from("exampleconfigs")),
+            coeff_dir: None,
+            ..Default::default()
+        };
+        assert!(settings.library_path("preset.yml").is_ok());
+        assert!(settings.library_path("preset.yaml").is_ok());
+        assert!(settings.library_path("preset.txt").is_err());
+        assert!(settings.library_path(".hidden.yml").is_err());
+        assert!(settings.library_path("sub/preset.yml").is_err());
+        assert!(settings.library_path("sub\\preset.yml").is_err());
+        assert!(settings.library_path("C:preset.yml").is_err());
+        assert!(settings.library_path("preset:1.yml").is_err());
+        assert!(settings.library_path("").is_err());
+        assert!(ServerSettings::default()
+            .library_path("preset.yml")
+            .is_err());
+    }
+
+    #[test]
+    fn coefficient_files() {
+        let temp_dir = TempDir::new("coeffs");
+        let dir = temp_dir.path();
+        let shared_data = make_shared_data();
+        let settings = Arc::new(ServerSettings {
+            coeff_dir: Some(dir.canonicalize().unwrap()),
+            ..Default::default()
+        });
+        let mut client = ClientState::new(settings);
+        let wav = std::fs::read("testdata/int32.wav").unwrap();
+        let cmd = WsCommand::UploadCoeffFile {
+            name: "filter.wav".to_string(),
+            data: Some(base64::encode(&wav)),
+        };
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        if let WsReply::UploadCoeffFile {
+            result: WsResult::Ok,
+            value: Some(info),
+        } = reply
+        {
+            assert_eq!(info.format, Some(config::FileFormat::S32LE));
+            assert_eq!(info.channels, Some(1));
+            assert_eq!(info.length, Some(5));
+            assert_eq!(info.size, wav.len() as u64);
+        } else {
+            panic!("Upload failed");
+        }
+        // A binary message after an upload command without data
+        let cmd = WsCommand::UploadCoeffFile {
+            name: "filter.txt".to_string(),
+            data: None,
+        };
+        assert!(handle_command(cmd, &shared_data, &mut client).is_none());
+        let cmd = WsCommand::Binary(b"0.5\n0.25\n".to_vec());
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert!(matches!(
+            reply,
+            WsReply::UploadCoeffFile {
+                result: WsResult::Ok,
+                ..
+            }
+        ));
+        // A broken wav file is rejected
+        let cmd = WsCommand::UploadCoeffFile {
+            name: "broken.wav".to_string(),
+            data: Some(base64::encode(&wav[0..20])),
+        };
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::UploadCoeffFile {
+                result: WsResult::Error,
+                value: None
+            }
+        );
+        // A broken file that is already in the directory is listed with an error
+        std::fs::write(dir.join("old.wav"), &wav[0..20]).unwrap();
+        let reply = handle_command(WsCommand::ListCoeffFiles, &shared_data, &mut client).unwrap();
+        if let WsReply::ListCoeffFiles { result, value } = reply {
+            assert_eq!(result, WsResult::Ok);
+            let names: Vec<&str> = value.iter().map(|info| info.name.as_str()).collect();
+            assert_eq!(names, vec!["filter.txt", "filter.wav", "old.wav"]);
+            assert!(value[1].error.is_none());
+            assert!(value[2].error.is_some());
+            assert_eq!(value[2].format, None);
+            assert_eq!(value[2].size, 20);
+        } else {
+            panic!("Listing failed");
+        }
+        // Files used by the active config can't be replaced or deleted
+        let mut conf = make_config();
+        conf.filters.insert(
+            "fir".to_string(),
+            config::Filter::Conv {
+                parameters: config::ConvParameters::Wav {
+                    filename: dir.join("filter.wav").to_string_lossy().to_string(),
+                    channel: 0,
+                    use_pipeline_channel: false,
+                },
+            },
+        );
+        *shared_data.active_config.lock().unwrap() = Some(conf);
+        let cmd = WsCommand::DeleteCoeffFile("filter.wav".to_string());
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert_eq!(
+            reply,
+            WsReply::DeleteCoeffFile {
+                result: WsResult::Error
+            }
+        );
+        let cmd = WsCommand::UploadCoeffFile {
+            name: "filter.wav".to_string(),
+            data: None,
+        };
+        let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+        assert!(reply.is_error());
+        assert!(client.pending_upload.is_none());
+        assert!(dir.join("filter.wav").exists());
+        *shared_data.active_config.lock().unwrap() = None;
+        let cmd = WsCommand::DeleteCoeffFile("filter.wav".to_string());
+        handle_command(cmd, &shared_data, &mut client);
+        for name in ["../filter.txt", "C:filter.txt", "..\\filter.txt", ""] {
+            let cmd = WsCommand::DeleteCoeffFile(name.to_string());
+            let reply = handle_command(cmd, &shared_data, &mut client).unwrap();
+            assert_eq!(
+                reply,
+                WsReply::DeleteCoeffFile {
+                    result: WsResult::Error
+                }
+            );
+        }
+        assert!(!dir.join("filter.wav").exists());
+        assert!(dir.join("filter.txt").exists());
+    }
+
+    #[test]
+    fn unexpected_binary_message() {
+        let shared_data = make_shared_data();
+        let mut client = ClientState::new(Arc::new(ServerSettings::default()));
+        let reply = handle_command(WsCommand::Binary(vec![1, 2, 3]), &shared_data, &mut client);
+        assert!(matches!(reply, Some(WsReply::Invalid { .. })));
     }
 }
@@ -0,0 +1,364 @@
+// Soft clipper.
+// Samples below the knee pass unchanged, and above the knee a smooth saturation curve
+// brings the level gradually towards full scale instead of clipping it hard.
+// The saturation can run at a multiple of the sample rate, to reduce the aliasing of the generated harmonics.
+
+use crate::config;
+use crate::filters::Filter;
+
+// Sample format
+use crate::PrcFmt;
+use crate::Res;
+
+// Number of taps per phase of the oversampling filters
+const TAPS_PER_PHASE: usize = 32;
+
+// Allowed oversampling factors
+const OVERSAMPLING_FACTORS: [usize; 4] = [1, 2, 4, 8];
+
+// Level that the output never exceeds
+const CLIP_LIMIT: PrcFmt = 1.0;
+
+// Level where the saturation ends when oversampling, about -2.5 dB.
+// This leaves room for the ringing of the decimation filter, that overshoots
+// the saturated level by up to about 25% for heavily clipped high frequencies.
+const OVERSAMPLING_HEADROOM: PrcFmt = 0.75;
+
+pub struct SoftClip {
+    pub name: String,
+    curve: config::SoftClipCurve,
+    knee: PrcFmt,
+    oversampling: usize,
+    upsampling_coeffs: Vec<Vec<PrcFmt>>,
+    downsampling_coeffs: Vec<PrcFmt>,
+    input_history: History,
+    output_history: History,
+}
+
+/// History of the latest samples, kept contiguous by writing every sample twice.
+struct History {
+    buffer: Vec<PrcFmt>,
+    index: usize,
+    length: usize,
+}
+
+impl History {
+    fn new(length: usize) -> Self {
+        History {
+            buffer: vec![0.0; 2 * length],
+            index: 0,
+            length,
+        }
+    }
+
+    fn push(&mut self, value: PrcFmt) {
+        self.buffer[self.index] = value;
+        self.buffer[self.index + self.length] = value;
+        self.index = (self.index + 1) % self.length;
+    }
+
+    /// Get the stored samples, from the oldest to the newest.
+    fn samples(&self) -> &[PrcFmt] {
+        &self.buffer[self.index..self.index + self.length]
+    }
+}
+
+/// Design a windowed sinc lowpass filter with unity gain at DC.
+/// The cutoff is given as a fraction of the sample rate.
+fn make_lowpass(length: usize, cutoff: PrcFmt) -> Vec<PrcFmt> {
+    let pi = std::f64::consts::PI as PrcFmt;
+    let center = (length - 1) as PrcFmt / 2.0;
+    let mut coeffs: Vec<PrcFmt> = (0..length)
+        .map(|n| {
+            let t = n as PrcFmt - center;
+            let sinc = if t == 0.0 {
+                1.0
+            } else {
+                (2.0 * pi * cutoff * t).sin() / (2.0 * pi * cutoff * t)
+            };
+            let phase = 2.0 * pi * n as PrcFmt / (length - 1) as PrcFmt;
+            let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+            sinc * window
+        })
+        .collect();
+    let sum: PrcFmt = coeffs.iter().sum();
+    for coeff in coeffs.iter_mut() {
+        *coeff /= sum;
+    }
+    coeffs
+}
+
+/// Polynomial saturation curve of odd order.
+/// The curve has unity slope at zero, and reaches 1.0 with zero slope at order/(order-1).
+fn polynomial_clip(value: PrcFmt, order: i32) -> PrcFmt {
+    let n = order as PrcFmt;
+    let limit = n / (n - 1.0);
+    if value >= limit {
+        1.0
+    } else {
+        value - value.powi(order) / (n * limit.powi(order - 1))
+    }
+}
+
+impl SoftClip {
+    pub fn from_config(name: String, parameters: config::SoftClipParameters) -> Self {
+        let mut clipper = SoftClip {
+            name,
+            curve: config::SoftClipCurve::Tanh,
+            knee: 0.0,
+            oversampling: 0,
+            upsampling_coeffs: Vec::new(),
+            downsampling_coeffs: Vec::new(),
+            input_history: History::new(1),
+            output_history: History::new(1),
+        };
+        clipper.set_parameters(parameters);
+        clipper
+    }
+
+    fn set_parameters(&mut self, parameters: config::SoftClipParameters) {
+        let base: PrcFmt = 10.0;
+        self.knee = base.powf(parameters.knee / 20.0);
+        self.curve = parameters.curve;
+        if parameters.oversampling != self.oversampling {
+            self.set_oversampling(parameters.oversampling);
+        }
+    }
+
+    /// Prepare the polyphase interpolation filter and the decimation filter.
+    /// Both use the same lowpass, with the cutoff a bit below the original Nyquist frequency.
+    fn set_oversampling(&mut self, factor: usize) {
+        self.oversampling = factor;
+        let length = factor * TAPS_PER_PHASE;
+        let lowpass = make_lowpass(length, 0.45 / factor as PrcFmt);
+        // Phase coefficients are stored in the order of the history, oldest sample first.
+        // The gain of the interpolation filter is scaled up by the factor, to make up for the inserted zeros.
+        self.upsampling_coeffs = (0..factor)
+            .map(|phase| {
+                (0..TAPS_PER_PHASE)
+                    .map(|n| factor as PrcFmt * lowpass[(TAPS_PER_PHASE - 1 - n) * factor + phase])
+                    .collect()
+            })
+            .collect();
+        self.downsampling_coeffs = lowpass.into_iter().rev().collect();
+        self.input_history = History::new(TAPS_PER_PHASE);
+        self.output_history = History::new(length);
+    }
+
+    /// Apply the saturation curve to a single sample.
+    fn saturate(&self, value: PrcFmt) -> PrcFmt {
+        let level = value.abs();
+        if level <= self.knee {
+            return value;
+        }
+        let span = 1.0 - self.knee;
+        let scaled = (level - self.knee) / span;
+        let shaped = match self.curve {
+            config::SoftClipCurve::Tanh => scaled.tanh(),
+            config::SoftClipCurve::Cubic => polynomial_clip(scaled, 3),
+            config::SoftClipCurve::Polynomial => polynomial_clip(scaled, 5),
+        };
+        (self.knee + span * shaped).copysign(value)
+    }
+
+    /// Upsample a single sample, saturate at the higher rate, and downsample again.
+    /// The two linear phase filters delay the signal by (factor * TAPS_PER_PHASE - 1) / factor samples,
+    /// just under TAPS_PER_PHASE samples at the original rate.
+    /// The saturation is scaled down to leave headroom for the overshoot of the decimation filter,
+    /// and the result is finally limited hard to full scale.
+    fn process_oversampled(&mut self, value: PrcFmt) -> PrcFmt {
+        self.input_history.push(value);
+        for phase in 0..self.oversampling {
+            let upsampled: PrcFmt = self.upsampling_coeffs[phase]
+                .iter()
+                .zip(self.input_history.samples())
+                .map(|(coeff, sample)| coeff * sample)
+                .sum();
+            let saturated =
+                OVERSAMPLING_HEADROOM * self.saturate(upsampled / OVERSAMPLING_HEADROOM);
+            self.output_history.push(saturated);
+        }
+        let output: PrcFmt = self
+            .downsampling_coeffs
+            .iter()
+            .zip(self.output_history.samples())
+            .map(|(coeff, sample)| coeff * sample)
+            .sum();
+        output.clamp(-CLIP_LIMIT, CLIP_LIMIT)
+    }
+}
+
+impl Filter for SoftClip {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn process_waveform(&mut self, waveform: &mut [PrcFmt]) -> Res<()> {
+        if self.oversampling > 1 {
+            for item in waveform.iter_mut() {
+                *item = self.process_oversampled(*item);
+            }
+        } else {
+            for item in waveform.iter_mut() {
+                *item = self.saturate(*item);
+            }
+        }
+        Ok(())
+    }
+
+    fn update_parameters(&mut self, conf: config::Filter) {
+        if let config::Filter::SoftClip { parameters } = conf {
+            self.set_parameters(parameters);
+        } else {
+            // This should never happen unless there is a bug somewhere else
+            panic!("Invalid config change!");
+        }
+    }
+}
+
+/// Validate a SoftClip config.
+pub fn validate_config(conf: &config::SoftClipParameters) -> Res<()> {
+    if conf.knee >= 0.0 {
+        return Err(config::ConfigError::new("Knee must be less than 0 dB").into());
+    } else if conf.knee < -40.0 {
+        return Err(config::ConfigError::new("Knee must be higher than -40 dB").into());
+    }
+    if !OVERSAMPLING_FACTORS.contains(&conf.oversampling) {
+        let msg = format!(
+            "Oversampling factor must be one of {:?}",
+            OVERSAMPLING_FACTORS
+        );
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config;
+    use crate::filters::Filter;
+    use crate::softclip;
+    use crate::PrcFmt;
+
+    fn is_close(left: PrcFmt, right: PrcFmt, maxdiff: PrcFmt) -> bool {
+        println!("{} - {}", left, right);
+        (left - right).abs() < maxdiff
+    }
+
+    fn make_conf(curve: config::SoftClipCurve, oversampling: usize) -> config::SoftClipParameters {
+        config::SoftClipParameters {
+            curve,
+            knee: -6.0,
+            oversampling,
+        }
+    }
+
+    // Amplitude of the component at the given frequency, for a waveform with a whole number of periods
+    fn amplitude_at(waveform: &[PrcFmt], freq: PrcFmt, fs: usize) -> PrcFmt {
+        let omega = 2.0 * (std::f64::consts::PI as PrcFmt) * freq / fs as PrcFmt;
+        let (re, im) = waveform
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (n, value)| {
+                (
+                    re + value * (omega * n as PrcFmt).cos(),
+                    im + value * (omega * n as PrcFmt).sin(),
+                )
+            });
+        2.0 * (re * re + im * im).sqrt() / waveform.len() as PrcFmt
+    }
+
+    fn process_sine(conf: config::SoftClipParameters, freq: PrcFmt, ampl: PrcFmt) -> Vec<PrcFmt> {
+        let fs = 48000;
+        let mut clipper = softclip::SoftClip::from_config("test".to_string(), conf);
+        let omega = 2.0 * (std::f64::consts::PI as PrcFmt) * freq / fs as PrcFmt;
+        let mut waveform: Vec<PrcFmt> = (0..2 * fs)
+            .map(|n| ampl * (omega * n as PrcFmt).sin())
+            .collect();
+        clipper.process_waveform(&mut waveform).unwrap();
+        // Skip the first second to get past the latency
+        waveform[fs..].to_vec()
+    }
+
+    #[test]
+    fn below_knee_unchanged() {
+        let mut clipper = softclip::SoftClip::from_config(
+            "test".to_string(),
+            make_conf(config::SoftClipCurve::Tanh, 1),
+        );
+        let mut waveform = vec![-0.5, -0.1, 0.0, 0.3, 0.5];
+        let expected = waveform.clone();
+        clipper.process_waveform(&mut waveform).unwrap();
+        assert_eq!(waveform, expected);
+    }
+
+    #[test]
+    fn curves_limited() {
+        for curve in [
+            config::SoftClipCurve::Tanh,
+            config::SoftClipCurve::Cubic,
+            config::SoftClipCurve::Polynomial,
+        ] {
+            let mut clipper =
+                softclip::SoftClip::from_config("test".to_string(), make_conf(curve, 1));
+            let mut waveform: Vec<PrcFmt> = (0..1000).map(|n| 0.01 * n as PrcFmt).collect();
+            clipper.process_waveform(&mut waveform).unwrap();
+            // Monotonic, and never above full scale
+            assert!(waveform.windows(2).all(|pair| pair[1] >= pair[0]));
+            assert!(waveform.iter().all(|value| *value <= 1.0));
+            assert!(is_close(waveform[999], 1.0, 0.01));
+        }
+    }
+
+    #[test]
+    fn polynomial_clip_smooth() {
+        // Unity slope at zero, full scale with zero slope at the limit
+        assert!(is_close(
+            softclip::polynomial_clip(1.0e-6, 3),
+            1.0e-6,
+            1.0e-12
+        ));
+        assert!(is_close(softclip::polynomial_clip(1.5, 3), 1.0, 1.0e-9));
+        assert!(is_close(softclip::polynomial_clip(1.499, 3), 1.0, 1.0e-5));
+        assert!(is_close(softclip::polynomial_clip(1.25, 5), 1.0, 1.0e-9));
+    }
+
+    #[test]
+    fn oversampled_passband() {
+        let output = process_sine(make_conf(config::SoftClipCurve::Tanh, 4), 1000.0, 0.1);
+        assert!(is_close(amplitude_at(&output, 1000.0, 48000), 0.1, 0.001));
+    }
+
+    #[test]
+    fn oversampling_reduces_aliasing() {
+        // The 7th harmonic of 5 kHz aliases to 13 kHz
+        let plain = process_sine(make_conf(config::SoftClipCurve::Cubic, 1), 5000.0, 2.0);
+        let oversampled = process_sine(make_conf(config::SoftClipCurve::Cubic, 8), 5000.0, 2.0);
+        let alias_plain = amplitude_at(&plain, 13000.0, 48000);
+        let alias_oversampled = amplitude_at(&oversampled, 13000.0, 48000);
+        println!("{} {}", alias_plain, alias_oversampled);
+        assert!(alias_oversampled < 0.1 * alias_plain);
+    }
+
+    #[test]
+    fn oversampled_limited() {
+        // Heavily clipped high frequencies overshoot even with the headroom
+        for factor in [2, 4, 8] {
+            let output = process_sine(
+                make_conf(config::SoftClipCurve::Polynomial, factor),
+                15000.0,
+                100.0,
+            );
+            assert!(output.iter().all(|value| value.abs() <= 1.0));
+        }
+    }
+
+    #[test]
+    fn check_softclip() {
+        assert!(softclip::validate_config(&make_conf(config::SoftClipCurve::Tanh, 4)).is_ok());
+        assert!(softclip::validate_config(&make_conf(config::SoftClipCurve::Tanh, 3)).is_err());
+        let mut badconf = make_conf(config::SoftClipCurve::Tanh, 1);
+        badconf.knee = 0.0;
+        assert!(softclip::validate_config(&badconf).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+// Persistent runtime state.
+// The volume and mute settings, the faders, the path of the active config and the last loaded preset
+// are saved to a file when they change, so that they can be restored when CamillaDSP is started again.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::FaderState;
+use crate::ProcessingParameters;
+use crate::Res;
+
+// Interval in ms for checking if the state has changed
+const SAVE_INTERVAL: u64 = 1000;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct State {
+    #[serde(default)]
+    pub config_path: Option<String>,
+    // Name of the config file that was last loaded from the config library
+    #[serde(default)]
+    pub preset: Option<String>,
+    #[serde(default)]
+    pub volume: f32,
+    #[serde(default)]
+    pub mute: bool,
+    #[serde(default)]
+    pub faders: HashMap<String, FaderState>,
+}
+
+impl State {
+    /// Collect the current state.
+    pub fn from_status(
+        params: &ProcessingParameters,
+        config_path: &Option<String>,
+        preset: &Option<String>,
+    ) -> Self {
+        State {
+            config_path: config_path.clone(),
+            preset: preset.clone(),
+            volume: params.volume,
+            mute: params.mute,
+            faders: params.faders.clone(),
+        }
+    }
+}
+
+/// Load the state from a file.
+/// A missing or unreadable file gives None, and CamillaDSP then starts with the default state.
+pub fn load_state(filename: &str) -> Option<State> {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(err) => {
+            warn!("Could not read state file '{}': {}", filename, err);
+            return None;
+        }
+    };
+    match serde_yaml::from_str::<State>(&contents) {
+        Ok(state) => {
+            debug!("Loaded state: {:?}", state);
+            Some(state)
+        }
+        Err(err) => {
+            warn!("Invalid state file '{}': {}", filename, err);
+            None
+        }
+    }
+}
+
+/// Save the state to a file.
+/// The state is first written to a temporary file, that is flushed to disk and then replaces the old file.
+/// This way the old file is left intact if writing is interrupted, for example by a power cut.
+pub fn save_state(filename: &str, state: &State) -> Res<()> {
+    let path = Path::new(filename);
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path: PathBuf = path.with_file_name(temp_name);
+    let mut file = File::create(&temp_path)?;
+    file.write_all(serde_yaml::to_string(state)?.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)?;
+    // The rename is only durable once the directory has been flushed.
+    // Directories can't be opened as files on Windows, where the rename is flushed by the file system.
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Start a thread that saves the state whenever it changes.
+pub fn start_saver(
+    filename: String,
+    processing_status: Arc<RwLock<ProcessingParameters>>,
+    active_config_path: Arc<Mutex<Option<String>>>,
+    last_preset: Arc<Mutex<Option<String>>>,
+) {
+    thread::spawn(move || {
+        let mut saved_state = load_state(&filename);
+        loop {
+            let state = State::from_status(
+                &processing_status.read().unwrap(),
+                &active_config_path.lock().unwrap(),
+                &last_preset.lock().unwrap(),
+            );
+            if saved_state.as_ref() != Some(&state) {
+                match save_state(&filename, &state) {
+                    Ok(()) => {
+                        debug!("Saved state to '{}'", filename);
+                        saved_state = Some(state);
+                    }
+                    Err(err) => {
+                        error!("Could not save state file '{}': {}", filename, err);
+                    }
+                }
+            }
+            thread::sleep(Duration::from_millis(SAVE_INTERVAL));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::statefile::{load_state, save_state, State};
+    use crate::testutils::TempDir;
+    use crate::FaderState;
+    use std::collections::HashMap;
+
+    #[test]
+    fn save_and_load() {
+        let temp_dir = TempDir::new("state");
+        let filename = temp_dir
+            .path()
+            .join("state.yml")
+            .to_string_lossy()
+            .to_string();
+        let mut faders = HashMap::new();
+        faders.insert(
+            "sub".to_string(),
+            FaderState {
+                volume: -3.0,
+                mute: true,
+            },
+        );
+        let state = State {
+            config_path: Some("/path/to/config.yml".to_string()),
+            preset: Some("config.yml".to_string()),
+            volume: -25.5,
+            mute: false,
+            faders,
+        };
+        save_state(&filename, &state).unwrap();
+        assert!(!std::path::Path::new(&format!("{}.tmp", filename)).exists());
+        let loaded = load_state(&filename).unwrap();
+        assert_eq!(loaded, state);
+        std::fs::remove_file(&filename).unwrap();
+        assert!(load_state(&filename).is_none());
+    }
+
+    #[test]
+    fn partial_state() {
+        let state: State = serde_yaml::from_str("volume: -30.0").unwrap();
+        assert_eq!(state.volume, -30.0);
+        assert!(state.config_path.is_none());
+        assert!(state.preset.is_none());
+        assert!(!state.mute);
+        assert!(serde_yaml::from_str::<State>("volume: -30.0\nsomething: 1").is_err());
+    }
+}
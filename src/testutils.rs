@@ -0,0 +1,29 @@
+// Helpers shared by the tests of several modules.
+
+use std::path::{Path, PathBuf};
+
+/// A directory for the files of a test, that is removed with its contents when dropped.
+/// The process id is included in the name, so that parallel test runs don't share directories.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("camilladsp_{}_{}", name, std::process::id()));
+        // Remove anything left behind by an earlier run that was interrupted
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,419 @@
+// Stereo to multichannel upmixer, using a frequency domain decomposition.
+// The stereo signal is analyzed with a short time Fourier transform (STFT),
+// and each frequency bin is split into center, front and ambient parts
+// depending on the correlation and level similarity between the channels.
+
+use crate::audiodevice::AudioChunk;
+use crate::config;
+use crate::filters::Processor;
+use num_complex::Complex;
+use num_traits::Zero;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use std::sync::Arc;
+
+// Sample format
+use crate::PrcFmt;
+use crate::Res;
+
+// Indexes of the outputs
+const FRONT_LEFT: usize = 0;
+const FRONT_RIGHT: usize = 1;
+const CENTER: usize = 2;
+const SURROUND_LEFT: usize = 3;
+const SURROUND_RIGHT: usize = 4;
+const LFE: usize = 5;
+const NBR_OUTPUTS: usize = 6;
+
+// Time constant in seconds for smoothing the channel correlation estimates
+const SMOOTHING_TIME: PrcFmt = 0.05;
+
+// Small value to avoid division by zero for silent bins
+const EPSILON: PrcFmt = 1.0e-12;
+
+pub struct Upmixer {
+    pub name: String,
+    samplerate: usize,
+    fft_size: usize,
+    hop: usize,
+    channels: [usize; 2],
+    destinations: [Option<usize>; NBR_OUTPUTS],
+    lfe_weights: Vec<PrcFmt>,
+    smoothing: PrcFmt,
+    window: Vec<PrcFmt>,
+    fft: Arc<dyn RealToComplex<PrcFmt>>,
+    ifft: Arc<dyn ComplexToReal<PrcFmt>>,
+    scratch_fw: Vec<Complex<PrcFmt>>,
+    scratch_inv: Vec<Complex<PrcFmt>>,
+    input_bufs: [Vec<PrcFmt>; 2],
+    spectra: [Vec<Complex<PrcFmt>>; 2],
+    output_spectrum: Vec<Complex<PrcFmt>>,
+    time_buf: Vec<PrcFmt>,
+    power_l: Vec<PrcFmt>,
+    power_r: Vec<PrcFmt>,
+    cross: Vec<Complex<PrcFmt>>,
+    overlap: Vec<Vec<PrcFmt>>,
+    ready: Vec<Vec<PrcFmt>>,
+    index: usize,
+}
+
+impl Upmixer {
+    /// Create a new upmixer.
+    /// The transform length is scaled with the sample rate to give the same frequency resolution,
+    /// 2048 at 44.1 and 48 kHz. This is also the latency of the upmixer.
+    pub fn from_config(
+        name: String,
+        samplerate: usize,
+        parameters: config::UpmixerParameters,
+    ) -> Self {
+        let fft_size = (samplerate / 24).next_power_of_two();
+        let hop = fft_size / 2;
+        let nbins = fft_size / 2 + 1;
+        let mut planner = RealFftPlanner::<PrcFmt>::new();
+        let fft = planner.plan_fft_forward(fft_size);
+        let ifft = planner.plan_fft_inverse(fft_size);
+        let scratch_fw = fft.make_scratch_vec();
+        let scratch_inv = ifft.make_scratch_vec();
+
+        // Square root of a periodic Hann window, used for both analysis and synthesis.
+        // The squared windows sum to one with 50% overlap.
+        let pi = std::f64::consts::PI as PrcFmt;
+        let window = (0..fft_size)
+            .map(|n| (pi * n as PrcFmt / fft_size as PrcFmt).sin())
+            .collect();
+        let smoothing = (-(hop as PrcFmt) / (SMOOTHING_TIME * samplerate as PrcFmt)).exp();
+
+        debug!(
+            "Upmixer {} using transform length {}, latency {} ms",
+            name,
+            fft_size,
+            1000.0 * fft_size as PrcFmt / samplerate as PrcFmt
+        );
+
+        let mut upmixer = Upmixer {
+            name,
+            samplerate,
+            fft_size,
+            hop,
+            channels: parameters.channels,
+            destinations: [None; NBR_OUTPUTS],
+            lfe_weights: Vec::new(),
+            smoothing,
+            window,
+            fft,
+            ifft,
+            scratch_fw,
+            scratch_inv,
+            input_bufs: [vec![0.0; fft_size], vec![0.0; fft_size]],
+            spectra: [vec![Complex::zero(); nbins], vec![Complex::zero(); nbins]],
+            output_spectrum: vec![Complex::zero(); nbins],
+            time_buf: vec![0.0; fft_size],
+            power_l: vec![0.0; nbins],
+            power_r: vec![0.0; nbins],
+            cross: vec![Complex::zero(); nbins],
+            overlap: vec![vec![0.0; fft_size]; NBR_OUTPUTS],
+            ready: vec![vec![0.0; hop]; NBR_OUTPUTS],
+            index: 0,
+        };
+        upmixer.set_parameters(parameters);
+        upmixer
+    }
+
+    fn set_parameters(&mut self, parameters: config::UpmixerParameters) {
+        let mut destinations = [None; NBR_OUTPUTS];
+        destinations[FRONT_LEFT] = Some(parameters.channels[0]);
+        destinations[FRONT_RIGHT] = Some(parameters.channels[1]);
+        destinations[CENTER] = parameters.center;
+        if let Some([surround_l, surround_r]) = parameters.surround {
+            destinations[SURROUND_LEFT] = Some(surround_l);
+            destinations[SURROUND_RIGHT] = Some(surround_r);
+        }
+        destinations[LFE] = parameters.lfe;
+        self.destinations = destinations;
+        self.channels = parameters.channels;
+        self.lfe_weights =
+            Upmixer::lfe_weights(self.fft_size, self.samplerate, parameters.lfe_freq);
+    }
+
+    /// Weights for the LFE output, one below the cutoff frequency
+    /// and then rolling off as a half cosine to zero at twice the cutoff.
+    fn lfe_weights(fft_size: usize, samplerate: usize, cutoff: PrcFmt) -> Vec<PrcFmt> {
+        let pi = std::f64::consts::PI as PrcFmt;
+        (0..fft_size / 2 + 1)
+            .map(|n| {
+                let freq = n as PrcFmt * samplerate as PrcFmt / fft_size as PrcFmt;
+                if freq <= cutoff {
+                    1.0
+                } else if freq >= 2.0 * cutoff {
+                    0.0
+                } else {
+                    0.5 + 0.5 * (pi * (freq - cutoff) / cutoff).cos()
+                }
+            })
+            .collect()
+    }
+
+    /// Get the output spectrum for one output, from the spectra of the left and right inputs.
+    fn output_bin(
+        &self,
+        output: usize,
+        bin: usize,
+        left: Complex<PrcFmt>,
+        right: Complex<PrcFmt>,
+    ) -> Complex<PrcFmt> {
+        let power_l = self.power_l[bin];
+        let power_r = self.power_r[bin];
+        let cross = self.cross[bin].norm();
+        // Similarity is one only when both channels are correlated and have the same level
+        let similarity = (2.0 * cross / (power_l + power_r + EPSILON)).min(1.0);
+        let coherence = (cross / ((power_l * power_r).sqrt() + EPSILON)).min(1.0);
+        let ambience = (1.0 - coherence * coherence).max(0.0).sqrt();
+        let center = (left + right) * (0.5 * similarity);
+        match output {
+            FRONT_LEFT => left - center,
+            FRONT_RIGHT => right - center,
+            CENTER => center,
+            SURROUND_LEFT => (left - center) * ambience,
+            SURROUND_RIGHT => (right - center) * ambience,
+            LFE => (left + right) * (0.5 * self.lfe_weights[bin]),
+            _ => Complex::zero(),
+        }
+    }
+
+    /// Process one block of `hop` new samples.
+    fn process_frame(&mut self) {
+        for (input, spectrum) in self.input_bufs.iter().zip(self.spectra.iter_mut()) {
+            for ((windowed, value), win) in self
+                .time_buf
+                .iter_mut()
+                .zip(input.iter())
+                .zip(self.window.iter())
+            {
+                *windowed = value * win;
+            }
+            self.fft
+                .process_with_scratch(&mut self.time_buf, spectrum, &mut self.scratch_fw)
+                .unwrap();
+        }
+
+        // Update the smoothed power and cross spectra
+        let alpha = self.smoothing;
+        for (bin, (left, right)) in self.spectra[0]
+            .iter()
+            .zip(self.spectra[1].iter())
+            .enumerate()
+        {
+            self.power_l[bin] = alpha * self.power_l[bin] + (1.0 - alpha) * left.norm_sqr();
+            self.power_r[bin] = alpha * self.power_r[bin] + (1.0 - alpha) * right.norm_sqr();
+            self.cross[bin] = self.cross[bin] * alpha + left * right.conj() * (1.0 - alpha);
+        }
+
+        let nbins = self.fft_size / 2 + 1;
+        let scale = 1.0 / self.fft_size as PrcFmt;
+        for output in 0..NBR_OUTPUTS {
+            if self.destinations[output].is_none() {
+                continue;
+            }
+            for bin in 0..nbins {
+                let value =
+                    self.output_bin(output, bin, self.spectra[0][bin], self.spectra[1][bin]);
+                self.output_spectrum[bin] = value;
+            }
+            // The DC and Nyquist bins of a real signal have no imaginary part
+            self.output_spectrum[0].im = 0.0;
+            self.output_spectrum[nbins - 1].im = 0.0;
+            self.ifft
+                .process_with_scratch(
+                    &mut self.output_spectrum,
+                    &mut self.time_buf,
+                    &mut self.scratch_inv,
+                )
+                .unwrap();
+            let overlap = &mut self.overlap[output];
+            for ((acc, value), win) in overlap
+                .iter_mut()
+                .zip(self.time_buf.iter())
+                .zip(self.window.iter())
+            {
+                *acc += value * win * scale;
+            }
+            self.ready[output].copy_from_slice(&overlap[0..self.hop]);
+            overlap.copy_within(self.hop.., 0);
+            for value in overlap[self.hop..].iter_mut() {
+                *value = 0.0;
+            }
+        }
+        for input in self.input_bufs.iter_mut() {
+            input.copy_within(self.hop.., 0);
+        }
+    }
+}
+
+impl Processor for Upmixer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn process_chunk(&mut self, chunk: &mut AudioChunk) -> Res<()> {
+        let mut outputs: Vec<Vec<PrcFmt>> = (0..NBR_OUTPUTS)
+            .map(|_| Vec::with_capacity(chunk.frames))
+            .collect();
+        let [chan_l, chan_r] = self.channels;
+        let input_l = std::mem::take(&mut chunk.waveforms[chan_l]);
+        let input_r = std::mem::take(&mut chunk.waveforms[chan_r]);
+        for frame in 0..chunk.frames {
+            // Unused input channels are empty, and are treated as silence
+            let value_l = input_l.get(frame).copied().unwrap_or_default();
+            let value_r = input_r.get(frame).copied().unwrap_or_default();
+            self.input_bufs[0][self.hop + self.index] = value_l;
+            self.input_bufs[1][self.hop + self.index] = value_r;
+            for (output, ready) in outputs.iter_mut().zip(self.ready.iter()) {
+                output.push(ready[self.index]);
+            }
+            self.index += 1;
+            if self.index == self.hop {
+                self.process_frame();
+                self.index = 0;
+            }
+        }
+        for (destination, output) in self.destinations.iter().zip(outputs) {
+            if let Some(channel) = destination {
+                chunk.waveforms[*channel] = output;
+            }
+        }
+        Ok(())
+    }
+
+    fn update_parameters(&mut self, conf: config::Processor) {
+        if let config::Processor::Upmixer { parameters } = conf {
+            self.set_parameters(parameters);
+        } else {
+            // This should never happen unless there is a bug somewhere else
+            panic!("Invalid config change!");
+        }
+    }
+}
+
+/// Validate an Upmixer config.
+pub fn validate_config(
+    samplerate: usize,
+    num_channels: usize,
+    conf: &config::UpmixerParameters,
+) -> Res<()> {
+    let mut used_channels = conf.channels.to_vec();
+    if let Some(center) = conf.center {
+        used_channels.push(center);
+    }
+    if let Some(surround) = conf.surround {
+        used_channels.extend_from_slice(&surround);
+    }
+    if let Some(lfe) = conf.lfe {
+        used_channels.push(lfe);
+    }
+    for (n, channel) in used_channels.iter().enumerate() {
+        if *channel >= num_channels {
+            let msg = format!("Invalid channel {}, max is {}", channel, num_channels - 1);
+            return Err(config::ConfigError::new(&msg).into());
+        }
+        if used_channels[..n].contains(channel) {
+            let msg = format!("Channel {} is used for more than one output", channel);
+            return Err(config::ConfigError::new(&msg).into());
+        }
+    }
+    if conf.lfe_freq <= 0.0 {
+        return Err(config::ConfigError::new("LFE frequency must be > 0").into());
+    } else if 2.0 * conf.lfe_freq >= samplerate as PrcFmt / 2.0 {
+        return Err(config::ConfigError::new("LFE frequency must be < samplerate/4").into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audiodevice::AudioChunk;
+    use crate::config;
+    use crate::filters::Processor;
+    use crate::upmixer;
+    use crate::PrcFmt;
+
+    fn make_conf() -> config::UpmixerParameters {
+        config::UpmixerParameters {
+            channels: [0, 1],
+            center: Some(2),
+            surround: Some([3, 4]),
+            lfe: Some(5),
+            lfe_freq: 120.0,
+        }
+    }
+
+    fn rms(values: &[PrcFmt]) -> PrcFmt {
+        (values.iter().map(|v| v * v).sum::<PrcFmt>() / values.len() as PrcFmt).sqrt()
+    }
+
+    // Run a sine through the upmixer and return the rms of each output at the end.
+    fn upmix_sine(freq: PrcFmt, gain_l: PrcFmt, gain_r: PrcFmt) -> Vec<PrcFmt> {
+        let fs = 48000;
+        // Whole number of periods for both 50 Hz and 1 kHz
+        let frames = 960;
+        let mut upmixer = upmixer::Upmixer::from_config("test".to_string(), fs, make_conf());
+        let omega = 2.0 * (std::f64::consts::PI as PrcFmt) * freq / fs as PrcFmt;
+        let mut result = Vec::new();
+        for chunk_nbr in 0..50 {
+            let sine: Vec<PrcFmt> = (0..frames)
+                .map(|n| (omega * (chunk_nbr * frames + n) as PrcFmt).sin())
+                .collect();
+            let left = sine.iter().map(|v| v * gain_l).collect();
+            let right = sine.iter().map(|v| v * gain_r).collect();
+            let mut waveforms = vec![left, right];
+            waveforms.extend(vec![Vec::new(); 4]);
+            let mut chunk = AudioChunk::new(waveforms, 1.0, -1.0, frames, frames);
+            upmixer.process_chunk(&mut chunk).unwrap();
+            result = chunk.waveforms.iter().map(|w| rms(w)).collect();
+        }
+        result
+    }
+
+    #[test]
+    fn upmix_center() {
+        let levels = upmix_sine(1000.0, 1.0, 1.0);
+        let sine_rms = (0.5 as PrcFmt).sqrt();
+        println!("{:?}", levels);
+        assert!((levels[2] - sine_rms).abs() < 0.01);
+        assert!(levels[0] < 0.01);
+        assert!(levels[1] < 0.01);
+        assert!(levels[3] < 0.01);
+        assert!(levels[4] < 0.01);
+        assert!(levels[5] < 0.01);
+    }
+
+    #[test]
+    fn upmix_hard_left() {
+        let levels = upmix_sine(1000.0, 1.0, 0.0);
+        let sine_rms = (0.5 as PrcFmt).sqrt();
+        println!("{:?}", levels);
+        assert!((levels[0] - sine_rms).abs() < 0.01);
+        assert!(levels[1] < 0.01);
+        assert!(levels[2] < 0.01);
+    }
+
+    #[test]
+    fn upmix_lfe() {
+        let levels = upmix_sine(50.0, 1.0, 1.0);
+        let sine_rms = (0.5 as PrcFmt).sqrt();
+        println!("{:?}", levels);
+        assert!((levels[5] - sine_rms).abs() < 0.01);
+    }
+
+    #[test]
+    fn check_upmixer() {
+        let fs = 48000;
+        let okconf = make_conf();
+        assert!(upmixer::validate_config(fs, 6, &okconf).is_ok());
+        assert!(upmixer::validate_config(fs, 4, &okconf).is_err());
+        let mut badconf1 = make_conf();
+        badconf1.lfe = Some(2);
+        assert!(upmixer::validate_config(fs, 6, &badconf1).is_err());
+        let mut badconf2 = make_conf();
+        badconf2.lfe_freq = 0.0;
+        assert!(upmixer::validate_config(fs, 6, &badconf2).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+// Volume control settings.
+// Volume values from remote control clients are limited to the configured range,
+// and can be given as a position on a 0 to 100 scale that is mapped to dB by the selected curve.
+
+use crate::config;
+use crate::ProcessingParameters;
+use crate::Res;
+
+// Lowest and highest volume limits that can be configured
+const LOWEST_VOLUME: f32 = -150.0;
+const HIGHEST_VOLUME: f32 = 50.0;
+
+/// Limit a volume in dB to the configured range.
+pub fn clamp_volume(settings: &config::VolumeSettings, volume: f32) -> f32 {
+    if volume < settings.min_volume || volume > settings.max_volume {
+        warn!(
+            "Volume {} dB is outside the allowed range {} to {} dB, limiting",
+            volume, settings.min_volume, settings.max_volume
+        );
+    }
+    volume.clamp(settings.min_volume, settings.max_volume)
+}
+
+/// Limit the main volume and all faders to the configured range.
+/// Used when a config is applied, since the volumes may have been set
+/// on the command line, restored from the state file or allowed by the previous config.
+pub fn clamp_volumes(settings: &config::VolumeSettings, params: &mut ProcessingParameters) {
+    params.volume = clamp_volume(settings, params.volume);
+    for fader in params.faders.values_mut() {
+        fader.volume = clamp_volume(settings, fader.volume);
+    }
+}
+
+// Relative loudness at a volume in dB, when 10 dB lower is perceived as half as loud.
+fn perceptual_loudness(settings: &config::VolumeSettings, volume: f32) -> f32 {
+    let base: f32 = 2.0;
+    base.powf((volume - settings.max_volume) / 10.0)
+}
+
+/// Convert a position on the 0 to 100 scale to a volume in dB.
+/// The Linear curve spreads the range linearly in dB over the scale.
+/// The Perceptual curve spreads the perceived loudness linearly over the scale,
+/// where 10 dB lower volume is perceived as half as loud.
+/// Position 0 gives the lowest volume and 100 the highest with both curves.
+pub fn percent_to_volume(settings: &config::VolumeSettings, percent: f32) -> f32 {
+    let fraction = (percent / 100.0).clamp(0.0, 1.0);
+    let volume = match settings.curve {
+        config::VolumeCurve::Linear => {
+            settings.min_volume + fraction * (settings.max_volume - settings.min_volume)
+        }
+        config::VolumeCurve::Perceptual => {
+            let lowest = perceptual_loudness(settings, settings.min_volume);
+            let loudness = lowest + fraction * (1.0 - lowest);
+            settings.max_volume + 10.0 * loudness.log2()
+        }
+    };
+    volume.clamp(settings.min_volume, settings.max_volume)
+}
+
+/// Convert a volume in dB to a position on the 0 to 100 scale, the inverse of `percent_to_volume`.
+pub fn volume_to_percent(settings: &config::VolumeSettings, volume: f32) -> f32 {
+    let volume = volume.clamp(settings.min_volume, settings.max_volume);
+    let fraction = match settings.curve {
+        config::VolumeCurve::Linear => {
+            (volume - settings.min_volume) / (settings.max_volume - settings.min_volume)
+        }
+        config::VolumeCurve::Perceptual => {
+            let lowest = perceptual_loudness(settings, settings.min_volume);
+            (perceptual_loudness(settings, volume) - lowest) / (1.0 - lowest)
+        }
+    };
+    100.0 * fraction.clamp(0.0, 1.0)
+}
+
+/// Validate the volume control settings.
+pub fn validate_config(settings: &config::VolumeSettings) -> Res<()> {
+    if settings.min_volume < LOWEST_VOLUME {
+        let msg = format!("min_volume must be at least {} dB", LOWEST_VOLUME);
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    if settings.max_volume > HIGHEST_VOLUME {
+        let msg = format!("max_volume must be at most {} dB", HIGHEST_VOLUME);
+        return Err(config::ConfigError::new(&msg).into());
+    }
+    if settings.min_volume >= settings.max_volume {
+        return Err(config::ConfigError::new("min_volume must be less than max_volume").into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{VolumeCurve, VolumeSettings};
+    use crate::volumecontrol;
+    use crate::{FaderState, ProcessingParameters};
+    use std::collections::HashMap;
+
+    fn is_close(left: f32, right: f32, maxdiff: f32) -> bool {
+        println!("{} - {}", left, right);
+        (left - right).abs() < maxdiff
+    }
+
+    fn make_settings(curve: VolumeCurve) -> VolumeSettings {
+        VolumeSettings {
+            min_volume: -60.0,
+            max_volume: 0.0,
+            curve,
+        }
+    }
+
+    #[test]
+    fn clamp() {
+        let settings = make_settings(VolumeCurve::Linear);
+        assert_eq!(volumecontrol::clamp_volume(&settings, 40.0), 0.0);
+        assert_eq!(volumecontrol::clamp_volume(&settings, -80.0), -60.0);
+        assert_eq!(volumecontrol::clamp_volume(&settings, -12.5), -12.5);
+    }
+
+    #[test]
+    fn clamp_all() {
+        let settings = make_settings(VolumeCurve::Linear);
+        let mut params = ProcessingParameters {
+            volume: 10.0,
+            mute: false,
+            faders: HashMap::new(),
+        };
+        params.faders.insert(
+            "low".to_string(),
+            FaderState {
+                volume: -100.0,
+                mute: false,
+            },
+        );
+        params.faders.insert(
+            "ok".to_string(),
+            FaderState {
+                volume: -20.0,
+                mute: true,
+            },
+        );
+        volumecontrol::clamp_volumes(&settings, &mut params);
+        assert_eq!(params.volume, 0.0);
+        assert_eq!(params.fader(Some("low")).volume, -60.0);
+        assert_eq!(params.fader(Some("ok")).volume, -20.0);
+        assert!(params.fader(Some("ok")).mute);
+    }
+
+    #[test]
+    fn linear_curve() {
+        let settings = make_settings(VolumeCurve::Linear);
+        assert!(is_close(
+            volumecontrol::percent_to_volume(&settings, 0.0),
+            -60.0,
+            1e-4
+        ));
+        assert!(is_close(
+            volumecontrol::percent_to_volume(&settings, 50.0),
+            -30.0,
+            1e-4
+        ));
+        assert!(is_close(
+            volumecontrol::percent_to_volume(&settings, 150.0),
+            0.0,
+            1e-4
+        ));
+        assert!(is_close(
+            volumecontrol::volume_to_percent(&settings, -15.0),
+            75.0,
+            1e-4
+        ));
+    }
+
+    #[test]
+    fn perceptual_curve() {
+        let settings = make_settings(VolumeCurve::Perceptual);
+        assert!(is_close(
+            volumecontrol::percent_to_volume(&settings, 100.0),
+            0.0,
+            1e-4
+        ));
+        // Close to 10 dB per halving, slightly adjusted to reach the lowest volume at 0
+        assert!(is_close(
+            volumecontrol::percent_to_volume(&settings, 50.0),
+            -9.78,
+            0.01
+        ));
+        assert!(is_close(
+            volumecontrol::percent_to_volume(&settings, 25.0),
+            -19.34,
+            0.01
+        ));
+        assert!(is_close(
+            volumecontrol::percent_to_volume(&settings, 0.0),
+            -60.0,
+            1e-4
+        ));
+        assert!(is_close(
+            volumecontrol::volume_to_percent(&settings, -60.0),
+            0.0,
+            1e-4
+        ));
+        assert!(is_close(
+            volumecontrol::volume_to_percent(&settings, 0.0),
+            100.0,
+            1e-4
+        ));
+        for percent in [1.0, 33.0, 75.0] {
+            let volume = volumecontrol::percent_to_volume(&settings, percent);
+            assert!(is_close(
+                volumecontrol::volume_to_percent(&settings, volume),
+                percent,
+                1e-3
+            ));
+        }
+    }
+
+    #[test]
+    fn check_settings() {
+        let settings = make_settings(VolumeCurve::Linear);
+        assert!(volumecontrol::validate_config(&settings).is_ok());
+        let mut badsettings = make_settings(VolumeCurve::Linear);
+        badsettings.max_volume = -70.0;
+        assert!(volumecontrol::validate_config(&badsettings).is_err());
+        let mut badsettings = make_settings(VolumeCurve::Linear);
+        badsettings.max_volume = 60.0;
+        assert!(volumecontrol::validate_config(&badsettings).is_err());
+    }
+}